@@ -2,7 +2,11 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let proto_file = "./proto/mr_cache.proto";
+    let proto_files = [
+        "./proto/mr_cache.proto",
+        "./proto/health.proto",
+        "./proto/reflection.proto",
+    ];
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
     tonic_build::configure()
@@ -11,7 +15,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build_server(true)
         .file_descriptor_set_path(out_dir.join("mr_cache.bin"))
         .out_dir("./src/api")
-        .compile(&[proto_file], &["./proto"])
+        .compile(&proto_files, &["./proto"])
         .expect("Building proto failed");
 
     Ok(())
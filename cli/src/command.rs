@@ -0,0 +1,420 @@
+use mrcache_client::mr_cache::{
+    ContentType, HashedKeyValues, HashedKeys, JsonArrayItems, JsonCondition, JsonDocument,
+    JsonIncrement, JsonPatch, JsonPath, JsonPaths, Key, KeyValue, KeyValues, Keys, Scan, Tags,
+    Value,
+};
+use mrcache_client::{Client, Error};
+
+use crate::output::Output;
+
+/// Usage of every command, also used for completion.
+pub const COMMANDS: &[(&str, &str)] = &[
+    ("get", "get KEY... [--accept TYPE]"),
+    (
+        "set",
+        "set KEY VALUE [KEY VALUE...] [--soft-ttl MS] [--hard-ttl MS] [--tag TAG]... \
+         [--content-type TYPE]",
+    ),
+    (
+        "hset",
+        "hset KEY FIELD VALUE [FIELD VALUE...] [--soft-ttl MS] [--hard-ttl MS] [--tag TAG]...",
+    ),
+    ("hget", "hget KEY FIELD..."),
+    ("hgetall", "hgetall KEY"),
+    ("hkeys", "hkeys KEY"),
+    ("hvals", "hvals KEY"),
+    ("scan", "scan [PATTERN] [--cursor N] [--count N] [--all]"),
+    ("invalidate-tags", "invalidate-tags TAG..."),
+    (
+        "json-set",
+        "json-set KEY PATH JSON [--nx|--xx] [--soft-ttl MS] [--hard-ttl MS] [--tag TAG]...",
+    ),
+    ("json-get", "json-get KEY [PATH...]"),
+    ("json-del", "json-del KEY [PATH]"),
+    ("json-arrappend", "json-arrappend KEY PATH JSON..."),
+    ("json-numincrby", "json-numincrby KEY PATH NUMBER"),
+    ("json-merge", "json-merge KEY PATH JSON"),
+];
+
+pub const OPTIONS: &[&str] = &[
+    "--soft-ttl",
+    "--hard-ttl",
+    "--tag",
+    "--cursor",
+    "--count",
+    "--all",
+    "--content-type",
+    "--accept",
+    "--nx",
+    "--xx",
+];
+
+/// Content types by the names the CLI takes them as.
+const CONTENT_TYPES: &[(&str, ContentType)] = &[
+    ("raw", ContentType::Raw),
+    ("json", ContentType::Json),
+    ("msgpack", ContentType::Msgpack),
+    ("cbor", ContentType::Cbor),
+    ("protobuf", ContentType::Protobuf),
+];
+
+pub enum Command {
+    Get(Keys),
+    Set(KeyValues),
+    HSet(String, KeyValues),
+    HGet(String, Vec<String>),
+    HGetAll(String),
+    HKeys(String),
+    HVals(String),
+    Scan { scan: Scan, all: bool },
+    InvalidateTags(Vec<String>),
+    JsonSet(JsonDocument),
+    JsonGet(JsonPaths),
+    JsonDel(JsonPath),
+    JsonArrAppend(JsonArrayItems),
+    JsonNumIncrBy(JsonIncrement),
+    JsonMerge(JsonPatch),
+}
+
+/// Positional arguments and `--` options of a command line.
+struct Args {
+    positional: Vec<String>,
+    soft_ttl_ms: Option<u64>,
+    hard_ttl_ms: Option<u64>,
+    tags: Vec<String>,
+    cursor: u64,
+    count: u64,
+    all: bool,
+    content_type: ContentType,
+    accept: ContentType,
+    condition: JsonCondition,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> Result<Args, String> {
+        let mut parsed = Args {
+            positional: Vec::new(),
+            soft_ttl_ms: None,
+            hard_ttl_ms: None,
+            tags: Vec::new(),
+            cursor: 0,
+            count: 0,
+            all: false,
+            content_type: ContentType::Unspecified,
+            accept: ContentType::Unspecified,
+            condition: JsonCondition::Always,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = |args: &mut std::slice::Iter<String>| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{arg} needs a value"))
+            };
+            match arg.as_str() {
+                "--soft-ttl" => parsed.soft_ttl_ms = Some(number(arg, &value(&mut args)?)?),
+                "--hard-ttl" => parsed.hard_ttl_ms = Some(number(arg, &value(&mut args)?)?),
+                "--tag" => parsed.tags.push(value(&mut args)?),
+                "--cursor" => parsed.cursor = number(arg, &value(&mut args)?)?,
+                "--count" => parsed.count = number(arg, &value(&mut args)?)?,
+                "--all" => parsed.all = true,
+                "--content-type" => parsed.content_type = content_type(&value(&mut args)?)?,
+                "--accept" => parsed.accept = content_type(&value(&mut args)?)?,
+                "--nx" => parsed.condition = JsonCondition::Missing,
+                "--xx" => parsed.condition = JsonCondition::Exists,
+                "--" => parsed.positional.extend(args.by_ref().cloned()),
+                _ => parsed.positional.push(arg.clone()),
+            }
+        }
+        Ok(parsed)
+    }
+
+    fn key_values(&self, pairs: &[String]) -> KeyValues {
+        KeyValues {
+            key_values: pairs
+                .chunks_exact(2)
+                .map(|pair| KeyValue {
+                    key: pair[0].clone(),
+                    value: pair[1].clone(),
+                    content_type: self.content_type.into(),
+                    ..Default::default()
+                })
+                .collect(),
+            soft_ttl_ms: self.soft_ttl_ms,
+            hard_ttl_ms: self.hard_ttl_ms,
+            tags: self.tags.clone(),
+        }
+    }
+}
+
+/// Whether the arguments are one or more key value pairs.
+fn pairs(args: &[String]) -> bool {
+    !args.is_empty() && args.chunks_exact(2).remainder().is_empty()
+}
+
+fn content_type(name: &str) -> Result<ContentType, String> {
+    CONTENT_TYPES
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, content_type)| *content_type)
+        .ok_or_else(|| {
+            format!("unknown content type {name}, expected raw, json, msgpack, cbor or protobuf")
+        })
+}
+
+/// Name of a content type, empty when unspecified.
+pub fn content_type_name(content_type: ContentType) -> &'static str {
+    CONTENT_TYPES
+        .iter()
+        .find(|(_, known)| *known == content_type)
+        .map_or("", |(name, _)| name)
+}
+
+fn number(option: &str, value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("{option} needs a number, got {value}"))
+}
+
+impl Command {
+    pub fn parse(name: &str, args: &[String]) -> Result<Command, String> {
+        let args = Args::parse(args)?;
+        let positional = &args.positional;
+        let usage = || {
+            let usage = COMMANDS
+                .iter()
+                .find(|(command, _)| *command == name)
+                .map_or("", |(_, usage)| usage);
+            format!("usage: {usage}")
+        };
+
+        let command = match name {
+            "get" if !positional.is_empty() => Command::Get(Keys {
+                accept: args.accept.into(),
+                ..keys_message(positional.clone())
+            }),
+            "set" if pairs(positional) => Command::Set(args.key_values(positional)),
+            "hset" if pairs(positional.get(1..).unwrap_or_default()) => {
+                Command::HSet(positional[0].clone(), args.key_values(&positional[1..]))
+            }
+            "hget" if positional.len() >= 2 => {
+                Command::HGet(positional[0].clone(), positional[1..].to_vec())
+            }
+            "hgetall" if positional.len() == 1 => Command::HGetAll(positional[0].clone()),
+            "hkeys" if positional.len() == 1 => Command::HKeys(positional[0].clone()),
+            "hvals" if positional.len() == 1 => Command::HVals(positional[0].clone()),
+            "scan" if positional.len() <= 1 => Command::Scan {
+                scan: Scan {
+                    pattern: positional.first().cloned().unwrap_or_default(),
+                    cursor: args.cursor,
+                    count: args.count,
+                },
+                all: args.all,
+            },
+            "invalidate-tags" if !positional.is_empty() => {
+                Command::InvalidateTags(positional.clone())
+            }
+            "json-set" if positional.len() == 3 => Command::JsonSet(JsonDocument {
+                key: positional[0].clone(),
+                path: positional[1].clone(),
+                json: positional[2].clone(),
+                condition: args.condition.into(),
+                soft_ttl_ms: args.soft_ttl_ms,
+                hard_ttl_ms: args.hard_ttl_ms,
+                tags: args.tags.clone(),
+            }),
+            "json-get" if !positional.is_empty() => Command::JsonGet(JsonPaths {
+                key: positional[0].clone(),
+                paths: positional[1..].to_vec(),
+            }),
+            "json-del" if (1..=2).contains(&positional.len()) => Command::JsonDel(JsonPath {
+                key: positional[0].clone(),
+                path: positional.get(1).cloned().unwrap_or_default(),
+            }),
+            "json-arrappend" if positional.len() >= 3 => Command::JsonArrAppend(JsonArrayItems {
+                key: positional[0].clone(),
+                path: positional[1].clone(),
+                json: positional[2..].to_vec(),
+            }),
+            "json-numincrby" if positional.len() == 3 => Command::JsonNumIncrBy(JsonIncrement {
+                key: positional[0].clone(),
+                path: positional[1].clone(),
+                by: positional[2].clone(),
+            }),
+            "json-merge" if positional.len() == 3 => Command::JsonMerge(JsonPatch {
+                key: positional[0].clone(),
+                path: positional[1].clone(),
+                json: positional[2].clone(),
+            }),
+            _ if COMMANDS.iter().any(|(command, _)| *command == name) => return Err(usage()),
+            _ => return Err(format!("unknown command {name}, try help")),
+        };
+        Ok(command)
+    }
+
+    pub async fn run(self, client: &Client) -> Result<Output, Error> {
+        let output = match self {
+            Command::Get(keys) => {
+                let values = client
+                    .call(
+                        keys,
+                        |mut stub, request| async move { stub.get(request).await },
+                    )
+                    .await?;
+                Output::Values(values.values)
+            }
+            Command::Set(key_values) => {
+                let effect = client
+                    .call(key_values, |mut stub, request| async move {
+                        stub.set(request).await
+                    })
+                    .await?;
+                Output::Effect(effect.effect)
+            }
+            Command::HSet(key, key_values) => {
+                let hashed = HashedKeyValues {
+                    key: Some(Key { key }),
+                    key_values: Some(key_values),
+                };
+                let effect = client
+                    .call(hashed, |mut stub, request| async move {
+                        stub.hset(request).await
+                    })
+                    .await?;
+                Output::Effect(effect.effect)
+            }
+            Command::HGet(key, fields) => {
+                let hashed = HashedKeys {
+                    key: Some(Key { key }),
+                    keys: Some(keys_message(fields)),
+                };
+                let values = client
+                    .call(hashed, |mut stub, request| async move {
+                        stub.hget(request).await
+                    })
+                    .await?;
+                Output::Values(values.values)
+            }
+            Command::HGetAll(key) => {
+                let values = client
+                    .call(Key { key }, |mut stub, request| async move {
+                        stub.hgetall(request).await
+                    })
+                    .await?;
+                Output::Fields(fields(values.values))
+            }
+            Command::HKeys(key) => {
+                let keys = client
+                    .call(Key { key }, |mut stub, request| async move {
+                        stub.hkeys(request).await
+                    })
+                    .await?;
+                Output::Keys(keys.keys.into_iter().map(|key| key.key).collect())
+            }
+            Command::HVals(key) => {
+                let values = client
+                    .call(Key { key }, |mut stub, request| async move {
+                        stub.hvals(request).await
+                    })
+                    .await?;
+                Output::Values(values.values)
+            }
+            Command::Scan { mut scan, all } => {
+                let mut keys = Vec::new();
+                loop {
+                    let page = client
+                        .call(scan.clone(), |mut stub, request| async move {
+                            stub.scan(request).await
+                        })
+                        .await?;
+                    keys.extend(page.keys.into_iter().flat_map(|keys| keys.keys));
+                    scan.cursor = page.cursor;
+                    if !all || scan.cursor == 0 {
+                        break;
+                    }
+                }
+                let keys = keys.into_iter().map(|key| key.key).collect();
+                if all {
+                    Output::Keys(keys)
+                } else {
+                    Output::Page(scan.cursor, keys)
+                }
+            }
+            Command::InvalidateTags(tags) => {
+                let count = client
+                    .call(Tags { tags }, |mut stub, request| async move {
+                        stub.invalidate_tags(request).await
+                    })
+                    .await?;
+                Output::Count(count.count)
+            }
+            Command::JsonSet(document) => {
+                let effect = client
+                    .call(document, |mut stub, request| async move {
+                        stub.json_set(request).await
+                    })
+                    .await?;
+                Output::Effect(effect.effect)
+            }
+            Command::JsonGet(paths) => {
+                let json = client
+                    .call(paths, |mut stub, request| async move {
+                        stub.json_get(request).await
+                    })
+                    .await?;
+                Output::Json(json.json)
+            }
+            Command::JsonDel(path) => {
+                let count = client
+                    .call(path, |mut stub, request| async move {
+                        stub.json_del(request).await
+                    })
+                    .await?;
+                Output::Count(count.count)
+            }
+            Command::JsonArrAppend(items) => {
+                let json = client
+                    .call_once(items, |mut stub, request| async move {
+                        stub.json_arr_append(request).await
+                    })
+                    .await?;
+                Output::Json(json.json)
+            }
+            Command::JsonNumIncrBy(increment) => {
+                let json = client
+                    .call_once(increment, |mut stub, request| async move {
+                        stub.json_num_incr_by(request).await
+                    })
+                    .await?;
+                Output::Json(json.json)
+            }
+            Command::JsonMerge(patch) => {
+                let effect = client
+                    .call(patch, |mut stub, request| async move {
+                        stub.json_merge(request).await
+                    })
+                    .await?;
+                Output::Effect(effect.effect)
+            }
+        };
+        Ok(output)
+    }
+}
+
+fn keys_message(keys: Vec<String>) -> Keys {
+    Keys {
+        keys: keys.into_iter().map(|key| Key { key }).collect(),
+        ..Default::default()
+    }
+}
+
+/// HGETALL returns fields and values interleaved, as Redis does.
+fn fields(values: Vec<Value>) -> Vec<(String, Value)> {
+    let mut values = values.into_iter();
+    let mut fields = Vec::new();
+    while let (Some(field), Some(value)) = (values.next(), values.next()) {
+        fields.push((field.value, value));
+    }
+    fields
+}
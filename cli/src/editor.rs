@@ -0,0 +1,99 @@
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{CompletionType, Config, Context, Helper};
+use std::io::{self, BufRead, IsTerminal};
+use std::path::PathBuf;
+
+const HISTORY_SIZE: usize = 1000;
+
+/// Returns the candidates for the word being typed, given the line up to the cursor.
+pub type Complete = Box<dyn Fn(&str) -> Vec<String>>;
+
+/// Line editing for the REPL through rustyline: cursor movement, history and tab completion on a
+/// terminal, plain line reads when stdin is piped.
+pub struct Editor {
+    editor: rustyline::Editor<Completion, DefaultHistory>,
+    path: Option<PathBuf>,
+}
+
+/// Completes the word before the cursor with the REPL's candidates, adding a space after the
+/// only one.
+struct Completion(Complete);
+
+impl Completer for Completion {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let before = &line[..pos];
+        let start = before.rfind(' ').map_or(0, |space| space + 1);
+        let mut candidates = (self.0)(before);
+        if let [only] = candidates.as_mut_slice() {
+            only.push(' ');
+        }
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for Completion {
+    type Hint = String;
+}
+
+impl Highlighter for Completion {}
+
+impl Validator for Completion {}
+
+impl Helper for Completion {}
+
+impl Editor {
+    pub fn new(path: Option<PathBuf>, complete: Complete) -> io::Result<Editor> {
+        let config = Config::builder()
+            .max_history_size(HISTORY_SIZE)
+            .and_then(|config| config.history_ignore_dups(true))
+            .map_err(io::Error::other)?
+            .auto_add_history(true)
+            .completion_type(CompletionType::List)
+            .build();
+        let mut editor = rustyline::Editor::with_config(config).map_err(io::Error::other)?;
+        editor.set_helper(Some(Completion(complete)));
+        if let Some(path) = &path {
+            // There is no history yet on the first run.
+            let _ = editor.load_history(path);
+        }
+        Ok(Editor { editor, path })
+    }
+
+    /// Reads a line, or `None` at the end of input. Ctrl-C drops the line being typed.
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        if !io::stdin().is_terminal() {
+            let mut line = String::new();
+            return match io::stdin().lock().read_line(&mut line)? {
+                0 => Ok(None),
+                _ => Ok(Some(line.trim_end_matches(['\r', '\n']).to_string())),
+            };
+        }
+
+        loop {
+            match self.editor.readline(prompt) {
+                Ok(line) => {
+                    if let Some(path) = &self.path {
+                        let _ = self.editor.save_history(path);
+                    }
+                    return Ok(Some(line));
+                }
+                Err(ReadlineError::Interrupted) => continue,
+                Err(ReadlineError::Eof) => return Ok(None),
+                Err(ReadlineError::Io(err)) => return Err(err),
+                Err(err) => return Err(io::Error::other(err)),
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use clap::{CommandFactory, FromArgMatches, Parser};
+use std::io::IsTerminal;
+use std::process::ExitCode;
+
+use crate::profile::Profiles;
+use crate::session::{Overrides, Session};
+
+mod command;
+mod editor;
+mod output;
+mod profile;
+mod repl;
+mod session;
+
+/// Runs COMMAND against mrCache, or starts a REPL when there is none.
+#[derive(Parser)]
+#[command(name = "mrcache-cli")]
+struct Options {
+    /// Profile from the config file, `default` otherwise
+    #[arg(short, long, value_name = "NAME")]
+    profile: Option<String>,
+    /// Server to call, repeat to balance across several
+    #[arg(short, long = "endpoint", value_name = "URI")]
+    endpoints: Vec<String>,
+    /// Authenticate with an API key
+    #[arg(long, value_name = "KEY")]
+    api_key: Option<String>,
+    /// Authenticate with a bearer token
+    #[arg(long, value_name = "JWT")]
+    token: Option<String>,
+    /// Tenant to call as
+    #[arg(long, value_name = "NAME")]
+    tenant: Option<String>,
+    /// Time a command may take, retries included
+    #[arg(long, value_name = "MS")]
+    deadline_ms: Option<u64>,
+    /// Print JSON instead of text
+    #[arg(long)]
+    json: bool,
+    /// Config file, defaults to $MRCACHE_CLI_CONFIG or ~/.config/mrcache/cli.toml
+    #[arg(long, value_name = "PATH")]
+    config: Option<String>,
+    /// Command to run and its arguments
+    #[arg(trailing_var_arg = true)]
+    command: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let matches = Options::command().after_help(repl::help()).get_matches();
+    let options = match Options::from_arg_matches(&matches) {
+        Ok(options) => options,
+        Err(err) => err.exit(),
+    };
+    let overrides = Overrides {
+        endpoints: options.endpoints,
+        api_key: options.api_key,
+        bearer_token: options.token,
+        tenant: options.tenant,
+        deadline_ms: options.deadline_ms,
+    };
+    let session = Profiles::load(options.config.as_deref()).and_then(|profiles| {
+        Session::open(
+            profiles,
+            options.profile.as_deref(),
+            overrides,
+            options.json,
+        )
+    });
+    let session = match session {
+        Ok(session) => session,
+        Err(err) => {
+            eprintln!("error: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let Some((name, args)) = options.command.split_first() else {
+        if std::io::stdin().is_terminal() {
+            println!(
+                "Calling {}, type help for commands.",
+                session.profile.endpoints.join(", ")
+            );
+        }
+        repl::run(session).await;
+        return ExitCode::SUCCESS;
+    };
+    if name == "help" {
+        print!("{}", repl::help());
+        return ExitCode::SUCCESS;
+    }
+
+    match session.execute(name, args).await {
+        Ok(output) => {
+            print!("{output}");
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use mrcache_client::mr_cache::Value;
+use serde_json::{json, Map};
+use std::fmt::Write;
+
+use crate::command::content_type_name;
+
+pub enum Output {
+    Values(Vec<Value>),
+    Fields(Vec<(String, Value)>),
+    Keys(Vec<String>),
+    /// A page of scanned keys with the cursor for the next page.
+    Page(u64, Vec<String>),
+    Effect(bool),
+    Count(u64),
+    /// JSON text from a document command, `None` when the key does not exist.
+    Json(Option<String>),
+}
+
+impl Output {
+    /// Renders the output the way redis-cli does: numbered lists with quoted values, so that
+    /// empty values and whitespace stay visible.
+    pub fn human(&self) -> String {
+        let mut text = String::new();
+        match self {
+            Output::Values(values) if values.is_empty() => text.push_str("(nil)\n"),
+            Output::Values(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    let _ = writeln!(text, "{}) {}{}", i + 1, quoted(value), notes(value));
+                }
+            }
+            Output::Fields(fields) if fields.is_empty() => text.push_str("(empty hash)\n"),
+            Output::Fields(fields) => {
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    let _ = writeln!(
+                        text,
+                        "{}) {field} = {}{}",
+                        i + 1,
+                        quoted(value),
+                        notes(value)
+                    );
+                }
+            }
+            Output::Keys(keys) => text.push_str(&keys_list(keys)),
+            Output::Page(cursor, keys) => {
+                let _ = writeln!(text, "cursor: {cursor}");
+                text.push_str(&keys_list(keys));
+            }
+            Output::Effect(true) => text.push_str("OK\n"),
+            Output::Effect(false) => text.push_str("(no effect)\n"),
+            Output::Count(count) => {
+                let _ = writeln!(text, "(integer) {count}");
+            }
+            Output::Json(None) => text.push_str("(nil)\n"),
+            Output::Json(Some(json)) => {
+                let _ = writeln!(text, "{json}");
+            }
+        }
+        text
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        match self {
+            Output::Values(values) => values.iter().map(value_json).collect(),
+            Output::Fields(fields) => {
+                let fields: Map<String, serde_json::Value> = fields
+                    .iter()
+                    .map(|(field, value)| (field.clone(), value_json(value)))
+                    .collect();
+                fields.into()
+            }
+            Output::Keys(keys) => json!(keys),
+            Output::Page(cursor, keys) => json!({ "cursor": cursor, "keys": keys }),
+            Output::Effect(effect) => json!({ "effect": effect }),
+            Output::Count(count) => json!({ "count": count }),
+            Output::Json(json) => json
+                .as_deref()
+                .and_then(|json| serde_json::from_str(json).ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn keys_list(keys: &[String]) -> String {
+    if keys.is_empty() {
+        return "(empty list)\n".to_string();
+    }
+    let mut text = String::new();
+    for (i, key) in keys.iter().enumerate() {
+        let _ = writeln!(text, "{}) {key}", i + 1);
+    }
+    text
+}
+
+/// Binary values are quoted with their bytes escaped, like redis-cli shows them.
+fn quoted(value: &Value) -> String {
+    if value.data.is_empty() {
+        return format!("{:?}", value.value);
+    }
+    let escaped: String = value
+        .data
+        .iter()
+        .flat_map(|&byte| std::ascii::escape_default(byte))
+        .map(char::from)
+        .collect();
+    format!("\"{escaped}\"")
+}
+
+fn notes(value: &Value) -> String {
+    let mut notes = Vec::new();
+    let content_type = content_type_name(value.content_type());
+    if !content_type.is_empty() {
+        notes.push(content_type.to_string());
+    }
+    if let Some(age) = value.age_ms {
+        notes.push(format!("age {age}ms"));
+    }
+    if value.stale {
+        notes.push("stale".to_string());
+    }
+    match notes.is_empty() {
+        true => String::new(),
+        false => format!("  ({})", notes.join(", ")),
+    }
+}
+
+fn value_json(value: &Value) -> serde_json::Value {
+    let mut json = json!({ "value": value.value, "age_ms": value.age_ms, "stale": value.stale });
+    if !value.data.is_empty() {
+        json["data"] = BASE64.encode(&value.data).into();
+    }
+    let content_type = content_type_name(value.content_type());
+    if !content_type.is_empty() {
+        json["content_type"] = content_type.into();
+    }
+    json
+}
@@ -0,0 +1,123 @@
+use mrcache_client::{Backoff, Certificate, Client, ClientTlsConfig};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::{env, fs};
+
+/// The CLI's config file, a set of named servers and credentials to call them with.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Profiles {
+    /// Profile used when none is picked with `--profile`, `default` when unset.
+    pub default_profile: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct Profile {
+    pub endpoints: Vec<String>,
+    pub api_key: Option<String>,
+    pub bearer_token: Option<String>,
+    pub tenant: Option<String>,
+    /// CA to verify the servers with, which turns on TLS.
+    pub ca_file: Option<String>,
+    /// Name to expect on the servers' certificates when it differs from the endpoint host.
+    pub tls_domain: Option<String>,
+    pub deadline_ms: u64,
+    pub retries: u32,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile {
+            endpoints: vec!["http://127.0.0.1:50051".to_string()],
+            api_key: None,
+            bearer_token: None,
+            tenant: None,
+            ca_file: None,
+            tls_domain: None,
+            deadline_ms: 5000,
+            retries: 3,
+        }
+    }
+}
+
+impl Profiles {
+    /// Reads the config from `path`, `MRCACHE_CLI_CONFIG` or `~/.config/mrcache/cli.toml`.
+    /// Only a path that was asked for explicitly has to exist.
+    pub fn load(path: Option<&str>) -> Result<Profiles, String> {
+        let explicit = path
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("MRCACHE_CLI_CONFIG").map(PathBuf::from));
+        let path = match explicit.clone().or_else(default_path) {
+            Some(path) => path,
+            None => return Ok(Profiles::default()),
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| format!("invalid config {}: {err}", path.display())),
+            Err(_) if explicit.is_none() => Ok(Profiles::default()),
+            Err(err) => Err(format!("cannot read config {}: {err}", path.display())),
+        }
+    }
+
+    /// Looks up a profile, falling back to the defaults for the default profile when the config
+    /// does not define it.
+    pub fn get(&self, name: Option<&str>) -> Result<(String, Profile), String> {
+        let default = self.default_profile.as_deref().unwrap_or("default");
+        let name = name.unwrap_or(default);
+
+        match self.profiles.get(name) {
+            Some(profile) => Ok((name.to_string(), profile.clone())),
+            None if name == default => Ok((name.to_string(), Profile::default())),
+            None => Err(format!("unknown profile {name}")),
+        }
+    }
+}
+
+impl Profile {
+    pub fn client(&self) -> Result<Client, String> {
+        let mut builder = Client::builder()
+            .deadline(Duration::from_millis(self.deadline_ms))
+            .backoff(Backoff {
+                max_retries: self.retries,
+                ..Backoff::default()
+            });
+        for endpoint in &self.endpoints {
+            builder = builder.endpoint(endpoint);
+        }
+        if let Some(key) = &self.api_key {
+            builder = builder.api_key(key);
+        }
+        if let Some(token) = &self.bearer_token {
+            builder = builder.bearer_token(token);
+        }
+        if let Some(tenant) = &self.tenant {
+            builder = builder.tenant(tenant);
+        }
+        if let Some(ca_file) = &self.ca_file {
+            let ca = fs::read(ca_file).map_err(|err| format!("cannot read {ca_file}: {err}"))?;
+            let mut tls = ClientTlsConfig::new().ca_certificate(Certificate::from_pem(ca));
+            if let Some(domain) = &self.tls_domain {
+                tls = tls.domain_name(domain);
+            }
+            builder = builder.tls(tls);
+        }
+
+        builder.build().map_err(|err| err.to_string())
+    }
+}
+
+fn default_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".config/mrcache/cli.toml"))
+}
+
+/// Where the REPL keeps its history between sessions.
+pub fn history_path() -> Option<PathBuf> {
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".mrcache_history"))
+}
@@ -0,0 +1,126 @@
+use crate::command::{COMMANDS, OPTIONS};
+use crate::editor::Editor;
+use crate::profile;
+use crate::session::Session;
+
+/// Commands that only exist in the REPL.
+const REPL_COMMANDS: &[(&str, &str)] = &[
+    ("profile", "profile [NAME]      show or switch the profile"),
+    (
+        "profiles",
+        "profiles            list the configured profiles",
+    ),
+    (
+        "tenant",
+        "tenant [NAME|-]     show, switch or clear the tenant",
+    ),
+    ("json", "json [on|off]       show or switch JSON output"),
+    ("help", "help                show this help"),
+    ("quit", "quit                leave, as does Ctrl-D"),
+];
+
+pub fn help() -> String {
+    let mut text = String::from("Commands:\n");
+    for (_, usage) in COMMANDS {
+        text.push_str(&format!("  {usage}\n"));
+    }
+    text.push_str("\nIn the REPL:\n");
+    for (_, usage) in REPL_COMMANDS {
+        text.push_str(&format!("  {usage}\n"));
+    }
+    text
+}
+
+pub async fn run(mut session: Session) {
+    let profile_names: Vec<String> = session.profiles.profiles.keys().cloned().collect();
+    let completed = profile_names.clone();
+    let complete = Box::new(move |before: &str| complete(before, &completed));
+    let mut editor = match Editor::new(profile::history_path(), complete) {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("error: {err}");
+            return;
+        }
+    };
+
+    loop {
+        let prompt = match &session.profile.tenant {
+            Some(tenant) => format!("mrcache[{}/{tenant}]> ", session.profile_name),
+            None => format!("mrcache[{}]> ", session.profile_name),
+        };
+
+        let line = match editor.read_line(&prompt) {
+            Ok(Some(line)) => line,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("error: {err}");
+                break;
+            }
+        };
+        let words = match shlex::split(&line) {
+            Some(words) => words,
+            None => {
+                eprintln!("error: unbalanced quotes");
+                continue;
+            }
+        };
+        let Some((name, args)) = words.split_first() else {
+            continue;
+        };
+
+        let result = match (name.as_str(), args) {
+            ("quit" | "exit", _) => break,
+            ("help", _) => Ok(help()),
+            ("profile", []) => Ok(format!("{}\n", session.profile_name)),
+            ("profile", [name]) => session.switch(name).map(|_| "OK\n".to_string()),
+            ("profiles", _) => Ok(profile_names
+                .iter()
+                .map(|name| name.clone() + "\n")
+                .collect()),
+            ("tenant", []) => Ok(format!(
+                "{}\n",
+                session.profile.tenant.as_deref().unwrap_or("(none)")
+            )),
+            ("tenant", [tenant]) => {
+                let tenant = (tenant != "-").then(|| tenant.clone());
+                session.set_tenant(tenant).map(|_| "OK\n".to_string())
+            }
+            ("json", []) => Ok(format!("{}\n", if session.json { "on" } else { "off" })),
+            ("json", [mode]) if mode == "on" || mode == "off" => {
+                session.json = mode == "on";
+                Ok("OK\n".to_string())
+            }
+            (name, _) if REPL_COMMANDS.iter().any(|(command, _)| *command == name) => {
+                Err(format!("wrong arguments for {name}, see help"))
+            }
+            (name, args) => session.execute(name, args).await,
+        };
+
+        match result {
+            Ok(output) => print!("{output}"),
+            Err(err) => println!("(error) {err}"),
+        }
+    }
+}
+
+fn complete(before: &str, profiles: &[String]) -> Vec<String> {
+    let words: Vec<&str> = before.split(' ').collect();
+    let word = words.last().copied().unwrap_or_default();
+
+    let candidates: Vec<&str> = match words.as_slice() {
+        [_] => COMMANDS
+            .iter()
+            .chain(REPL_COMMANDS)
+            .map(|(command, _)| *command)
+            .collect(),
+        ["profile", _] => profiles.iter().map(String::as_str).collect(),
+        ["json", _] => vec!["on", "off"],
+        _ if word.starts_with('-') => OPTIONS.to_vec(),
+        _ => Vec::new(),
+    };
+    candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(word))
+        .map(str::to_string)
+        .collect()
+}
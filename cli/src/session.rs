@@ -0,0 +1,99 @@
+use mrcache_client::Client;
+
+use crate::command::Command;
+use crate::profile::{Profile, Profiles};
+
+/// Connection settings given on the command line, applied over the chosen profile.
+#[derive(Default)]
+pub struct Overrides {
+    pub endpoints: Vec<String>,
+    pub api_key: Option<String>,
+    pub bearer_token: Option<String>,
+    pub tenant: Option<String>,
+    pub deadline_ms: Option<u64>,
+}
+
+impl Overrides {
+    fn apply(&self, profile: &mut Profile) {
+        if !self.endpoints.is_empty() {
+            profile.endpoints = self.endpoints.clone();
+        }
+        if let Some(key) = &self.api_key {
+            profile.api_key = Some(key.clone());
+        }
+        if let Some(token) = &self.bearer_token {
+            profile.bearer_token = Some(token.clone());
+        }
+        if let Some(tenant) = &self.tenant {
+            profile.tenant = Some(tenant.clone());
+        }
+        if let Some(deadline_ms) = self.deadline_ms {
+            profile.deadline_ms = deadline_ms;
+        }
+    }
+}
+
+/// The profile in use and a client for it.
+pub struct Session {
+    pub profiles: Profiles,
+    overrides: Overrides,
+    pub profile_name: String,
+    pub profile: Profile,
+    client: Client,
+    pub json: bool,
+}
+
+impl Session {
+    pub fn open(
+        profiles: Profiles,
+        name: Option<&str>,
+        overrides: Overrides,
+        json: bool,
+    ) -> Result<Session, String> {
+        let (profile_name, mut profile) = profiles.get(name)?;
+        overrides.apply(&mut profile);
+        let client = profile.client()?;
+
+        Ok(Session {
+            profiles,
+            overrides,
+            profile_name,
+            profile,
+            client,
+            json,
+        })
+    }
+
+    /// Moves to another profile. The command line overrides still apply.
+    pub fn switch(&mut self, name: &str) -> Result<(), String> {
+        let (profile_name, mut profile) = self.profiles.get(Some(name))?;
+        self.overrides.apply(&mut profile);
+        self.client = profile.client()?;
+        self.profile_name = profile_name;
+        self.profile = profile;
+        Ok(())
+    }
+
+    pub fn set_tenant(&mut self, tenant: Option<String>) -> Result<(), String> {
+        let mut profile = self.profile.clone();
+        profile.tenant = tenant.clone();
+        self.client = profile.client()?;
+        self.profile = profile;
+        self.overrides.tenant = tenant;
+        Ok(())
+    }
+
+    /// Runs one command and renders its output.
+    pub async fn execute(&self, name: &str, args: &[String]) -> Result<String, String> {
+        let output = Command::parse(name, args)?
+            .run(&self.client)
+            .await
+            .map_err(|err| err.to_string())?;
+
+        if self.json {
+            Ok(output.json().to_string() + "\n")
+        } else {
+            Ok(output.human())
+        }
+    }
+}
@@ -0,0 +1,11 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tonic_build::configure()
+        .protoc_arg("--experimental_allow_proto3_optional")
+        .build_client(true)
+        .build_server(false)
+        .out_dir("./src")
+        .compile(&["../proto/mr_cache.proto"], &["../proto"])
+        .expect("Building proto failed");
+
+    Ok(())
+}
@@ -0,0 +1,459 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::{Request, Response, Status};
+
+use crate::error::Error;
+use crate::mr_cache::mr_cache_client::MrCacheClient;
+use crate::mr_cache::{
+    ContentType, HashedKeyValues, HashedKeys, Key, KeyValue, KeyValues, Keys, Scan, Tags, Value,
+};
+use crate::retry::Backoff;
+
+const API_KEY_HEADER: &str = "x-api-key";
+const AUTHORIZATION_HEADER: &str = "authorization";
+const TENANT_HEADER: &str = "x-mrcache-tenant";
+const CONSISTENCY_HEADER: &str = "x-mrcache-consistency";
+
+/// TTLs and tags for [`Client::set`] and [`Client::hset`].
+#[derive(Clone, Debug, Default)]
+pub struct SetOptions {
+    /// After the soft TTL values are still served but flagged stale, and string keys are
+    /// refreshed in the background.
+    pub soft_ttl: Option<Duration>,
+    /// After the hard TTL values are removed.
+    pub hard_ttl: Option<Duration>,
+    /// Tags to index the keys under for [`Client::invalidate_tags`].
+    pub tags: Vec<String>,
+}
+
+/// A value read back with what mrCache knows about its freshness.
+#[derive(Clone, Debug)]
+pub struct Cached<T> {
+    pub value: T,
+    /// Time since the value was last set, when mrCache knows it.
+    pub age: Option<Duration>,
+    /// The value is past its soft TTL. For string keys a refresh from the loader has been
+    /// requested.
+    pub stale: bool,
+}
+
+pub struct ClientBuilder {
+    endpoints: Vec<String>,
+    api_key: Option<String>,
+    bearer_token: Option<String>,
+    tenant: Option<String>,
+    strong_reads: bool,
+    tls: Option<ClientTlsConfig>,
+    connect_timeout: Duration,
+    deadline: Duration,
+    backoff: Backoff,
+}
+
+impl ClientBuilder {
+    /// Adds an mrCache endpoint such as `http://cache-1:50051`. Calls are balanced across all of
+    /// them.
+    pub fn endpoint(mut self, uri: impl Into<String>) -> Self {
+        self.endpoints.push(uri.into());
+        self
+    }
+
+    pub fn api_key(mut self, key: impl Into<String>) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Authenticates with a JWT, sent as `authorization: Bearer <token>`.
+    pub fn bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    pub fn tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Reads from the Redis primary rather than its replicas, so that every read sees the
+    /// writes made before it.
+    pub fn strong_reads(mut self) -> Self {
+        self.strong_reads = true;
+        self
+    }
+
+    /// Connects over TLS, which the endpoints then need `https://` URIs for.
+    pub fn tls(mut self, config: ClientTlsConfig) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Time a call may take, retries included. It is sent to mrCache as the gRPC deadline.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets up the client. Connections are opened lazily and shared by every clone of the
+    /// client, so build it once and clone it around. Must be called within a Tokio runtime.
+    pub fn build(self) -> Result<Client, Error> {
+        let mut endpoints = Vec::with_capacity(self.endpoints.len());
+        for uri in self.endpoints {
+            let mut endpoint = Endpoint::from_shared(uri)?
+                .connect_timeout(self.connect_timeout)
+                .tcp_keepalive(Some(Duration::from_secs(60)))
+                .http2_keep_alive_interval(Duration::from_secs(30))
+                .keep_alive_while_idle(true);
+            if let Some(tls) = &self.tls {
+                endpoint = endpoint.tls_config(tls.clone())?;
+            }
+            endpoints.push(endpoint);
+        }
+        if endpoints.is_empty() {
+            endpoints.push(Endpoint::from_static("http://127.0.0.1:50051"));
+        }
+
+        let mut metadata = MetadataMap::new();
+        if let Some(key) = self.api_key {
+            let value = MetadataValue::try_from(key.as_str())
+                .map_err(|_| Error::InvalidMetadata(API_KEY_HEADER))?;
+            metadata.insert(API_KEY_HEADER, value);
+        }
+        if let Some(token) = self.bearer_token {
+            let value = MetadataValue::try_from(format!("Bearer {token}"))
+                .map_err(|_| Error::InvalidMetadata(AUTHORIZATION_HEADER))?;
+            metadata.insert(AUTHORIZATION_HEADER, value);
+        }
+        if let Some(tenant) = self.tenant {
+            let value = MetadataValue::try_from(tenant.as_str())
+                .map_err(|_| Error::InvalidMetadata(TENANT_HEADER))?;
+            metadata.insert(TENANT_HEADER, value);
+        }
+        if self.strong_reads {
+            metadata.insert(CONSISTENCY_HEADER, MetadataValue::from_static("strong"));
+        }
+
+        Ok(Client {
+            stub: MrCacheClient::new(Channel::balance_list(endpoints.into_iter())),
+            metadata,
+            deadline: self.deadline,
+            backoff: self.backoff,
+        })
+    }
+}
+
+/// Typed mrCache client. Values are stored as JSON, so anything written through it can be read
+/// back by any other client as a JSON string. Values other clients stored as MessagePack or CBOR
+/// are read back through mrCache's transcoding to JSON.
+#[derive(Clone)]
+pub struct Client {
+    stub: MrCacheClient<Channel>,
+    metadata: MetadataMap,
+    deadline: Duration,
+    backoff: Backoff,
+}
+
+impl Client {
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder {
+            endpoints: Vec::new(),
+            api_key: None,
+            bearer_token: None,
+            tenant: None,
+            strong_reads: false,
+            tls: None,
+            connect_timeout: Duration::from_secs(5),
+            deadline: Duration::from_secs(5),
+            backoff: Backoff::default(),
+        }
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, Error> {
+        Ok(self.get_cached(key).await?.map(|cached| cached.value))
+    }
+
+    /// Like [`Client::get`], with the value's age and staleness.
+    pub async fn get_cached<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<Option<Cached<T>>, Error> {
+        let keys = Keys {
+            keys: vec![Key { key: key.into() }],
+            accept: ContentType::Json.into(),
+        };
+        let values = self
+            .call(
+                keys,
+                |mut stub, request| async move { stub.get(request).await },
+            )
+            .await?;
+
+        values.values.into_iter().next().map(decode).transpose()
+    }
+
+    /// Returns whether the value was written.
+    pub async fn set<T: Serialize + ?Sized>(
+        &self,
+        key: &str,
+        value: &T,
+        options: &SetOptions,
+    ) -> Result<bool, Error> {
+        self.set_many([(key, value)], options).await
+    }
+
+    /// Writes several keys in one call, all with the same TTLs and tags.
+    pub async fn set_many<'a, K, T, I>(&self, items: I, options: &SetOptions) -> Result<bool, Error>
+    where
+        K: AsRef<str>,
+        T: Serialize + ?Sized + 'a,
+        I: IntoIterator<Item = (K, &'a T)>,
+    {
+        let key_values = key_values(items, options)?;
+        let effect = self
+            .call(key_values, |mut stub, request| async move {
+                stub.set(request).await
+            })
+            .await?;
+
+        Ok(effect.effect)
+    }
+
+    /// Writes the fields of a hash. TTLs and tags apply to the whole hash.
+    pub async fn hset<'a, F, T, I>(
+        &self,
+        key: &str,
+        fields: I,
+        options: &SetOptions,
+    ) -> Result<bool, Error>
+    where
+        F: AsRef<str>,
+        T: Serialize + ?Sized + 'a,
+        I: IntoIterator<Item = (F, &'a T)>,
+    {
+        let hashed = HashedKeyValues {
+            key: Some(Key { key: key.into() }),
+            key_values: Some(key_values(fields, options)?),
+        };
+        let effect = self
+            .call(hashed, |mut stub, request| async move {
+                stub.hset(request).await
+            })
+            .await?;
+
+        Ok(effect.effect)
+    }
+
+    pub async fn hget<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        field: &str,
+    ) -> Result<Option<T>, Error> {
+        let hashed = HashedKeys {
+            key: Some(Key { key: key.into() }),
+            keys: Some(Keys {
+                keys: vec![Key { key: field.into() }],
+                ..Default::default()
+            }),
+        };
+        let values = self
+            .call(hashed, |mut stub, request| async move {
+                stub.hget(request).await
+            })
+            .await?;
+
+        values
+            .values
+            .into_iter()
+            .next()
+            .map(|value| Ok(serde_json::from_str(&value.value)?))
+            .transpose()
+    }
+
+    pub async fn hgetall<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> Result<HashMap<String, T>, Error> {
+        let values = self
+            .call(Key { key: key.into() }, |mut stub, request| async move {
+                stub.hgetall(request).await
+            })
+            .await?;
+
+        // Fields and values come back interleaved, as Redis returns them.
+        let mut values = values.values.into_iter();
+        let mut fields = HashMap::new();
+        while let (Some(field), Some(value)) = (values.next(), values.next()) {
+            fields.insert(field.value, serde_json::from_str(&value.value)?);
+        }
+        Ok(fields)
+    }
+
+    pub async fn hkeys(&self, key: &str) -> Result<Vec<String>, Error> {
+        let keys = self
+            .call(Key { key: key.into() }, |mut stub, request| async move {
+                stub.hkeys(request).await
+            })
+            .await?;
+
+        Ok(keys.keys.into_iter().map(|key| key.key).collect())
+    }
+
+    pub async fn hvals<T: DeserializeOwned>(&self, key: &str) -> Result<Vec<T>, Error> {
+        let values = self
+            .call(Key { key: key.into() }, |mut stub, request| async move {
+                stub.hvals(request).await
+            })
+            .await?;
+
+        values
+            .values
+            .iter()
+            .map(|value| Ok(serde_json::from_str(&value.value)?))
+            .collect()
+    }
+
+    /// Returns one page of keys matching a glob-style pattern and the cursor for the next page,
+    /// which is 0 once the scan is complete.
+    pub async fn scan(
+        &self,
+        pattern: &str,
+        cursor: u64,
+        count: u64,
+    ) -> Result<(u64, Vec<String>), Error> {
+        let scan = Scan {
+            pattern: pattern.into(),
+            cursor,
+            count,
+        };
+        let page = self
+            .call(
+                scan,
+                |mut stub, request| async move { stub.scan(request).await },
+            )
+            .await?;
+
+        let keys = page.keys.map(|keys| keys.keys).unwrap_or_default();
+        Ok((page.cursor, keys.into_iter().map(|key| key.key).collect()))
+    }
+
+    /// Removes every key indexed under the tags and returns how many were removed.
+    pub async fn invalidate_tags<S: AsRef<str>>(&self, tags: &[S]) -> Result<u64, Error> {
+        let tags = Tags {
+            tags: tags.iter().map(|tag| tag.as_ref().to_string()).collect(),
+        };
+        let count = self
+            .call(tags, |mut stub, request| async move {
+                stub.invalidate_tags(request).await
+            })
+            .await?;
+
+        Ok(count.count)
+    }
+
+    /// Sends one RPC with the client's credentials, retrying with backoff until it succeeds, fails
+    /// with an error that is not worth retrying or runs out of time. This is how to make calls the
+    /// typed methods do not cover, such as reading values that were not written as JSON:
+    /// `client.call(keys, |mut stub, request| async move { stub.get(request).await })`. Only for
+    /// RPCs that are safe to repeat; use [`Client::call_once`] for the others.
+    pub async fn call<M, R, F, Fut>(&self, message: M, rpc: F) -> Result<R, Error>
+    where
+        M: Clone,
+        F: Fn(MrCacheClient<Channel>, Request<M>) -> Fut,
+        Fut: Future<Output = Result<Response<R>, Status>>,
+    {
+        let deadline = Instant::now() + self.deadline;
+        let mut attempt = 0;
+
+        loop {
+            let status = match self.send(message.clone(), deadline, &rpc).await {
+                Ok(response) => return Ok(response),
+                Err(status) => status,
+            };
+
+            let delay = self.backoff.delay(attempt);
+            if !self.backoff.retries(status.code())
+                || attempt >= self.backoff.max_retries
+                || Instant::now() + delay >= deadline
+            {
+                return Err(status.into());
+            }
+            attempt += 1;
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Sends one RPC with the client's credentials exactly once. For calls that are not safe to
+    /// repeat, such as `JsonArrAppend` and `JsonNumIncrBy`: an `Unavailable` error does not prove
+    /// the first attempt was never applied.
+    pub async fn call_once<M, R, F, Fut>(&self, message: M, rpc: F) -> Result<R, Error>
+    where
+        F: Fn(MrCacheClient<Channel>, Request<M>) -> Fut,
+        Fut: Future<Output = Result<Response<R>, Status>>,
+    {
+        let deadline = Instant::now() + self.deadline;
+        Ok(self.send(message, deadline, &rpc).await?)
+    }
+
+    async fn send<M, R, F, Fut>(&self, message: M, deadline: Instant, rpc: &F) -> Result<R, Status>
+    where
+        F: Fn(MrCacheClient<Channel>, Request<M>) -> Fut,
+        Fut: Future<Output = Result<Response<R>, Status>>,
+    {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let mut request = Request::new(message);
+        *request.metadata_mut() = self.metadata.clone();
+        request.set_timeout(remaining);
+
+        match tokio::time::timeout(remaining, rpc(self.stub.clone(), request)).await {
+            Ok(result) => result.map(Response::into_inner),
+            Err(_) => Err(Status::deadline_exceeded("mrCache call timed out")),
+        }
+    }
+}
+
+fn key_values<'a, K, T, I>(items: I, options: &SetOptions) -> Result<KeyValues, Error>
+where
+    K: AsRef<str>,
+    T: Serialize + ?Sized + 'a,
+    I: IntoIterator<Item = (K, &'a T)>,
+{
+    let key_values = items
+        .into_iter()
+        .map(|(key, value)| {
+            Ok(KeyValue {
+                key: key.as_ref().to_string(),
+                value: serde_json::to_string(value)?,
+                content_type: ContentType::Json.into(),
+                ..Default::default()
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    Ok(KeyValues {
+        key_values,
+        soft_ttl_ms: options.soft_ttl.map(|ttl| ttl.as_millis() as u64),
+        hard_ttl_ms: options.hard_ttl.map(|ttl| ttl.as_millis() as u64),
+        tags: options.tags.clone(),
+    })
+}
+
+fn decode<T: DeserializeOwned>(value: Value) -> Result<Cached<T>, Error> {
+    Ok(Cached {
+        value: serde_json::from_str(&value.value)?,
+        age: value.age_ms.map(Duration::from_millis),
+        stale: value.stale,
+    })
+}
@@ -0,0 +1,58 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum Error {
+    /// An endpoint could not be set up, e.g. a malformed URI or TLS config.
+    Transport(tonic::transport::Error),
+    /// mrCache, or the connection to it, failed the call.
+    Status(tonic::Status),
+    /// A value could not be encoded to or decoded from JSON.
+    Json(serde_json::Error),
+    /// A credential or tenant can not be sent as a header.
+    InvalidMetadata(&'static str),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(err) => write!(f, "transport error: {err}"),
+            Error::Status(status) => write!(
+                f,
+                "mrCache returned {:?}: {}",
+                status.code(),
+                status.message()
+            ),
+            Error::Json(err) => write!(f, "invalid JSON value: {err}"),
+            Error::InvalidMetadata(header) => write!(f, "invalid {header} header value"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(err) => Some(err),
+            Error::Status(status) => Some(status),
+            Error::Json(err) => Some(err),
+            Error::InvalidMetadata(_) => None,
+        }
+    }
+}
+
+impl From<tonic::transport::Error> for Error {
+    fn from(err: tonic::transport::Error) -> Self {
+        Error::Transport(err)
+    }
+}
+
+impl From<tonic::Status> for Error {
+    fn from(status: tonic::Status) -> Self {
+        Error::Status(status)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Json(err)
+    }
+}
@@ -0,0 +1,18 @@
+//! Rust client for mrCache.
+//!
+//! [`Client`] wraps the generated gRPC stubs with serde-typed reads and writes, one shared
+//! connection per process, retries with backoff, per-call deadlines and load-balancing across
+//! several mrCache endpoints. The raw stubs are in [`mr_cache`] for anything the wrapper does not
+//! cover.
+
+#![allow(clippy::result_large_err)]
+
+mod client;
+mod error;
+pub mod mr_cache;
+mod retry;
+
+pub use client::{Cached, Client, ClientBuilder, SetOptions};
+pub use error::Error;
+pub use retry::Backoff;
+pub use tonic::transport::{Certificate, ClientTlsConfig, Identity};
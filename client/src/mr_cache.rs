@@ -0,0 +1,665 @@
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Key {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Keys {
+    #[prost(message, repeated, tag = "1")]
+    pub keys: ::prost::alloc::vec::Vec<Key>,
+    /// Encoding to return values in. JSON, MessagePack and CBOR values are transcoded to it, others
+    /// come back as stored. Values come back as stored when unset.
+    #[prost(enumeration = "ContentType", tag = "2")]
+    pub accept: i32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Value {
+    /// The value, when it is valid UTF-8.
+    #[prost(string, tag = "1")]
+    pub value: ::prost::alloc::string::String,
+    /// Milliseconds since the value was last set, when mrCache knows it.
+    #[prost(uint64, optional, tag = "2")]
+    pub age_ms: ::core::option::Option<u64>,
+    /// The value is past its soft TTL. For string keys a refresh from the loader has been requested.
+    #[prost(bool, tag = "3")]
+    pub stale: bool,
+    /// The value, when it is not valid UTF-8.
+    #[prost(bytes = "vec", tag = "4")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+    /// How the value is encoded, unspecified for values set without a content type.
+    #[prost(enumeration = "ContentType", tag = "5")]
+    pub content_type: i32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Values {
+    #[prost(message, repeated, tag = "1")]
+    pub values: ::prost::alloc::vec::Vec<Value>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub value: ::prost::alloc::string::String,
+    /// Binary value, used instead of value when not empty.
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+    #[prost(enumeration = "ContentType", tag = "4")]
+    pub content_type: i32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct KeyValues {
+    #[prost(message, repeated, tag = "1")]
+    pub key_values: ::prost::alloc::vec::Vec<KeyValue>,
+    /// After the soft TTL values are still served but flagged stale, and string keys are refreshed in
+    /// the background.
+    #[prost(uint64, optional, tag = "2")]
+    pub soft_ttl_ms: ::core::option::Option<u64>,
+    /// After the hard TTL values are removed.
+    #[prost(uint64, optional, tag = "3")]
+    pub hard_ttl_ms: ::core::option::Option<u64>,
+    /// Tags to index the keys under for InvalidateTags, replacing any tags they had before.
+    #[prost(string, repeated, tag = "4")]
+    pub tags: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HashedKeyValues {
+    #[prost(message, optional, tag = "1")]
+    pub key: ::core::option::Option<Key>,
+    #[prost(message, optional, tag = "2")]
+    pub key_values: ::core::option::Option<KeyValues>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HashedKeys {
+    #[prost(message, optional, tag = "1")]
+    pub key: ::core::option::Option<Key>,
+    #[prost(message, optional, tag = "2")]
+    pub keys: ::core::option::Option<Keys>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Effect {
+    #[prost(bool, tag = "1")]
+    pub effect: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Scan {
+    /// Glob-style pattern, matching every key when empty.
+    #[prost(string, tag = "1")]
+    pub pattern: ::prost::alloc::string::String,
+    /// Cursor from the previous page, 0 to start.
+    #[prost(uint64, tag = "2")]
+    pub cursor: u64,
+    /// Hint for how many keys to look at, defaults to 10.
+    #[prost(uint64, tag = "3")]
+    pub count: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScanPage {
+    /// Cursor for the next page, 0 once the scan is complete.
+    #[prost(uint64, tag = "1")]
+    pub cursor: u64,
+    #[prost(message, optional, tag = "2")]
+    pub keys: ::core::option::Option<Keys>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Tags {
+    #[prost(string, repeated, tag = "1")]
+    pub tags: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Count {
+    #[prost(uint64, tag = "1")]
+    pub count: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonPath {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonPaths {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    /// No paths returns the whole document, one path a JSON array of what it matched, and several
+    /// an object of those arrays keyed by path.
+    #[prost(string, repeated, tag = "2")]
+    pub paths: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonDocument {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    /// New documents must be set at the root.
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub json: ::prost::alloc::string::String,
+    #[prost(enumeration = "JsonCondition", tag = "4")]
+    pub condition: i32,
+    /// TTLs and tags only apply when setting the root, deeper sets keep the document's.
+    #[prost(uint64, optional, tag = "5")]
+    pub soft_ttl_ms: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "6")]
+    pub hard_ttl_ms: ::core::option::Option<u64>,
+    #[prost(string, repeated, tag = "7")]
+    pub tags: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonArrayItems {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    /// JSON values appended to every array the path matches.
+    #[prost(string, repeated, tag = "3")]
+    pub json: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonIncrement {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    /// JSON number added to every number the path matches.
+    #[prost(string, tag = "3")]
+    pub by: ::prost::alloc::string::String,
+}
+/// RFC 7386 merge patch applied at the path. Nulls in the patch delete members.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonPatch {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub json: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Json {
+    /// JSON text, unset when the key does not exist.
+    #[prost(string, optional, tag = "1")]
+    pub json: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// How a value is encoded. Structured values are validated when they are set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ContentType {
+    Unspecified = 0,
+    Raw = 1,
+    Json = 2,
+    Msgpack = 3,
+    Cbor = 4,
+    /// Checked to be well-formed, but without a schema never transcoded.
+    Protobuf = 5,
+}
+impl ContentType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ContentType::Unspecified => "CONTENT_TYPE_UNSPECIFIED",
+            ContentType::Raw => "CONTENT_TYPE_RAW",
+            ContentType::Json => "CONTENT_TYPE_JSON",
+            ContentType::Msgpack => "CONTENT_TYPE_MSGPACK",
+            ContentType::Cbor => "CONTENT_TYPE_CBOR",
+            ContentType::Protobuf => "CONTENT_TYPE_PROTOBUF",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CONTENT_TYPE_UNSPECIFIED" => Some(Self::Unspecified),
+            "CONTENT_TYPE_RAW" => Some(Self::Raw),
+            "CONTENT_TYPE_JSON" => Some(Self::Json),
+            "CONTENT_TYPE_MSGPACK" => Some(Self::Msgpack),
+            "CONTENT_TYPE_CBOR" => Some(Self::Cbor),
+            "CONTENT_TYPE_PROTOBUF" => Some(Self::Protobuf),
+            _ => None,
+        }
+    }
+}
+/// Whether a JsonSet depends on the path already holding a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum JsonCondition {
+    Always = 0,
+    /// Only set when the path does not exist yet, like NX.
+    Missing = 1,
+    /// Only set when the path exists, like XX.
+    Exists = 2,
+}
+impl JsonCondition {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            JsonCondition::Always => "JSON_CONDITION_ALWAYS",
+            JsonCondition::Missing => "JSON_CONDITION_MISSING",
+            JsonCondition::Exists => "JSON_CONDITION_EXISTS",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "JSON_CONDITION_ALWAYS" => Some(Self::Always),
+            "JSON_CONDITION_MISSING" => Some(Self::Missing),
+            "JSON_CONDITION_EXISTS" => Some(Self::Exists),
+            _ => None,
+        }
+    }
+}
+/// Generated client implementations.
+pub mod mr_cache_client {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    #[derive(Debug, Clone)]
+    pub struct MrCacheClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl MrCacheClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> MrCacheClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> MrCacheClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+            >>::Error: Into<StdError> + Send + Sync,
+        {
+            MrCacheClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        /// Keys
+        pub async fn scan(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Scan>,
+        ) -> std::result::Result<tonic::Response<super::ScanPage>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/SCAN");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "SCAN"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Strings
+        pub async fn set(
+            &mut self,
+            request: impl tonic::IntoRequest<super::KeyValues>,
+        ) -> std::result::Result<tonic::Response<super::Effect>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/SET");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "SET"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Keys>,
+        ) -> std::result::Result<tonic::Response<super::Values>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/GET");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "GET"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Hashes (Should I rename the messages instead of reusing the key/value ones?)
+        pub async fn hset(
+            &mut self,
+            request: impl tonic::IntoRequest<super::HashedKeyValues>,
+        ) -> std::result::Result<tonic::Response<super::Effect>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/HSET");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "HSET"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn hget(
+            &mut self,
+            request: impl tonic::IntoRequest<super::HashedKeys>,
+        ) -> std::result::Result<tonic::Response<super::Values>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/HGET");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "HGET"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn hgetall(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Key>,
+        ) -> std::result::Result<tonic::Response<super::Values>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/HGETALL");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "HGETALL"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn hkeys(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Key>,
+        ) -> std::result::Result<tonic::Response<super::Keys>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/HKEYS");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "HKEYS"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn hvals(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Key>,
+        ) -> std::result::Result<tonic::Response<super::Values>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/HVALS");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "HVALS"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Tags
+        pub async fn invalidate_tags(
+            &mut self,
+            request: impl tonic::IntoRequest<super::Tags>,
+        ) -> std::result::Result<tonic::Response<super::Count>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/mr_cache.MrCache/InvalidateTags",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("mr_cache.MrCache", "InvalidateTags"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// JSON documents, kept by RedisJSON when the server has it and as JSON strings otherwise
+        pub async fn json_set(
+            &mut self,
+            request: impl tonic::IntoRequest<super::JsonDocument>,
+        ) -> std::result::Result<tonic::Response<super::Effect>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/JsonSet");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "JsonSet"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn json_get(
+            &mut self,
+            request: impl tonic::IntoRequest<super::JsonPaths>,
+        ) -> std::result::Result<tonic::Response<super::Json>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/JsonGet");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "JsonGet"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn json_del(
+            &mut self,
+            request: impl tonic::IntoRequest<super::JsonPath>,
+        ) -> std::result::Result<tonic::Response<super::Count>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/mr_cache.MrCache/JsonDel");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("mr_cache.MrCache", "JsonDel"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn json_arr_append(
+            &mut self,
+            request: impl tonic::IntoRequest<super::JsonArrayItems>,
+        ) -> std::result::Result<tonic::Response<super::Json>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/mr_cache.MrCache/JsonArrAppend",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("mr_cache.MrCache", "JsonArrAppend"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn json_num_incr_by(
+            &mut self,
+            request: impl tonic::IntoRequest<super::JsonIncrement>,
+        ) -> std::result::Result<tonic::Response<super::Json>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/mr_cache.MrCache/JsonNumIncrBy",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("mr_cache.MrCache", "JsonNumIncrBy"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn json_merge(
+            &mut self,
+            request: impl tonic::IntoRequest<super::JsonPatch>,
+        ) -> std::result::Result<tonic::Response<super::Effect>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/mr_cache.MrCache/JsonMerge",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("mr_cache.MrCache", "JsonMerge"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
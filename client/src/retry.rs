@@ -0,0 +1,51 @@
+use rand::Rng;
+use std::time::Duration;
+use tonic::Code;
+
+/// Exponential backoff between attempts of a call.
+///
+/// Only `Unavailable` errors are retried, as they usually mean the call never reached a healthy
+/// mrCache instance. They do not prove it, so `Client::call` must only be given RPCs that are
+/// safe to repeat; `JsonArrAppend` and `JsonNumIncrBy` are not and go through
+/// `Client::call_once`. The balancer picks the endpoint for each attempt, so retries move away
+/// from an instance that is down.
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    /// Attempts after the first one, 0 to never retry.
+    pub max_retries: u32,
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            max_retries: 3,
+            initial: Duration::from_millis(50),
+            max: Duration::from_secs(2),
+            multiplier: 2.0,
+        }
+    }
+}
+
+impl Backoff {
+    pub fn none() -> Self {
+        Backoff {
+            max_retries: 0,
+            ..Backoff::default()
+        }
+    }
+
+    pub(crate) fn retries(&self, code: Code) -> bool {
+        code == Code::Unavailable
+    }
+
+    /// Delay before retry number `attempt` (from 0), jittered between half and all of the
+    /// exponential step so that clients failing together do not retry together.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let step = self.initial.as_secs_f64() * self.multiplier.powi(attempt as i32);
+        let step = step.min(self.max.as_secs_f64());
+        Duration::from_secs_f64(step * rand::thread_rng().gen_range(0.5..=1.0))
+    }
+}
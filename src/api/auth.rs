@@ -0,0 +1,634 @@
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::sync::Arc;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::Certificate;
+use tonic::{Request, Status};
+
+use x509_parser::certificate::X509Certificate;
+use x509_parser::prelude::FromDer;
+
+use crate::api::tenant::{DEFAULT_TENANT, TENANT_HEADER};
+use crate::config::AuthConfig;
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Grants every tenant, the shared keyspace included.
+const ANY_TENANT: &str = "*";
+
+/// What a caller may do, each role allowing everything the ones before it do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    ReadOnly,
+    ReadWrite,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = Status;
+
+    fn from_str(role: &str) -> Result<Self, Self::Err> {
+        match role {
+            "read-only" => Ok(Role::ReadOnly),
+            "read-write" => Ok(Role::ReadWrite),
+            "admin" => Ok(Role::Admin),
+            role => Err(Status::permission_denied(
+                "Unknown role ".to_string() + role,
+            )),
+        }
+    }
+}
+
+/// The tenants a caller may name in the `x-mrcache-tenant` metadata, `default` standing for the
+/// shared keyspace and `*` for every tenant.
+#[derive(Clone, Debug)]
+pub struct TenantGrant(Arc<Vec<String>>);
+
+impl TenantGrant {
+    fn new(tenants: Vec<String>) -> Self {
+        Self(Arc::new(tenants))
+    }
+
+    fn any() -> Self {
+        Self::new(vec![ANY_TENANT.to_string()])
+    }
+
+    /// Whether the caller may use `tenant`, or the shared keyspace for `None`.
+    pub fn allows(&self, tenant: Option<&str>) -> bool {
+        let tenant = tenant.unwrap_or(DEFAULT_TENANT);
+        self.0
+            .iter()
+            .any(|granted| granted == ANY_TENANT || granted == tenant)
+    }
+}
+
+/// What an authenticated caller may do, and where.
+#[derive(Clone, Debug)]
+pub struct Grant {
+    pub role: Role,
+    pub tenants: TenantGrant,
+}
+
+impl Grant {
+    fn new(role: Role, tenants: &[String]) -> Self {
+        Self {
+            role,
+            tenants: TenantGrant::new(tenants.to_vec()),
+        }
+    }
+
+    /// Records the grant in the request extensions, for [`require`] and the tenant lookup.
+    pub fn record<T>(self, request: &mut Request<T>) {
+        request.extensions_mut().insert(self.role);
+        request.extensions_mut().insert(self.tenants);
+    }
+}
+
+struct Jwt {
+    jwks: JwkSet,
+    issuer: Option<String>,
+    audience: Option<String>,
+    role_claim: String,
+    tenant_claim: String,
+}
+
+/// Authenticates callers by static API key (`x-api-key`), JWT bearer token or mutual-TLS client
+/// certificate, and records their role and tenants in the request extensions for each RPC to
+/// check with [`require`] and `Tenants::resolve`.
+#[derive(Clone)]
+pub struct Auth {
+    enabled: bool,
+    api_keys: Arc<HashMap<String, Grant>>,
+    jwt: Option<Arc<Jwt>>,
+    client_certs: Arc<HashMap<String, Grant>>,
+}
+
+impl Auth {
+    pub fn from_config(config: &AuthConfig) -> Self {
+        let api_keys = config
+            .api_keys
+            .iter()
+            .map(|api_key| {
+                let grant = Grant::new(api_key.role, &api_key.tenants);
+                (api_key.key.clone(), grant)
+            })
+            .collect();
+        let client_certs = config
+            .client_certs
+            .iter()
+            .map(|client_cert| {
+                let grant = Grant::new(client_cert.role, &client_cert.tenants);
+                (client_cert.subject.clone(), grant)
+            })
+            .collect();
+
+        let jwt = config.jwks_file.as_ref().map(|path| {
+            let contents = fs::read_to_string(path).expect("Failed to read JWKS file.");
+            Arc::new(Jwt {
+                jwks: serde_json::from_str(&contents).expect("Failed to parse JWKS file."),
+                issuer: config.issuer.clone(),
+                audience: config.audience.clone(),
+                role_claim: config.role_claim.clone(),
+                tenant_claim: config.tenant_claim.clone(),
+            })
+        });
+
+        Self {
+            enabled: config.enabled,
+            api_keys: Arc::new(api_keys),
+            jwt,
+            client_certs: Arc::new(client_certs),
+        }
+    }
+
+    /// Credentials in the metadata win over the client certificate, so a service holding a cert
+    /// can still act for a caller with a narrower key or token.
+    pub fn authenticate(
+        &self,
+        metadata: &MetadataMap,
+        peer_certs: Option<&[Certificate]>,
+    ) -> Result<Grant, Status> {
+        if !self.enabled {
+            return Ok(Grant {
+                role: Role::Admin,
+                tenants: TenantGrant::any(),
+            });
+        }
+
+        if let Some(key) = metadata.get(API_KEY_HEADER) {
+            let key = key
+                .to_str()
+                .map_err(|_| Status::unauthenticated("Invalid API key"))?;
+            return self
+                .api_keys
+                .get(key)
+                .cloned()
+                .ok_or_else(|| Status::unauthenticated("Invalid API key"));
+        }
+
+        let token = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let token = match (token, peer_certs.and_then(|certs| certs.first())) {
+            (Some(token), _) => token,
+            (None, Some(cert)) => return self.client_cert_grant(cert),
+            (None, None) => {
+                return Err(Status::unauthenticated(
+                    "Missing API key, bearer token or client certificate",
+                ))
+            }
+        };
+
+        match &self.jwt {
+            Some(jwt) => jwt.validate(token),
+            None => Err(Status::unauthenticated("Bearer tokens are not accepted")),
+        }
+    }
+
+    /// Looks the leaf certificate up by its full subject, then by its common name alone.
+    fn client_cert_grant(&self, cert: &Certificate) -> Result<Grant, Status> {
+        let (_, cert) = X509Certificate::from_der(cert.get_ref())
+            .map_err(|_| Status::unauthenticated("Invalid client certificate"))?;
+        let subject = cert.subject();
+
+        let mut names = vec![subject.to_string()];
+        names.extend(
+            subject
+                .iter_common_name()
+                .filter_map(|cn| cn.as_str().ok())
+                .map(|cn| "CN=".to_string() + cn),
+        );
+
+        names
+            .iter()
+            .find_map(|name| self.client_certs.get(name).cloned())
+            .ok_or_else(|| {
+                Status::permission_denied("No role for client certificate ".to_string() + &names[0])
+            })
+    }
+}
+
+impl Jwt {
+    fn validate(&self, token: &str) -> Result<Grant, Status> {
+        let invalid = |e: jsonwebtoken::errors::Error| {
+            Status::unauthenticated("Invalid bearer token: ".to_string() + &e.to_string())
+        };
+
+        let header = decode_header(token).map_err(invalid)?;
+        let jwk = header
+            .kid
+            .as_deref()
+            .and_then(|kid| self.jwks.find(kid))
+            .ok_or_else(|| Status::unauthenticated("Bearer token signed by an unknown key"))?;
+
+        if let Some(key_algorithm) = jwk.common.key_algorithm {
+            if Algorithm::from_str(&key_algorithm.to_string()).ok() != Some(header.alg) {
+                return Err(Status::unauthenticated(
+                    "Bearer token algorithm does not match its key",
+                ));
+            }
+        }
+
+        let key = DecodingKey::from_jwk(jwk).map_err(invalid)?;
+        let mut validation = Validation::new(header.alg);
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+        if let Some(issuer) = &self.issuer {
+            validation.set_issuer(&[issuer]);
+        }
+
+        let claims = decode::<HashMap<String, serde_json::Value>>(token, &key, &validation)
+            .map_err(invalid)?
+            .claims;
+
+        let role = claims
+            .get(&self.role_claim)
+            .and_then(|role| role.as_str())
+            .ok_or_else(|| Status::permission_denied("Bearer token has no role"))?
+            .parse()?;
+        // The tenant claim holds one tenant or a list of them, and leaves out every tenant but
+        // the shared keyspace when missing.
+        let tenants = match claims.get(&self.tenant_claim) {
+            None => vec![DEFAULT_TENANT.to_string()],
+            Some(serde_json::Value::String(tenant)) => vec![tenant.clone()],
+            Some(serde_json::Value::Array(tenants)) => tenants
+                .iter()
+                .map(|tenant| tenant.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .ok_or_else(|| Status::permission_denied("Bearer token has invalid tenants"))?,
+            Some(_) => {
+                return Err(Status::permission_denied(
+                    "Bearer token has invalid tenants",
+                ))
+            }
+        };
+
+        Ok(Grant::new(role, &tenants))
+    }
+}
+
+impl Interceptor for Auth {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let peer_certs = request.peer_certs();
+        let grant =
+            self.authenticate(request.metadata(), peer_certs.as_deref().map(Vec::as_slice))?;
+        grant.record(&mut request);
+
+        Ok(request)
+    }
+}
+
+/// The metadata a gRPC client would send for a username and password. The password is an API
+/// key, or a JWT when it has the three dot-separated parts of one. A username other than
+/// `default` names the tenant.
+fn password_metadata(username: Option<&str>, password: &str) -> Result<MetadataMap, Status> {
+    let invalid = |_| Status::invalid_argument("Credentials must be ASCII");
+    let mut metadata = MetadataMap::new();
+    if let Some(tenant) = username.filter(|username| *username != DEFAULT_TENANT) {
+        metadata.insert(
+            TENANT_HEADER,
+            MetadataValue::try_from(tenant).map_err(invalid)?,
+        );
+    }
+    if password.split('.').count() == 3 {
+        let bearer = "Bearer ".to_string() + password;
+        metadata.insert(
+            "authorization",
+            MetadataValue::try_from(bearer).map_err(invalid)?,
+        );
+    } else {
+        metadata.insert(
+            API_KEY_HEADER,
+            MetadataValue::try_from(password).map_err(invalid)?,
+        );
+    }
+
+    Ok(metadata)
+}
+
+/// Who is on the other end of a RESP or memcached connection. Those protocols log in once per
+/// connection with a username and password, so every call made for the connection carries the
+/// metadata and grant a gRPC call with the same credentials would have.
+pub struct Caller {
+    auth: Auth,
+    metadata: MetadataMap,
+    grant: Result<Grant, Status>,
+    peer_certs: Option<Vec<Certificate>>,
+}
+
+impl Caller {
+    /// Starts out as whoever the client certificate says, or as an admin when auth is off.
+    pub fn new(auth: Auth, peer_certs: Option<Vec<Certificate>>) -> Self {
+        let metadata = MetadataMap::new();
+        let grant = auth.authenticate(&metadata, peer_certs.as_deref());
+        Self {
+            auth,
+            metadata,
+            grant,
+            peer_certs,
+        }
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.grant.is_ok()
+    }
+
+    /// Switches to the given credentials, see [`password_metadata`]. A failed login keeps the
+    /// previous one.
+    pub fn login(&mut self, username: Option<&str>, password: &str) -> Result<(), Status> {
+        let metadata = password_metadata(username, password)?;
+        let grant = self
+            .auth
+            .authenticate(&metadata, self.peer_certs.as_deref())?;
+        self.metadata = metadata;
+        self.grant = Ok(grant);
+
+        Ok(())
+    }
+
+    pub fn request<T>(&self, message: T) -> Result<Request<T>, Status> {
+        let grant = self.grant.clone()?;
+        let mut request = Request::new(message);
+        *request.metadata_mut() = self.metadata.clone();
+        grant.record(&mut request);
+
+        Ok(request)
+    }
+}
+
+/// Checks the role the interceptor recorded allows an RPC needing `needed`.
+pub fn require<T>(request: &Request<T>, needed: Role) -> Result<(), Status> {
+    match request.extensions().get::<Role>() {
+        Some(role) if *role >= needed => Ok(()),
+        Some(role) => Err(Status::permission_denied(format!(
+            "{:?} callers cannot use this RPC, it needs {:?}",
+            role, needed
+        ))),
+        None => Err(Status::unauthenticated("Request was not authenticated")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use jsonwebtoken::{encode, get_current_timestamp, EncodingKey, Header};
+    use serde_json::json;
+    use tonic::Code;
+
+    const SECRET: &[u8] = b"mrcache-auth-tests-hmac-secret!!";
+
+    /// A self-signed certificate for `O=Acme, CN=web`, in DER as a peer presents it.
+    const CLIENT_CERT: &str = "\
+        MIIBUTCCAQOgAwIBAgIUaq1qb4QrjF7ABG5S377onAsmDnswBQYDK2VwMB0xDTAL\
+        BgNVBAoMBEFjbWUxDDAKBgNVBAMMA3dlYjAgFw0yNjEwMTkwNTUzMTlaGA8yMTI2\
+        MDkyNTA1NTMxOVowHTENMAsGA1UECgwEQWNtZTEMMAoGA1UEAwwDd2ViMCowBQYD\
+        K2VwAyEAl38BziAzgbdpXjmQW0hv6LX8dRA9TU0TpxKXrckA1fyjUzBRMB0GA1Ud\
+        DgQWBBTwZbr3tdisFC+vNQozdhB2b2VE8TAfBgNVHSMEGDAWgBTwZbr3tdisFC+v\
+        NQozdhB2b2VE8TAPBgNVHRMBAf8EBTADAQH/MAUGAytlcANBAO39KmSieVyK/HwL\
+        o4uOArZiTYscfRjUbZdFMdVxmHO7ky9Nj7AHAv9a+m0lkMFRz1pKIjlT5EGftQaZ\
+        x0ZpYAA=";
+
+    fn client_cert() -> Vec<Certificate> {
+        let der = base64::engine::general_purpose::STANDARD
+            .decode(CLIENT_CERT)
+            .unwrap();
+        vec![Certificate::from_pem(der)]
+    }
+
+    /// Auth taking a read-only API key, HS256 tokens for the `mrcache` audience and a
+    /// read-write grant for `cert_subject`.
+    fn auth(cert_subject: &str) -> Auth {
+        let dir = std::env::temp_dir().join(format!("mrcache-auth-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let jwks_file = dir.join("jwks.json");
+        let k = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(SECRET);
+        let jwks = json!({"keys": [{"kty": "oct", "kid": "test", "alg": "HS256", "k": k}]});
+        fs::write(&jwks_file, jwks.to_string()).unwrap();
+
+        Auth::from_config(&AuthConfig {
+            enabled: true,
+            api_keys: vec![crate::config::ApiKeyConfig {
+                key: "reader".to_string(),
+                role: Role::ReadOnly,
+                tenants: vec![DEFAULT_TENANT.to_string()],
+            }],
+            jwks_file: Some(jwks_file.display().to_string()),
+            audience: Some("mrcache".to_string()),
+            client_certs: vec![crate::config::ClientCertConfig {
+                subject: cert_subject.to_string(),
+                role: Role::ReadWrite,
+                tenants: vec!["acme".to_string()],
+            }],
+            ..AuthConfig::default()
+        })
+    }
+
+    fn token(kid: &str, secret: &[u8], claims: serde_json::Value) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(kid.to_string());
+        encode(&header, &claims, &EncodingKey::from_secret(secret)).unwrap()
+    }
+
+    fn admin_token() -> String {
+        let exp = get_current_timestamp() + 600;
+        token(
+            "test",
+            SECRET,
+            json!({"aud": "mrcache", "exp": exp, "role": "admin", "tenants": ["a", "b"]}),
+        )
+    }
+
+    fn metadata(api_key: Option<&str>, token: Option<&str>) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        if let Some(key) = api_key {
+            metadata.insert(API_KEY_HEADER, key.parse().unwrap());
+        }
+        if let Some(token) = token {
+            let bearer = "Bearer ".to_string() + token;
+            metadata.insert("authorization", bearer.parse().unwrap());
+        }
+        metadata
+    }
+
+    fn denied(result: Result<Grant, Status>) -> Code {
+        result.map(|grant| grant.role).unwrap_err().code()
+    }
+
+    #[test]
+    fn lets_everyone_in_as_admin_when_disabled() {
+        let auth = Auth::from_config(&AuthConfig::default());
+        let grant = auth.authenticate(&MetadataMap::new(), None).unwrap();
+
+        assert_eq!(grant.role, Role::Admin);
+        assert!(grant.tenants.allows(Some("anyone")));
+    }
+
+    #[test]
+    fn prefers_api_key_then_bearer_token_then_client_certificate() {
+        let auth = auth("O=Acme, CN=web");
+        let certs = client_cert();
+        let token = admin_token();
+
+        let grant = auth
+            .authenticate(&metadata(Some("reader"), Some(&token)), Some(&certs))
+            .unwrap();
+        assert_eq!(grant.role, Role::ReadOnly);
+        assert!(grant.tenants.allows(None));
+
+        let grant = auth
+            .authenticate(&metadata(None, Some(&token)), Some(&certs))
+            .unwrap();
+        assert_eq!(grant.role, Role::Admin);
+        assert!(grant.tenants.allows(Some("b")));
+        assert!(!grant.tenants.allows(Some("acme")));
+
+        let grant = auth
+            .authenticate(&metadata(None, None), Some(&certs))
+            .unwrap();
+        assert_eq!(grant.role, Role::ReadWrite);
+        assert!(grant.tenants.allows(Some("acme")));
+    }
+
+    #[test]
+    fn does_not_fall_back_when_the_preferred_credential_is_bad() {
+        let auth = auth("O=Acme, CN=web");
+        let certs = client_cert();
+
+        let bad_key = metadata(Some("writer"), Some(&admin_token()));
+        assert_eq!(
+            denied(auth.authenticate(&bad_key, Some(&certs))),
+            Code::Unauthenticated
+        );
+        let bad_token = metadata(None, Some("not.a.token"));
+        assert_eq!(
+            denied(auth.authenticate(&bad_token, Some(&certs))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            denied(auth.authenticate(&MetadataMap::new(), None)),
+            Code::Unauthenticated
+        );
+    }
+
+    #[test]
+    fn matches_client_certificates_by_subject_or_common_name() {
+        let certs = client_cert();
+        let grant = auth("CN=web")
+            .authenticate(&MetadataMap::new(), Some(&certs))
+            .unwrap();
+        assert_eq!(grant.role, Role::ReadWrite);
+
+        let unknown = auth("CN=api").authenticate(&MetadataMap::new(), Some(&certs));
+        assert_eq!(denied(unknown), Code::PermissionDenied);
+        let garbage = [Certificate::from_pem(b"not a certificate")];
+        let garbage = auth("CN=web").authenticate(&MetadataMap::new(), Some(&garbage));
+        assert_eq!(denied(garbage), Code::Unauthenticated);
+    }
+
+    #[test]
+    fn rejects_expired_and_invalid_tokens() {
+        let auth = auth("CN=web");
+        let now = get_current_timestamp();
+        let claims = |exp: u64, aud: &str| json!({"aud": aud, "exp": exp, "role": "admin"});
+        let rejected =
+            |token: String| denied(auth.authenticate(&metadata(None, Some(&token)), None));
+
+        assert_eq!(
+            rejected(token("test", SECRET, claims(now - 600, "mrcache"))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            rejected(token(
+                "test",
+                b"some other secret",
+                claims(now + 600, "mrcache")
+            )),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            rejected(token("other", SECRET, claims(now + 600, "mrcache"))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            rejected(token("test", SECRET, claims(now + 600, "elsewhere"))),
+            Code::Unauthenticated
+        );
+        assert_eq!(
+            rejected(token(
+                "test",
+                SECRET,
+                json!({"aud": "mrcache", "role": "admin"})
+            )),
+            Code::Unauthenticated
+        );
+        assert_eq!(rejected("not.a.token".to_string()), Code::Unauthenticated);
+
+        let roleless = token("test", SECRET, json!({"aud": "mrcache", "exp": now + 600}));
+        assert_eq!(rejected(roleless), Code::PermissionDenied);
+        let unknown_role = token(
+            "test",
+            SECRET,
+            json!({"aud": "mrcache", "exp": now + 600, "role": "root"}),
+        );
+        assert_eq!(rejected(unknown_role), Code::PermissionDenied);
+        let bad_tenants = token(
+            "test",
+            SECRET,
+            json!({"aud": "mrcache", "exp": now + 600, "role": "admin", "tenants": 7}),
+        );
+        assert_eq!(rejected(bad_tenants), Code::PermissionDenied);
+    }
+
+    #[test]
+    fn gives_tokens_without_tenants_the_shared_keyspace() {
+        let auth = auth("CN=web");
+        let exp = get_current_timestamp() + 600;
+        let single = token(
+            "test",
+            SECRET,
+            json!({"aud": "mrcache", "exp": exp, "role": "read-write", "tenants": "a"}),
+        );
+        let grant = auth
+            .authenticate(&metadata(None, Some(&single)), None)
+            .unwrap();
+        assert!(grant.tenants.allows(Some("a")));
+        assert!(!grant.tenants.allows(None));
+
+        let missing = token(
+            "test",
+            SECRET,
+            json!({"aud": "mrcache", "exp": exp, "role": "read-write"}),
+        );
+        let grant = auth
+            .authenticate(&metadata(None, Some(&missing)), None)
+            .unwrap();
+        assert!(grant.tenants.allows(None));
+        assert!(!grant.tenants.allows(Some("a")));
+    }
+
+    #[test]
+    fn requires_a_role_at_least_as_strong_as_needed() {
+        let mut request = Request::new(());
+        assert_eq!(
+            require(&request, Role::ReadOnly).unwrap_err().code(),
+            Code::Unauthenticated
+        );
+
+        Grant::new(Role::ReadWrite, &[]).record(&mut request);
+        assert!(require(&request, Role::ReadOnly).is_ok());
+        assert!(require(&request, Role::ReadWrite).is_ok());
+        let denied = require(&request, Role::Admin).unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+        assert!(denied.message().contains("needs Admin"));
+    }
+}
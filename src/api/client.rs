@@ -2,178 +2,1244 @@
 
 use crate::api::mr_cache::mr_cache_server::MrCache;
 use crate::api::mr_cache::{
-    Effect, HashedKeyValues, HashedKeys, Key, KeyValues, Keys, Value, Values,
+    ContentType, Count, Effect, HashedKeyValues, HashedKeys, Json, JsonArrayItems, JsonDocument,
+    JsonIncrement, JsonPatch, JsonPath, JsonPaths, Key, KeyValues, Keys, Scan, ScanPage, Tags,
+    Value, Values,
 };
 use r2d2::PooledConnection;
-use redis::{Client, Commands, RedisResult};
+use redis::{Commands, RedisResult};
+use std::fmt::Write;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tonic::{Request, Response, Status};
+use tracing::{error, info_span};
 
-use crate::api::pool::RedisPool;
+use crate::api::auth::{require, Role};
+use crate::api::coalesce::SingleFlight;
+use crate::api::compression::Compressor;
+use crate::api::documents::{self, Codec, Edit, Path, Store};
+use crate::api::encoding;
+use crate::api::encryption::Encryption;
+use crate::api::items::{self, CountItem, CountOutcome, Item, StoreItem, StoreOutcome, TouchItem};
+use crate::api::l1::{self, L1};
+use crate::api::loader::Loader;
+use crate::api::lock::RecomputeLock;
+use crate::api::meta::{Meta, Stored};
+use crate::api::metrics;
+use crate::api::pool::{Connector, Pool, RedisPool};
+use crate::api::replicas::{Consistency, Replica, Replicas};
+use crate::api::slots;
+use crate::api::tags;
+use crate::api::tenant::{Tenant, Tenants};
+use crate::config::Config;
+
+/// Values read from Redis, along with the metadata of the key each one came from.
+#[derive(Clone)]
+struct Read {
+    values: Vec<Option<Vec<u8>>>,
+    meta: Vec<Option<Meta>>,
+    /// Whether the read took the recompute lock on a missing key, leaving its caller to recompute
+    /// the value, so the miss is not to be shared with coalesced callers.
+    locked: bool,
+}
+
+type ReadResult = Result<Read, Status>;
+
+/// Where a `Scan` cursor keeps the index of the master it is on, above that master's own cursor.
+const MASTER_CURSOR_SHIFT: u32 = 48;
+
+impl Read {
+    fn without_meta(values: Vec<Option<String>>) -> Self {
+        Self {
+            values: strings_to_bytes(values),
+            meta: Vec::new(),
+            locked: false,
+        }
+    }
+
+    /// Builds the reply, transcoding structured values to the accepted content type.
+    fn into_values(self, accept: ContentType) -> Values {
+        Values {
+            values: self.into_found(accept).into_iter().flatten().collect(),
+        }
+    }
+
+    /// `into_values` keeping a `None` in place of each value that was missing.
+    fn into_found(self, accept: ContentType) -> Vec<Option<Value>> {
+        let meta = self.meta.into_iter().chain(std::iter::repeat(None));
+        self.values
+            .into_iter()
+            .zip(meta)
+            .map(|(opt, meta)| {
+                opt.map(|val| {
+                    let content_type = meta.map_or(ContentType::Unspecified, |m| m.content_type);
+                    let (val, content_type) = encoding::transcode(val, content_type, accept);
+                    Value {
+                        age_ms: meta.map(|m| m.age_ms()),
+                        stale: meta.is_some_and(|m| m.is_stale()),
+                        ..Value::from_bytes(val, content_type)
+                    }
+                })
+            })
+            .collect()
+    }
+}
 
 pub struct MrCacheService {
-    pub(crate) pool: Arc<RedisPool>,
+    tenants: Arc<Tenants>,
+    reads: SingleFlight<ReadResult>,
+    recompute_lock: Option<RecomputeLock>,
+    loader: Option<Arc<Loader>>,
+    redis_json: Option<bool>,
+    compressor: Arc<Compressor>,
+    encryption: Arc<Encryption>,
+    l1: Option<Arc<L1>>,
 }
 
 #[tonic::async_trait]
 impl MrCache for MrCacheService {
+    async fn scan(&self, request: Request<Scan>) -> Result<Response<ScanPage>, Status> {
+        require(&request, Role::ReadOnly)?;
+        let tenant = self.tenants.resolve(&request)?;
+        if self.encryption.hashes_keys() {
+            return Err(Status::failed_precondition(
+                "Keys cannot be scanned while they are hashed",
+            ));
+        }
+        let consistency = Consistency::of(&request)?;
+        let inner = request.into_inner();
+        let pattern = match inner.pattern.as_str() {
+            "" => tenant.key("*"),
+            pattern => tenant.key(pattern),
+        };
+        let count = match inner.count {
+            0 => 10,
+            count => count,
+        };
+        let (replica, cursor) = match &tenant.replicas {
+            Some(replicas) => replicas.pick_for_scan(consistency, inner.cursor)?,
+            None => (None, inner.cursor),
+        };
+
+        let scan_page = |mut con: PooledConnection<Connector>| {
+            // The cursor's top bits pick the master being scanned, the rest is its own cursor.
+            let master = (cursor >> MASTER_CURSOR_SHIFT) as usize;
+            let node_cursor = cursor & ((1 << MASTER_CURSOR_SHIFT) - 1);
+            let scanned = con.on_master(master, |node| {
+                redis::cmd("SCAN")
+                    .arg(node_cursor)
+                    .arg("MATCH")
+                    .arg(&pattern)
+                    .arg("COUNT")
+                    .arg(count)
+                    .query::<(u64, Vec<String>)>(node)
+            })?;
+            match scanned {
+                Some((0, found)) if master + 1 < con.masters()?.len() => {
+                    Ok((((master + 1) as u64) << MASTER_CURSOR_SHIFT, found))
+                }
+                Some((0, found)) => Ok((0, found)),
+                Some((next, found)) => Ok(((master as u64) << MASTER_CURSOR_SHIFT | next, found)),
+                None => Ok((0, Vec::new())),
+            }
+        };
+        let (next, found) = match replica {
+            Some(replica) => self.run_replica_cmd(replica, "SCAN", 0, scan_page)?,
+            None => self.run_redis_cmd(&tenant, "SCAN", 0, scan_page)?,
+        };
+
+        let keys: Vec<Key> = found
+            .iter()
+            .filter_map(|key| tenant.strip(key))
+            .map(|key| Key {
+                key: key.to_string(),
+            })
+            .collect();
+        Ok(Response::new(ScanPage {
+            cursor: Replicas::scan_cursor(replica, next),
+            keys: Some(Keys {
+                keys,
+                ..Default::default()
+            }),
+        }))
+    }
+
     async fn set(&self, request: Request<KeyValues>) -> Result<Response<Effect>, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
         let inner = request.into_inner();
-        let keyValues: Vec<(&str, &str)> = inner
+        let prefixed: Vec<String> = inner
+            .key_values
+            .iter()
+            .map(|kv| self.redis_key(&tenant, &kv.key))
+            .collect::<Result<_, Status>>()?;
+        let values = inner
             .key_values
             .iter()
-            .map(|kv| (kv.key.as_str(), kv.value.as_str()))
+            .zip(&prefixed)
+            .map(|(kv, key)| {
+                encoding::validate(kv.content_type(), kv.bytes().to_vec())
+                    .map(|value| self.encode_value(&kv.key, key, None, value))
+            })
+            .collect::<Result<Vec<Vec<u8>>, Status>>()?;
+        let keyValues: Vec<(&str, &[u8])> = prefixed
+            .iter()
+            .zip(&values)
+            .map(|(key, value)| (key.as_str(), value.as_slice()))
             .collect();
+        let keys: Vec<&str> = keyValues.iter().map(|(k, _)| *k).collect();
+        let bytes = keyValues
+            .iter()
+            .map(|(k, v)| (k.len() + v.len()) as u64)
+            .sum();
+        tenant.check_quota(keys.len() as u64, bytes)?;
+        let tags: Vec<String> = inner.tags.iter().map(|tag| tenant.key(tag)).collect();
+        let meta = Meta::checked(inner.soft_ttl_ms, inner.hard_ttl_ms)?;
 
-        self.execute_redis_cmd(
-            "SET",
-            |mut con| con.mset(&keyValues),
-            |_: ()| Effect { effect: true },
-        )
-        .await
+        let effect = self
+            .execute_redis_cmd(
+                &tenant,
+                "SET",
+                keys.len(),
+                |mut con| {
+                    for group in slots::groups(&keys) {
+                        let group_values: Vec<(&str, &[u8])> =
+                            group.iter().map(|&i| keyValues[i]).collect();
+                        let mut pipe = redis::pipe();
+                        pipe.atomic().mset(&group_values).ignore();
+                        for &i in &group {
+                            meta.with_content_type(inner.key_values[i].content_type())
+                                .write(&mut pipe, keys[i]);
+                        }
+                        pipe.query::<()>(&mut *con)?;
+                        for &i in &group {
+                            tags::write(&mut *con, keys[i], &tags, meta.hard_ttl_ms)?;
+                        }
+                    }
+                    match &self.recompute_lock {
+                        Some(lock) => lock.release(&mut *con, &keys),
+                        None => Ok(()),
+                    }
+                },
+                |_: ()| Effect { effect: true },
+            )
+            .await;
+        self.evict(&tenant, &keys);
+        if effect.is_ok() {
+            tenant.record_usage(keys.len() as u64, bytes);
+        }
+
+        effect
     }
 
     async fn get(&self, request: Request<Keys>) -> Result<Response<Values>, Status> {
-        let inner = request.into_inner();
-        let keys: Vec<&str> = inner.keys.iter().map(|k| k.key.as_str()).collect();
+        let found = self.mget(request).await?;
 
-        self.execute_redis_cmd(
-            "GET",
-            |mut con| con.mget(keys),
-            |results: Vec<Option<String>>| {
-                let values: Vec<Value> = results
-                    .into_iter()
-                    .filter_map(|opt| opt.map(|val| Value { value: val }))
-                    .collect();
-                Values { values }
-            },
-        )
-        .await
+        Ok(Response::new(Values {
+            values: found.into_iter().flatten().collect(),
+        }))
     }
 
     async fn hset(&self, request: Request<HashedKeyValues>) -> Result<Response<Effect>, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
         let inner = request.into_inner();
-        let key = inner.key.unwrap().key;
-        let keyValues = inner.key_values.unwrap().key_values;
-        let fieldValues: Vec<(&str, &str)> = keyValues
+        let hash_key = inner.key.unwrap().key;
+        let key = self.redis_key(&tenant, &hash_key)?;
+        let keyValues = inner.key_values.unwrap();
+        let tags: Vec<String> = keyValues.tags.iter().map(|tag| tenant.key(tag)).collect();
+        // A hash has one metadata record, so its fields carry one content type between them.
+        let content_type = keyValues
+            .key_values
+            .first()
+            .map_or(ContentType::Unspecified, |kv| kv.content_type());
+        if keyValues
+            .key_values
             .iter()
-            .map(|kv| (kv.key.as_str(), kv.value.as_str()))
-            .collect();
+            .any(|kv| kv.content_type() != content_type)
+        {
+            return Err(Status::invalid_argument(
+                "Fields set together must share a content type",
+            ));
+        }
+        let meta = Meta::checked(keyValues.soft_ttl_ms, keyValues.hard_ttl_ms)?
+            .with_content_type(content_type);
+        let fieldValues = keyValues
+            .key_values
+            .iter()
+            .map(|kv| {
+                let field = Some(kv.key.as_bytes());
+                encoding::validate(content_type, kv.bytes().to_vec()).map(|value| {
+                    (
+                        kv.key.as_str(),
+                        self.encode_value(&hash_key, &key, field, value),
+                    )
+                })
+            })
+            .collect::<Result<Vec<(&str, Vec<u8>)>, Status>>()?;
+        let bytes = fieldValues
+            .iter()
+            .map(|(field, value)| (field.len() + value.len()) as u64)
+            .sum::<u64>()
+            + key.len() as u64;
+        tenant.check_quota(1, bytes)?;
 
-        self.execute_redis_cmd(
-            "HSET",
-            |mut con| con.hset_multiple(key, &fieldValues),
-            |_: ()| Effect { effect: true },
-        )
-        .await
+        let effect = self
+            .execute_redis_cmd(
+                &tenant,
+                "HSET",
+                1,
+                |mut con| {
+                    let mut pipe = redis::pipe();
+                    pipe.atomic().hset_multiple(&key, &fieldValues).ignore();
+                    meta.write(&mut pipe, &key);
+                    pipe.query::<()>(&mut *con)?;
+                    tags::write(&mut *con, &key, &tags, meta.hard_ttl_ms)
+                },
+                |_: ()| Effect { effect: true },
+            )
+            .await;
+        if effect.is_ok() {
+            tenant.record_usage(1, bytes);
+        }
+
+        effect
     }
 
     async fn hget(&self, request: Request<HashedKeys>) -> Result<Response<Values>, Status> {
-        let inner = request.into_inner();
-        let key = inner.key.unwrap().key;
-        let keys = inner.keys.unwrap().keys;
-        let fields: Vec<&str> = keys.iter().map(|k| k.key.as_str()).collect();
+        let found = self.hmget(request).await?;
 
-        self.execute_redis_cmd(
-            "HGET",
-            |mut con| con.hget(key, &fields),
-            |results: Vec<Option<String>>| {
-                let values: Vec<Value> = results
-                    .into_iter()
-                    .filter_map(|opt| opt.map(|val| Value { value: val }))
-                    .collect();
-                Values { values }
-            },
-        )
-        .await
+        Ok(Response::new(Values {
+            values: found.into_iter().flatten().collect(),
+        }))
     }
 
     async fn hgetall(&self, request: Request<Key>) -> Result<Response<Values>, Status> {
+        require(&request, Role::ReadOnly)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let consistency = Consistency::of(&request)?;
         let inner = request.into_inner();
-        let key = inner.key;
+        let key = self.redis_key(&tenant, &inner.key)?;
 
-        self.execute_redis_cmd(
+        self.execute_coalesced_read(
+            &tenant,
             "HGETALL",
-            |mut con| con.hgetall(key),
-            |results: Vec<Option<String>>| {
-                let values: Vec<Value> = results
-                    .into_iter()
-                    .filter_map(|opt| opt.map(|val| Value { value: val }))
-                    .collect();
-                Values { values }
+            consistency,
+            &[&key],
+            async {
+                self.read_hash(&tenant, consistency, "HGETALL", &key, true, |con| {
+                    let fields: Vec<(Vec<u8>, Vec<u8>)> = con.hgetall(&key)?;
+                    Ok(fields.into_iter().map(|(f, v)| (f, Some(v))).collect())
+                })
+            },
+            |read: Read| {
+                // A whole hash is one lookup, found when it has any field.
+                metrics::record_lookups("HGETALL", &[(!read.values.is_empty()).then_some(())]);
+                read.into_values(ContentType::Unspecified)
             },
         )
         .await
     }
 
     async fn hkeys(&self, request: Request<Key>) -> Result<Response<Keys>, Status> {
+        require(&request, Role::ReadOnly)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let consistency = Consistency::of(&request)?;
         let inner = request.into_inner();
-        let key = inner.key;
+        let key = self.redis_key(&tenant, &inner.key)?;
 
-        self.execute_redis_cmd(
+        self.execute_coalesced_read(
+            &tenant,
             "HKEYS",
-            |mut con| con.hkeys(key),
-            |results: Vec<Option<String>>| {
-                let keys: Vec<Key> = results
+            consistency,
+            &[&key],
+            async {
+                let replica = tenant.replica(consistency);
+                self.run_read_cmd(&tenant, replica, "HKEYS", 1, |mut con| con.hkeys(&key))
+                    .map(Read::without_meta)
+            },
+            |read: Read| {
+                let keys: Vec<Key> = read
+                    .values
                     .into_iter()
-                    .filter_map(|opt| opt.map(|k| Key { key: k }))
+                    .filter_map(|opt| {
+                        opt.map(|k| Key {
+                            key: String::from_utf8_lossy(&k).into_owned(),
+                        })
+                    })
                     .collect();
-                Keys { keys }
+                Keys {
+                    keys,
+                    ..Default::default()
+                }
             },
         )
         .await
     }
 
     async fn hvals(&self, request: Request<Key>) -> Result<Response<Values>, Status> {
+        require(&request, Role::ReadOnly)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let consistency = Consistency::of(&request)?;
         let inner = request.into_inner();
-        let key = inner.key;
+        let key = self.redis_key(&tenant, &inner.key)?;
 
-        self.execute_redis_cmd(
+        self.execute_coalesced_read(
+            &tenant,
             "HVALS",
-            |mut con| con.hvals(key),
-            |results: Vec<Option<String>>| {
-                let values: Vec<Value> = results
-                    .into_iter()
-                    .filter_map(|opt| opt.map(|val| Value { value: val }))
-                    .collect();
-                Values { values }
+            consistency,
+            &[&key],
+            async {
+                // Values are read with their fields, which they are opened against.
+                self.read_hash(&tenant, consistency, "HVALS", &key, false, |con| {
+                    let fields: Vec<(Vec<u8>, Vec<u8>)> = con.hgetall(&key)?;
+                    Ok(fields.into_iter().map(|(f, v)| (f, Some(v))).collect())
+                })
+            },
+            |read: Read| {
+                metrics::record_lookups("HVALS", &[(!read.values.is_empty()).then_some(())]);
+                read.into_values(ContentType::Unspecified)
             },
         )
         .await
     }
+
+    async fn invalidate_tags(&self, request: Request<Tags>) -> Result<Response<Count>, Status> {
+        require(&request, Role::Admin)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let inner = request.into_inner();
+        let prefixed: Vec<String> = inner.tags.iter().map(|t| tenant.key(t)).collect();
+        let tags: Vec<&str> = prefixed.iter().map(|t| t.as_str()).collect();
+
+        self.execute_redis_cmd(
+            &tenant,
+            "INVALIDATE",
+            tags.len(),
+            |mut con| tags::invalidate(&mut *con, &tags),
+            |count: u64| Count { count },
+        )
+        .await
+    }
+
+    async fn json_set(&self, request: Request<JsonDocument>) -> Result<Response<Effect>, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let inner = request.into_inner();
+        let key = self.redis_key(&tenant, &inner.key)?;
+        let bytes = (key.len() + inner.json.len()) as u64;
+        tenant.check_quota(1, bytes)?;
+        let path = Path::parse(&inner.path)?;
+        let edit = Edit::Set(documents::parse_json(&inner.json)?, inner.condition());
+        let tags: Vec<String> = inner.tags.iter().map(|tag| tenant.key(tag)).collect();
+        let meta = Meta::checked(inner.soft_ttl_ms, inner.hard_ttl_ms)?;
+        let codec = DocumentCodec::new(self, &inner.key, &key);
+        let store = self.document_store(&tenant, &codec)?;
+
+        let effect = self.run_redis_cmd(&tenant, "JSON.SET", 1, |mut con| {
+            let set = documents::set(&mut *con, store, &key, &path, edit, &meta, &tags)?;
+            if let (Ok(true), true, Some(lock)) = (&set, path.is_root(), &self.recompute_lock) {
+                lock.release(&mut *con, &[&key])?;
+            }
+            Ok(set)
+        })??;
+        self.evict(&tenant, &[&key]);
+        tenant.record_usage(1, bytes);
+
+        Ok(Response::new(Effect { effect }))
+    }
+
+    async fn json_get(&self, request: Request<JsonPaths>) -> Result<Response<Json>, Status> {
+        require(&request, Role::ReadOnly)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let inner = request.into_inner();
+        let key = self.redis_key(&tenant, &inner.key)?;
+        let paths = inner
+            .paths
+            .iter()
+            .map(|path| Path::parse(path))
+            .collect::<Result<Vec<Path>, Status>>()?;
+        let codec = DocumentCodec::new(self, &inner.key, &key);
+        let store = self.document_store(&tenant, &codec)?;
+
+        let json = self.run_redis_cmd(&tenant, "JSON.GET", 1, |mut con| {
+            documents::get(&mut *con, store, &key, &paths)
+        })??;
+        metrics::record_lookups("JSON.GET", &[json.as_ref()]);
+
+        Ok(Response::new(Json { json }))
+    }
+
+    async fn json_del(&self, request: Request<JsonPath>) -> Result<Response<Count>, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let inner = request.into_inner();
+        let key = self.redis_key(&tenant, &inner.key)?;
+        let path = Path::parse(&inner.path)?;
+        let codec = DocumentCodec::new(self, &inner.key, &key);
+        let store = self.document_store(&tenant, &codec)?;
+
+        let count = self.run_redis_cmd(&tenant, "JSON.DEL", 1, |mut con| {
+            documents::del(&mut *con, store, &key, &path)
+        })??;
+        self.evict(&tenant, &[&key]);
+
+        Ok(Response::new(Count { count }))
+    }
+
+    async fn json_arr_append(
+        &self,
+        request: Request<JsonArrayItems>,
+    ) -> Result<Response<Json>, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let inner = request.into_inner();
+        let key = self.redis_key(&tenant, &inner.key)?;
+        let bytes = inner.json.iter().map(|json| json.len() as u64).sum();
+        tenant.check_quota(0, bytes)?;
+        let path = Path::parse(&inner.path)?;
+        let values = inner
+            .json
+            .iter()
+            .map(|json| documents::parse_json(json))
+            .collect::<Result<Vec<_>, Status>>()?;
+        let codec = DocumentCodec::new(self, &inner.key, &key);
+        let store = self.document_store(&tenant, &codec)?;
+
+        let json = self.run_redis_cmd(&tenant, "JSON.ARRAPPEND", 1, |mut con| {
+            documents::update(&mut *con, store, &key, &path, Edit::ArrAppend(values))
+        })??;
+        self.evict(&tenant, &[&key]);
+        tenant.record_usage(0, bytes);
+
+        Ok(Response::new(Json { json }))
+    }
+
+    async fn json_num_incr_by(
+        &self,
+        request: Request<JsonIncrement>,
+    ) -> Result<Response<Json>, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        tenant.check_quota(0, 0)?;
+        let inner = request.into_inner();
+        let key = self.redis_key(&tenant, &inner.key)?;
+        let path = Path::parse(&inner.path)?;
+        let by = documents::parse_number(&inner.by)?;
+        let codec = DocumentCodec::new(self, &inner.key, &key);
+        let store = self.document_store(&tenant, &codec)?;
+
+        let json = self.run_redis_cmd(&tenant, "JSON.NUMINCRBY", 1, |mut con| {
+            documents::update(&mut *con, store, &key, &path, Edit::NumIncrBy(by))
+        })??;
+        self.evict(&tenant, &[&key]);
+
+        Ok(Response::new(Json { json }))
+    }
+
+    async fn json_merge(&self, request: Request<JsonPatch>) -> Result<Response<Effect>, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let inner = request.into_inner();
+        let key = self.redis_key(&tenant, &inner.key)?;
+        let bytes = (key.len() + inner.json.len()) as u64;
+        tenant.check_quota(1, bytes)?;
+        let path = Path::parse(&inner.path)?;
+        let patch = documents::parse_json(&inner.json)?;
+        let codec = DocumentCodec::new(self, &inner.key, &key);
+        let store = self.document_store(&tenant, &codec)?;
+
+        let effect = self.run_redis_cmd(&tenant, "JSON.MERGE", 1, |mut con| {
+            documents::merge(&mut *con, store, &key, &path, Edit::Merge(patch))
+        })??;
+        self.evict(&tenant, &[&key]);
+        tenant.record_usage(1, bytes);
+
+        Ok(Response::new(Effect { effect }))
+    }
+}
+
+/// `Get` and `HGet` answering for every key or field asked for, with `None` for those missing,
+/// like the `MGET` and `HMGET` of the RESP listener do.
+impl MrCacheService {
+    pub async fn mget(&self, request: Request<Keys>) -> Result<Vec<Option<Value>>, Status> {
+        require(&request, Role::ReadOnly)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let consistency = Consistency::of(&request)?;
+        let inner = request.into_inner();
+        let accept = inner.accept();
+        let prefixed: Vec<String> = inner
+            .keys
+            .iter()
+            .map(|k| self.redis_key(&tenant, &k.key))
+            .collect::<Result<_, Status>>()?;
+        let keys: Vec<&str> = prefixed.iter().map(|k| k.as_str()).collect();
+        let names: Vec<&str> = inner.keys.iter().map(|k| k.key.as_str()).collect();
+
+        self.execute_coalesced_read(
+            &tenant,
+            "GET",
+            consistency,
+            &keys,
+            self.read_strings(&tenant, consistency, &keys, &names),
+            |read: Read| {
+                metrics::record_lookups("GET", &read.values);
+                read.into_found(accept)
+            },
+        )
+        .await
+        .map(Response::into_inner)
+    }
+
+    pub async fn hmget(&self, request: Request<HashedKeys>) -> Result<Vec<Option<Value>>, Status> {
+        require(&request, Role::ReadOnly)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let consistency = Consistency::of(&request)?;
+        let inner = request.into_inner();
+        let key = self.redis_key(&tenant, &inner.key.unwrap().key)?;
+        let keys = inner.keys.unwrap().keys;
+        let fields: Vec<&str> = keys.iter().map(|k| k.key.as_str()).collect();
+        let args: Vec<&str> = [key.as_str()]
+            .into_iter()
+            .chain(fields.iter().copied())
+            .collect();
+
+        self.execute_coalesced_read(
+            &tenant,
+            "HGET",
+            consistency,
+            &args,
+            async {
+                self.read_hash(&tenant, consistency, "HGET", &key, false, |con| {
+                    let values: Vec<Option<Vec<u8>>> = con.hget(&key, &fields)?;
+                    Ok(fields
+                        .iter()
+                        .map(|f| f.as_bytes().to_vec())
+                        .zip(values)
+                        .collect())
+                })
+            },
+            |read: Read| {
+                metrics::record_lookups("HGET", &read.values);
+                read.into_found(ContentType::Unspecified)
+            },
+        )
+        .await
+        .map(Response::into_inner)
+    }
+}
+
+/// Memcached-style operations on string keys for the memcached listener, held to the same roles,
+/// tenants and quotas as the RPCs.
+impl MrCacheService {
+    pub fn get_items(&self, request: Request<Keys>) -> Result<Vec<Option<Item>>, Status> {
+        require(&request, Role::ReadOnly)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let prefixed: Vec<String> = request
+            .get_ref()
+            .keys
+            .iter()
+            .map(|k| self.redis_key(&tenant, &k.key))
+            .collect::<Result<_, Status>>()?;
+        let keys: Vec<&str> = prefixed.iter().map(|k| k.as_str()).collect();
+
+        let mut found = self.run_redis_cmd(&tenant, "GETS", keys.len(), |mut con| {
+            items::get(&mut *con, &keys)
+        })?;
+        for (key, item) in keys.iter().zip(found.iter_mut()) {
+            if let Some(item) = item {
+                item.value = self.decode_value(key, None, std::mem::take(&mut item.value))?;
+            }
+        }
+        metrics::record_lookups("GETS", &found);
+
+        Ok(found)
+    }
+
+    pub fn store_item(&self, request: Request<StoreItem>) -> Result<StoreOutcome, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let mut item = request.into_inner();
+        let key = self.redis_key(&tenant, &item.key)?;
+        item.value = self.encode_value(&item.key, &key, None, item.value);
+        let bytes = (key.len() + item.value.len()) as u64;
+        tenant.check_quota(1, bytes)?;
+
+        let stored = self.run_redis_cmd(&tenant, "STORE", 1, |mut con| {
+            let stored = items::store(&mut *con, &key, &item)?;
+            if let (StoreOutcome::Stored(_), Some(lock)) = (&stored, &self.recompute_lock) {
+                lock.release(&mut *con, &[&key])?;
+            }
+            Ok(stored)
+        });
+        self.evict(&tenant, &[&key]);
+        if let Ok(StoreOutcome::Stored(_)) = &stored {
+            tenant.record_usage(1, bytes);
+        }
+
+        stored
+    }
+
+    pub fn delete_item(&self, request: Request<Key>) -> Result<bool, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let key = self.redis_key(&tenant, &request.get_ref().key)?;
+
+        let deleted = self.run_redis_cmd(&tenant, "DELETE", 1, |mut con| {
+            items::delete(&mut *con, &key)
+        });
+        self.evict(&tenant, &[&key]);
+
+        deleted
+    }
+
+    pub fn count_item(&self, request: Request<CountItem>) -> Result<CountOutcome, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let item = request.get_ref();
+        let key = self.redis_key(&tenant, &item.key)?;
+        // A counter holds at most 20 digits.
+        let bytes = (key.len() + 20) as u64;
+        tenant.check_quota(1, bytes)?;
+        let cmd = if item.decrement { "DECR" } else { "INCR" };
+
+        let counted = self
+            .run_redis_cmd(&tenant, cmd, 1, |mut con| {
+                items::count(
+                    &mut *con,
+                    &key,
+                    item,
+                    |value| self.decode_value(&key, None, value),
+                    |value| self.encode_value(&item.key, &key, None, value),
+                )
+            })
+            .and_then(|counted| counted);
+        self.evict(&tenant, &[&key]);
+        if counted.is_ok() {
+            tenant.record_usage(1, bytes);
+        }
+
+        counted
+    }
+
+    pub fn touch_item(&self, request: Request<TouchItem>) -> Result<bool, Status> {
+        require(&request, Role::ReadWrite)?;
+        let tenant = self.tenants.resolve(&request)?;
+        let item = request.get_ref();
+        let key = self.redis_key(&tenant, &item.key)?;
+
+        let touched = self.run_redis_cmd(&tenant, "TOUCH", 1, |mut con| {
+            items::touch(&mut *con, &key, item)
+        });
+        self.evict(&tenant, &[&key]);
+
+        touched
+    }
 }
 
 impl MrCacheService {
-    fn get_connection(&self) -> Result<PooledConnection<Client>, Status> {
-        self.pool.get().map_err(|e| {
-            eprintln!("Failed to get Redis connection: {:?}", e);
+    /// Every Redis pool the service uses, see `Tenants::pools`.
+    pub fn pools(&self) -> Vec<Arc<RedisPool>> {
+        self.tenants.pools()
+    }
+
+    pub fn new(pool: &Pool, config: &Config) -> Self {
+        let tenants = Arc::new(Tenants::new(pool, config));
+        let compressor = Arc::new(Compressor::new(&config.compression));
+        let encryption = Arc::new(Encryption::new(&config.encryption));
+        encryption.clone().spawn_reencryption(
+            tenants.pools(),
+            Duration::from_millis(config.encryption.reencrypt_interval_ms),
+            config.encryption.reencrypt_batch,
+        );
+        tenants
+            .clone()
+            .spawn_quota_refresh(Duration::from_millis(config.quotas.refresh_ms));
+        if let Some(replicas) = tenants.replicas() {
+            replicas.spawn_lag_check(Duration::from_millis(config.replicas.check_ms));
+        }
+        let l1 = L1::from_config(&config.l1, tenants.pools().len()).map(Arc::new);
+        if let Some(l1) = &l1 {
+            l1.clone().spawn_invalidation(tenants.pools());
+        }
+
+        Self {
+            tenants,
+            reads: SingleFlight::new(),
+            recompute_lock: RecomputeLock::from_config(&config.recompute_lock),
+            loader: Loader::from_config(&config.loader, compressor.clone(), encryption.clone())
+                .map(Arc::new),
+            redis_json: config.json.redis_json,
+            compressor,
+            encryption,
+            l1,
+        }
+    }
+
+    fn get_connection(&self, pool: &RedisPool) -> Result<PooledConnection<Connector>, Status> {
+        let start = Instant::now();
+        let con = pool.get().map_err(|e| {
+            error!(error = %e, "Failed to get Redis connection");
             Status::internal("Failed to connect to Redis DB")
-        })
+        });
+        metrics::observe_pool_wait(start);
+
+        con
+    }
+
+    /// Whether the tenant's documents go to RedisJSON: as configured, or else as detected on the
+    /// tenant's first document call.
+    fn redis_json(&self, tenant: &Tenant) -> Result<bool, Status> {
+        if let Some(redis_json) = self.redis_json.or(tenant.redis_json.get().copied()) {
+            return Ok(redis_json);
+        }
+        let detected =
+            self.run_redis_cmd(tenant, "MODULE", 0, |mut con| documents::detect(&mut *con))?;
+
+        Ok(*tenant.redis_json.get_or_init(|| detected))
+    }
+
+    /// Where the tenant's documents are kept. Documents are refused while values are encrypted
+    /// and RedisJSON is in use, as the module cannot see into sealed values.
+    fn document_store<'a>(
+        &self,
+        tenant: &Tenant,
+        codec: &'a DocumentCodec,
+    ) -> Result<Store<'a>, Status> {
+        match (self.redis_json(tenant)?, self.encryption.is_enabled()) {
+            (false, _) => Ok(Store::Native(codec)),
+            (true, false) => Ok(Store::Module),
+            (true, true) => Err(Status::failed_precondition(
+                "JSON documents are unavailable in RedisJSON while values are encrypted",
+            )),
+        }
+    }
+
+    /// The Redis key a tenant's key is stored under, hashed when keys are. It must not reach into
+    /// keys the tenant may not use, and on a cluster it must leave room for mrCache's bookkeeping
+    /// keys in its slot.
+    fn redis_key(&self, tenant: &Tenant, key: &str) -> Result<String, Status> {
+        let key = tenant.key(&self.encryption.hash_key(key));
+        tenant.check_key(&key)?;
+        match slots::can_colocate(&key) {
+            true => Ok(key),
+            false => Err(Status::invalid_argument(
+                "Keys must not be empty or have a '}' outside of a hash tag on Redis Cluster",
+            )),
+        }
+    }
+
+    /// Compresses and seals a value on its way to Redis, into `field` for a hash. `name` is the
+    /// key as the tenant knows it.
+    fn encode_value(
+        &self,
+        name: &str,
+        redis_key: &str,
+        field: Option<&[u8]>,
+        value: Vec<u8>,
+    ) -> Vec<u8> {
+        self.encryption
+            .seal(redis_key, field, self.compressor.compress(name, value))
+    }
+
+    /// Drops keys just written through this instance from the L1 cache, rather than waiting for
+    /// Redis to report them changed.
+    fn evict<K: AsRef<str>>(&self, tenant: &Tenant, keys: &[K]) {
+        if let Some(l1) = &self.l1 {
+            l1.invalidate(tenant.pool_id, keys);
+        }
+    }
+
+    fn decode_value(
+        &self,
+        redis_key: &str,
+        field: Option<&[u8]>,
+        value: Vec<u8>,
+    ) -> Result<Vec<u8>, Status> {
+        self.encryption
+            .open(redis_key, field, value)
+            .map(|value| self.compressor.decompress(value))
+    }
+
+    /// Decodes values read from the given keys, one key per value.
+    fn decode_all(
+        &self,
+        keys: &[&str],
+        values: Vec<Option<Vec<u8>>>,
+    ) -> Result<Vec<Option<Vec<u8>>>, Status> {
+        keys.iter()
+            .zip(values)
+            .map(|(key, value)| {
+                value
+                    .map(|value| self.decode_value(key, None, value))
+                    .transpose()
+            })
+            .collect()
+    }
+
+    fn run_redis_cmd<T, F>(
+        &self,
+        tenant: &Tenant,
+        cmd: &str,
+        keys: usize,
+        redis_cmd: F,
+    ) -> Result<T, Status>
+    where
+        F: FnOnce(PooledConnection<Connector>) -> RedisResult<T>,
+    {
+        let _span = info_span!("redis", command = cmd, keys).entered();
+        let con = self.get_connection(&tenant.pool)?;
+        self.run_on(con, cmd, redis_cmd)
+    }
+
+    /// Runs a read on `replica` when given one, or on the primary when there is none or no
+    /// connection to it can be had. A replica that fails is left out until its next check.
+    fn run_read_cmd<T, F>(
+        &self,
+        tenant: &Tenant,
+        replica: Option<&Replica>,
+        cmd: &str,
+        keys: usize,
+        redis_cmd: F,
+    ) -> Result<T, Status>
+    where
+        F: FnOnce(PooledConnection<Connector>) -> RedisResult<T>,
+    {
+        let Some(replica) = replica else {
+            return self.run_redis_cmd(tenant, cmd, keys, redis_cmd);
+        };
+
+        let _span = info_span!("redis", command = cmd, keys, replica = replica.name()).entered();
+        let con = match self.get_connection(replica.pool()) {
+            Ok(con) => con,
+            Err(_) => {
+                replica.exclude();
+                self.get_connection(&tenant.pool)?
+            }
+        };
+        let results = self.run_on(con, cmd, redis_cmd);
+        if results.is_err() {
+            replica.exclude();
+        }
+        results
+    }
+
+    /// `run_read_cmd` for reads only `replica` can serve, like the pages of a scan on it, which
+    /// fail with `Aborted` instead of going to the primary when it cannot be reached.
+    fn run_replica_cmd<T, F>(
+        &self,
+        replica: &Replica,
+        cmd: &str,
+        keys: usize,
+        redis_cmd: F,
+    ) -> Result<T, Status>
+    where
+        F: FnOnce(PooledConnection<Connector>) -> RedisResult<T>,
+    {
+        let _span = info_span!("redis", command = cmd, keys, replica = replica.name()).entered();
+        let con = self.get_connection(replica.pool()).map_err(|_| {
+            replica.exclude();
+            Status::aborted("Replica the scan was on cannot be reached, restart the scan")
+        })?;
+        let results = self.run_on(con, cmd, redis_cmd);
+        if results.is_err() {
+            replica.exclude();
+        }
+        results
+    }
+
+    fn run_on<T, F>(
+        &self,
+        con: PooledConnection<Connector>,
+        cmd: &str,
+        redis_cmd: F,
+    ) -> Result<T, Status>
+    where
+        F: FnOnce(PooledConnection<Connector>) -> RedisResult<T>,
+    {
+        let start = Instant::now();
+        let results = redis_cmd(con);
+        metrics::observe_redis(cmd, start, results.is_ok());
+
+        match results {
+            Ok(results) => Ok(results),
+            Err(e) => Err({
+                error!(command = cmd, error = %e, "Redis command failed");
+                Status::internal("Failed to use ".to_string() + cmd + " from Redis DB")
+            }),
+        }
     }
 
     async fn execute_redis_cmd<T, F, G, R>(
         &self,
+        tenant: &Tenant,
+        cmd: &str,
+        keys: usize,
+        redis_cmd: F,
+        transform: G,
+    ) -> Result<Response<R>, Status>
+    where
+        F: FnOnce(PooledConnection<Connector>) -> RedisResult<T>,
+        G: FnOnce(T) -> R,
+    {
+        let results = self.run_redis_cmd(tenant, cmd, keys, redis_cmd)?;
+
+        Ok(Response::new(transform(results)))
+    }
+
+    async fn execute_read_cmd<T, F, G, R>(
+        &self,
+        tenant: &Tenant,
+        replica: Option<&Replica>,
         cmd: &str,
+        keys: usize,
         redis_cmd: F,
         transform: G,
     ) -> Result<Response<R>, Status>
     where
-        F: FnOnce(PooledConnection<Client>) -> RedisResult<T>,
+        F: FnOnce(PooledConnection<Connector>) -> RedisResult<T>,
         G: FnOnce(T) -> R,
     {
-        let start = std::time::Instant::now();
+        let results = self.run_read_cmd(tenant, replica, cmd, keys, redis_cmd)?;
 
-        match redis_cmd(self.get_connection()?) {
-            Ok(results) => {
-                let transformed = transform(results);
-                println!("Redis {} - Time elapsed: {:?}", cmd, start.elapsed());
+        Ok(Response::new(transform(results)))
+    }
 
-                Ok(Response::new(transformed))
+    /// Runs `read` once for all concurrent requests of the same command, consistency and
+    /// arguments on the tenant's pool. A read that took the recompute lock is not shared: the
+    /// other requests run their own, which waits on the lock for the recomputed value.
+    async fn execute_coalesced_read<F, G, R>(
+        &self,
+        tenant: &Tenant,
+        cmd: &str,
+        consistency: Consistency,
+        args: &[&str],
+        read: F,
+        transform: G,
+    ) -> Result<Response<R>, Status>
+    where
+        F: Future<Output = ReadResult>,
+        G: FnOnce(Read) -> R,
+    {
+        // Every part is prefixed with its length, so no arguments can pass for others.
+        let mut flight_key = tenant.pool_id.to_string();
+        for part in [cmd, consistency.as_str()].iter().chain(args) {
+            let _ = write!(flight_key, ":{}:{part}", part.len());
+        }
+        let results = self
+            .reads
+            .run(flight_key, read, |result| {
+                !matches!(result, Ok(Read { locked: true, .. }))
+            })
+            .await?;
+
+        Ok(Response::new(transform(results)))
+    }
+
+    /// Runs a hash read returning fields with their values, and decodes the values, attaching
+    /// the hash's metadata to each. `names` puts each field's name before its value, the way
+    /// `HGETALL` replies.
+    fn read_hash<F>(
+        &self,
+        tenant: &Tenant,
+        consistency: Consistency,
+        cmd: &str,
+        key: &str,
+        names: bool,
+        redis_cmd: F,
+    ) -> ReadResult
+    where
+        F: FnOnce(&mut PooledConnection<Connector>) -> RedisResult<Vec<(Vec<u8>, Option<Vec<u8>>)>>,
+    {
+        let replica = tenant.replica(consistency);
+        let (fields, meta) = self.run_read_cmd(tenant, replica, cmd, 1, |mut con| {
+            let fields = redis_cmd(&mut con)?;
+            let meta = Meta::read(&mut *con, &[key])?.pop().flatten();
+            Ok((fields, meta))
+        })?;
+
+        let mut values = Vec::with_capacity(fields.len() * (1 + names as usize));
+        let mut metas = Vec::with_capacity(values.capacity());
+        for (field, value) in fields {
+            let value = value.map(|value| self.decode_value(key, Some(&field), value));
+            if names {
+                values.push(Some(field));
+                // Field names are not values of the hash's content type.
+                metas.push(meta.map(|m| m.with_content_type(ContentType::Unspecified)));
             }
-            Err(e) => Err({
-                eprintln!("Failed Redis command {}: {:?}", cmd, e);
-                Status::internal("Failed to use ".to_string() + cmd + " from Redis DB")
-            }),
+            values.push(value.transpose()?);
+            metas.push(meta);
         }
+        Ok(Read {
+            meta: metas,
+            values,
+            locked: false,
+        })
     }
+
+    /// Reads string keys with their metadata, from the L1 cache where it has them and from Redis
+    /// otherwise, handing any stale ones to the loader. `names` are the keys as the tenant knows
+    /// them. Values read from a replica are not cached, as it may not have seen writes the cache
+    /// was already told about.
+    async fn read_strings(
+        &self,
+        tenant: &Arc<Tenant>,
+        consistency: Consistency,
+        keys: &[&str],
+        names: &[&str],
+    ) -> ReadResult {
+        let l1 = |name: &str| self.l1.as_ref().filter(|l1| l1.caches(name));
+        let epoch = self.l1.as_ref().map(|l1| l1.epoch());
+        let mut read = Read {
+            values: vec![None; keys.len()],
+            meta: vec![None; keys.len()],
+            locked: false,
+        };
+
+        let mut missed = Vec::new();
+        for (i, (key, name)) in keys.iter().zip(names).enumerate() {
+            match l1(name).and_then(|l1| l1.get(tenant.pool_id, key)) {
+                Some(entry) => {
+                    read.values[i] = Some(entry.value);
+                    read.meta[i] = entry.meta;
+                }
+                None => missed.push(i),
+            }
+        }
+
+        if !missed.is_empty() {
+            let replica = tenant.replica(consistency);
+            let missed_keys: Vec<&str> = missed.iter().map(|&i| keys[i]).collect();
+            let (results, locked) = self.get_values(tenant, replica, &missed_keys).await?;
+            read.locked = locked;
+            let (values, meta): (Vec<_>, Vec<_>) = results.into_iter().unzip();
+            let values = self.decode_all(&missed_keys, values)?;
+            let epoch = epoch.filter(|_| replica.is_none());
+
+            for ((&i, value), meta) in missed.iter().zip(values).zip(meta) {
+                if let (Some(l1), Some(value), Some(epoch)) = (l1(names[i]), &value, epoch) {
+                    let entry = l1::Entry {
+                        value: value.clone(),
+                        meta,
+                    };
+                    l1.insert(tenant.pool_id, keys[i], entry, epoch);
+                }
+                read.values[i] = value;
+                read.meta[i] = meta;
+            }
+        }
+
+        if self.l1.is_some() {
+            let l2_hits = missed.iter().filter(|&&i| read.values[i].is_some()).count();
+            metrics::record_tier_hits(keys.len() - missed.len(), l2_hits);
+        }
+
+        if let Some(loader) = &self.loader {
+            for (((key, name), value), meta) in
+                keys.iter().zip(names).zip(&read.values).zip(&read.meta)
+            {
+                if let (Some(_), Some(meta)) = (value, meta) {
+                    if meta.is_stale() {
+                        tokio::spawn(loader.clone().refresh(
+                            tenant.clone(),
+                            key.to_string(),
+                            name.to_string(),
+                            *meta,
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// `MGET` with the keys' metadata that, with the recompute lock enabled, waits for keys
+    /// another caller is refreshing, and tells whether it took the lock on any missing key
+    /// itself. Only the first read goes to `replica`, the lock and the waiting are on the primary.
+    async fn get_values(
+        &self,
+        tenant: &Tenant,
+        replica: Option<&Replica>,
+        keys: &[&str],
+    ) -> Result<(Vec<Stored>, bool), Status> {
+        let mut results = self.run_read_cmd(tenant, replica, "GET", keys.len(), |mut con| {
+            Meta::read_values(&mut *con, keys)
+        })?;
+
+        let lock = match &self.recompute_lock {
+            Some(lock) => lock,
+            None => return Ok((results, false)),
+        };
+
+        let missing: Vec<usize> = (0..keys.len())
+            .filter(|&i| results[i].0.is_none())
+            .collect();
+        if missing.is_empty() {
+            return Ok((results, false));
+        }
+
+        let missing_keys: Vec<&str> = missing.iter().map(|&i| keys[i]).collect();
+        let acquired = self.run_redis_cmd(tenant, "LOCK", missing_keys.len(), |mut con| {
+            lock.try_acquire(&mut *con, &missing_keys)
+        })?;
+        let locked = acquired.iter().any(|&acquired| acquired);
+        let mut waiting: Vec<usize> = missing
+            .into_iter()
+            .zip(acquired)
+            .filter(|(_, acquired)| !acquired)
+            .map(|(i, _)| i)
+            .collect();
+
+        let deadline = Instant::now() + lock.wait;
+        while !waiting.is_empty() && Instant::now() < deadline {
+            tokio::time::sleep(lock.poll).await;
+
+            let waiting_keys: Vec<&str> = waiting.iter().map(|&i| keys[i]).collect();
+            let refreshed = self.run_redis_cmd(tenant, "GET", waiting_keys.len(), |mut con| {
+                Meta::read_values(&mut *con, &waiting_keys)
+            })?;
+            for (&i, result) in waiting.iter().zip(refreshed) {
+                results[i] = result;
+            }
+            waiting.retain(|&i| results[i].0.is_none());
+        }
+
+        Ok((results, locked))
+    }
+}
+
+/// Stores a tenant's native document like the value of a string key.
+struct DocumentCodec<'a> {
+    service: &'a MrCacheService,
+    name: &'a str,
+    redis_key: &'a str,
+}
+
+impl<'a> DocumentCodec<'a> {
+    fn new(service: &'a MrCacheService, name: &'a str, redis_key: &'a str) -> Self {
+        Self {
+            service,
+            name,
+            redis_key,
+        }
+    }
+}
+
+impl Codec for DocumentCodec<'_> {
+    fn encode(&self, json: Vec<u8>) -> Vec<u8> {
+        self.service
+            .encode_value(self.name, self.redis_key, None, json)
+    }
+
+    fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, Status> {
+        self.service.decode_value(self.redis_key, None, stored)
+    }
+}
+
+fn strings_to_bytes(values: Vec<Option<String>>) -> Vec<Option<Vec<u8>>> {
+    values
+        .into_iter()
+        .map(|value| value.map(String::into_bytes))
+        .collect()
 }
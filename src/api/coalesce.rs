@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+type Calls<T> = Mutex<HashMap<String, watch::Receiver<Option<T>>>>;
+
+/// Coalesces concurrent calls for the same key so only the first caller (the leader) does the
+/// work and everyone who arrives while it is in flight receives a clone of its result.
+pub struct SingleFlight<T> {
+    calls: Calls<T>,
+}
+
+enum Role<T> {
+    Leader(watch::Sender<Option<T>>),
+    Follower(watch::Receiver<Option<T>>),
+}
+
+/// Removes the in-flight entry once the leader finishes, or if its future is dropped early.
+struct Flight<'a, T> {
+    calls: &'a Calls<T>,
+    key: &'a str,
+}
+
+impl<T> Drop for Flight<'_, T> {
+    fn drop(&mut self) {
+        self.calls.lock().unwrap().remove(self.key);
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub fn new() -> Self {
+        Self {
+            calls: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Awaits `call` if nobody else is running `key`, otherwise waits for their result. Futures
+    /// are lazy, so a follower's `call` is never polled unless the leader is cancelled or its
+    /// result is not `shareable`, like a miss the leader's caller alone is meant to fill.
+    pub async fn run<F, S>(&self, key: String, call: F, shareable: S) -> T
+    where
+        F: Future<Output = T>,
+        S: FnOnce(&T) -> bool,
+    {
+        let role = {
+            let mut calls = self.calls.lock().unwrap();
+            match calls.get(&key) {
+                Some(receiver) => Role::Follower(receiver.clone()),
+                None => {
+                    let (sender, receiver) = watch::channel(None);
+                    calls.insert(key.clone(), receiver);
+                    Role::Leader(sender)
+                }
+            }
+        };
+
+        match role {
+            Role::Leader(sender) => {
+                let flight = Flight {
+                    calls: &self.calls,
+                    key: &key,
+                };
+                let result = call.await;
+                drop(flight);
+                sender.send_replace(Some(result.clone()));
+
+                result
+            }
+            Role::Follower(mut receiver) => {
+                if let Ok(result) = receiver.wait_for(Option::is_some).await {
+                    if let Some(result) = result.as_ref().filter(|result| shareable(result)) {
+                        return result.clone();
+                    }
+                }
+
+                // The leader was cancelled before finishing or kept its result to itself, so make
+                // the call ourselves.
+                call.await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::sync::Notify;
+
+    /// A call that counts how often it runs and, when given `gate`, holds until it is notified.
+    async fn counted(calls: &AtomicUsize, gate: Option<&Notify>, result: u32) -> u32 {
+        calls.fetch_add(1, Ordering::SeqCst);
+        if let Some(gate) = gate {
+            gate.notified().await;
+        }
+        result
+    }
+
+    #[tokio::test]
+    async fn followers_share_the_leaders_result() {
+        let flights = SingleFlight::new();
+        let (calls, gate) = (AtomicUsize::new(0), Notify::new());
+
+        let leader = flights.run("k".to_string(), counted(&calls, Some(&gate), 1), |_| true);
+        let follower = flights.run("k".to_string(), counted(&calls, None, 2), |_| true);
+        let release = async {
+            tokio::task::yield_now().await;
+            gate.notify_one();
+        };
+        let (leader, follower, _) = tokio::join!(leader, follower, release);
+
+        assert_eq!((leader, follower), (1, 1));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn followers_make_their_own_call_for_results_not_shared() {
+        let flights = SingleFlight::new();
+        let (calls, gate) = (AtomicUsize::new(0), Notify::new());
+
+        let leader = flights.run("k".to_string(), counted(&calls, Some(&gate), 1), |_| false);
+        let follower = flights.run("k".to_string(), counted(&calls, None, 2), |_| false);
+        let release = async {
+            tokio::task::yield_now().await;
+            gate.notify_one();
+        };
+        let (leader, follower, _) = tokio::join!(leader, follower, release);
+
+        assert_eq!((leader, follower), (1, 2));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn followers_make_their_own_call_when_the_leader_is_cancelled() {
+        let flights = Arc::new(SingleFlight::new());
+        let (calls, gate) = (Arc::new(AtomicUsize::new(0)), Arc::new(Notify::new()));
+
+        let leader = {
+            let (flights, calls, gate) = (flights.clone(), calls.clone(), gate.clone());
+            tokio::spawn(async move {
+                flights
+                    .run("k".to_string(), counted(&calls, Some(&gate), 1), |_| true)
+                    .await
+            })
+        };
+        while calls.load(Ordering::SeqCst) == 0 {
+            tokio::task::yield_now().await;
+        }
+        let follower = {
+            let (flights, calls) = (flights.clone(), calls.clone());
+            tokio::spawn(async move {
+                flights
+                    .run("k".to_string(), counted(&calls, None, 2), |_| true)
+                    .await
+            })
+        };
+        tokio::task::yield_now().await;
+        leader.abort();
+
+        assert_eq!(follower.await.unwrap(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn calls_for_other_keys_are_not_coalesced() {
+        let flights = SingleFlight::new();
+        let calls = AtomicUsize::new(0);
+
+        let (a, b) = tokio::join!(
+            flights.run("a".to_string(), counted(&calls, None, 1), |_| true),
+            flights.run("b".to_string(), counted(&calls, None, 2), |_| true),
+        );
+
+        assert_eq!((a, b), (1, 2));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
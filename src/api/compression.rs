@@ -0,0 +1,282 @@
+use std::io::{self, Read};
+use tracing::warn;
+
+use crate::api::metrics;
+use crate::config::{Codec, CompressionConfig};
+
+/// Starts every value mrCache compressed. 0xFF never starts UTF-8 text, so only binary values can
+/// begin the same way, and those are marked as stored uncompressed to keep them apart, along with
+/// any other value that starts like mrCache's own headers do.
+const MAGIC: [u8; 4] = [0xff, b'M', b'R', b'Z'];
+
+/// How every header mrCache puts in front of a value starts.
+pub const HEADER_PREFIX: [u8; 3] = [0xff, b'M', b'R'];
+
+/// The magic followed by one byte naming the codec.
+const HEADER_LEN: usize = MAGIC.len() + 1;
+
+const STORED: u8 = 0;
+
+impl Codec {
+    fn name(self) -> &'static str {
+        match self {
+            Codec::None => "none",
+            Codec::Zstd => "zstd",
+            Codec::Lz4 => "lz4",
+            Codec::Snappy => "snappy",
+        }
+    }
+
+    fn id(self) -> u8 {
+        match self {
+            Codec::None => STORED,
+            Codec::Zstd => 1,
+            Codec::Lz4 => 2,
+            Codec::Snappy => 3,
+        }
+    }
+}
+
+/// Picks a codec for each value written and compresses it, see `CompressionConfig`.
+pub struct Compressor {
+    codec: Codec,
+    min_bytes: usize,
+    zstd_level: i32,
+    max_decompressed_bytes: usize,
+    /// Key prefixes with their codec, longest first.
+    namespaces: Vec<(String, Codec)>,
+}
+
+impl Compressor {
+    pub fn new(config: &CompressionConfig) -> Self {
+        let mut namespaces: Vec<(String, Codec)> = config
+            .namespaces
+            .iter()
+            .map(|(prefix, codec)| (prefix.clone(), *codec))
+            .collect();
+        namespaces.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+        Self {
+            codec: config.codec,
+            min_bytes: config.min_bytes,
+            zstd_level: config.zstd_level,
+            max_decompressed_bytes: config.max_decompressed_bytes,
+            namespaces,
+        }
+    }
+
+    fn codec(&self, key: &str) -> Codec {
+        self.namespaces
+            .iter()
+            .find(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .map_or(self.codec, |(_, codec)| *codec)
+    }
+
+    fn encode(&self, codec: Codec, value: &[u8]) -> io::Result<Vec<u8>> {
+        match codec {
+            Codec::None => Ok(value.to_vec()),
+            Codec::Zstd => zstd::bulk::compress(value, self.zstd_level),
+            Codec::Lz4 => Ok(lz4_flex::compress_prepend_size(value)),
+            Codec::Snappy => snap::raw::Encoder::new()
+                .compress_vec(value)
+                .map_err(io::Error::other),
+        }
+    }
+
+    /// Prepares a value for storing under `key`, the key as its tenant names it.
+    pub fn compress(&self, key: &str, value: Vec<u8>) -> Vec<u8> {
+        let codec = self.codec(key);
+        if codec != Codec::None && value.len() >= self.min_bytes {
+            match self.encode(codec, &value) {
+                Ok(compressed) if compressed.len() + HEADER_LEN < value.len() => {
+                    metrics::observe_compression(
+                        codec.name(),
+                        value.len(),
+                        compressed.len() + HEADER_LEN,
+                    );
+                    return with_header(codec, &compressed);
+                }
+                Ok(_) => metrics::observe_compression(codec.name(), value.len(), value.len()),
+                Err(e) => warn!(key, codec = codec.name(), error = %e, "Failed to compress"),
+            }
+        }
+
+        match value.starts_with(&HEADER_PREFIX) {
+            true => with_header(Codec::None, &value),
+            false => value,
+        }
+    }
+
+    /// Undoes `compress`. Values without a header, like those written before compression was
+    /// turned on or by other Redis clients, come back as they are.
+    pub fn decompress(&self, value: Vec<u8>) -> Vec<u8> {
+        if value.len() < HEADER_LEN || !value.starts_with(&MAGIC) {
+            return value;
+        }
+
+        let body = &value[HEADER_LEN..];
+        let decoded = match value[MAGIC.len()] {
+            STORED => Ok(body.to_vec()),
+            1 => self.decode_zstd(body),
+            2 => self
+                .check_len(lz4_len(body))
+                .and_then(|()| lz4_flex::decompress_size_prepended(body).map_err(io::Error::other)),
+            3 => self
+                .check_len(snap::raw::decompress_len(body).map_err(io::Error::other))
+                .and_then(|()| {
+                    snap::raw::Decoder::new()
+                        .decompress_vec(body)
+                        .map_err(io::Error::other)
+                }),
+            _ => return value,
+        };
+
+        match decoded {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                warn!(error = %e, "Failed to decompress value, returning it as stored");
+                value
+            }
+        }
+    }
+
+    /// Checks the decompressed length a value claims before any of it is decompressed.
+    fn check_len(&self, len: io::Result<usize>) -> io::Result<()> {
+        match len? {
+            len if len > self.max_decompressed_bytes => Err(self.too_large()),
+            _ => Ok(()),
+        }
+    }
+
+    fn too_large(&self) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "value decompresses to over {} bytes",
+                self.max_decompressed_bytes
+            ),
+        )
+    }
+
+    /// zstd frames need not say how long they decompress to, so the output is cut off instead.
+    fn decode_zstd(&self, body: &[u8]) -> io::Result<Vec<u8>> {
+        let limit = self.max_decompressed_bytes as u64 + 1;
+        let mut decoded = Vec::new();
+        zstd::stream::read::Decoder::new(body)?
+            .take(limit)
+            .read_to_end(&mut decoded)?;
+        match decoded.len() {
+            len if len > self.max_decompressed_bytes => Err(self.too_large()),
+            _ => Ok(decoded),
+        }
+    }
+}
+
+/// The little-endian length `lz4_flex::compress_prepend_size` puts in front of the block.
+fn lz4_len(body: &[u8]) -> io::Result<usize> {
+    let len: [u8; 4] = body
+        .get(..4)
+        .and_then(|len| len.try_into().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing LZ4 length"))?;
+    Ok(u32::from_le_bytes(len) as usize)
+}
+
+fn with_header(codec: Codec, body: &[u8]) -> Vec<u8> {
+    let mut value = Vec::with_capacity(HEADER_LEN + body.len());
+    value.extend_from_slice(&MAGIC);
+    value.push(codec.id());
+    value.extend_from_slice(body);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn compressor(codec: Codec) -> Compressor {
+        Compressor::new(&CompressionConfig {
+            codec,
+            min_bytes: 16,
+            ..CompressionConfig::default()
+        })
+    }
+
+    #[test]
+    fn round_trips_every_codec() {
+        let value = b"mrCache ".repeat(64);
+        for codec in [Codec::Zstd, Codec::Lz4, Codec::Snappy] {
+            let compressed = compressor(codec).compress("key", value.clone());
+            assert!(compressed.starts_with(&MAGIC) && compressed.len() < value.len());
+            assert_eq!(compressor(codec).decompress(compressed), value);
+        }
+    }
+
+    #[test]
+    fn leaves_small_and_incompressible_values_alone() {
+        let compressor = compressor(Codec::Zstd);
+        assert_eq!(compressor.compress("key", b"short".to_vec()), b"short");
+        let noise: Vec<u8> = (0..64u32).map(|i| (i * 7919 % 251) as u8).collect();
+        assert_eq!(
+            compressor.decompress(compressor.compress("key", noise.clone())),
+            noise
+        );
+    }
+
+    #[test]
+    fn keeps_values_that_look_like_headers_apart() {
+        let compressor = compressor(Codec::None);
+        for value in [
+            [MAGIC.as_slice(), &[1], b"not zstd"].concat(),
+            [MAGIC.as_slice(), &[STORED]].concat(),
+            [HEADER_PREFIX.as_slice(), b"E"].concat(),
+            HEADER_PREFIX.to_vec(),
+        ] {
+            let stored = compressor.compress("key", value.clone());
+            assert_ne!(stored, value);
+            assert_eq!(compressor.decompress(stored), value);
+        }
+    }
+
+    #[test]
+    fn passes_values_without_a_header_through() {
+        let compressor = compressor(Codec::Zstd);
+        assert_eq!(compressor.decompress(b"plain".to_vec()), b"plain");
+        assert_eq!(compressor.decompress(vec![0xff, b'M']), vec![0xff, b'M']);
+    }
+
+    #[test]
+    fn refuses_values_that_decompress_past_the_maximum() {
+        let value = vec![0; 4096];
+        let bounded = Compressor::new(&CompressionConfig {
+            min_bytes: 16,
+            max_decompressed_bytes: 4095,
+            ..CompressionConfig::default()
+        });
+        for codec in [Codec::Zstd, Codec::Lz4, Codec::Snappy] {
+            let compressed = compressor(codec).compress("key", value.clone());
+            assert_eq!(bounded.decompress(compressed.clone()), compressed);
+        }
+
+        // An LZ4 block claiming to be larger than anything allowed is refused before decoding.
+        let mut lying = lz4_flex::compress_prepend_size(&value);
+        lying[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let stored = with_header(Codec::Lz4, &lying);
+        assert_eq!(bounded.decompress(stored.clone()), stored);
+    }
+
+    #[test]
+    fn picks_the_longest_matching_namespace() {
+        let compressor = Compressor::new(&CompressionConfig {
+            codec: Codec::Zstd,
+            namespaces: HashMap::from([
+                ("a:".to_string(), Codec::Lz4),
+                ("a:b:".to_string(), Codec::None),
+            ]),
+            ..CompressionConfig::default()
+        });
+        assert_eq!(compressor.codec("a:b:c"), Codec::None);
+        assert_eq!(compressor.codec("a:c"), Codec::Lz4);
+        assert_eq!(compressor.codec("c"), Codec::Zstd);
+    }
+}
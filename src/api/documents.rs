@@ -0,0 +1,894 @@
+use jsonpath_rust::parser::model::{JpQuery, Segment, Selector};
+use jsonpath_rust::parser::parse_json_path;
+use jsonpath_rust::query::js_path_process;
+use redis::{Commands, ConnectionLike, ErrorKind, Pipeline, RedisResult, Script};
+use serde_json::{Map, Number, Value as JsonValue};
+use tonic::Status;
+use tracing::warn;
+
+use crate::api::items;
+use crate::api::meta::{now_ms, Meta};
+use crate::api::mr_cache::{ContentType, JsonCondition};
+use crate::api::tags;
+
+/// Runs a RedisJSON command on KEYS[1] only when the key exists, so a missing key can be told
+/// apart from the command's own nil. ARGV[1] is the command and the rest its arguments after the
+/// key. Returns nil when the key is missing and the command's reply wrapped in an array otherwise.
+const IF_EXISTS_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+  return false
+end
+return {redis.call(ARGV[1], KEYS[1], unpack(ARGV, 2))}
+";
+
+/// How documents kept as JSON strings are stored, compressed and sealed on their way into Redis
+/// and opened on the way out like any other value.
+pub trait Codec {
+    fn encode(&self, json: Vec<u8>) -> Vec<u8>;
+    fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, Status>;
+}
+
+/// Where documents are kept: in the RedisJSON module, or as JSON strings edited by mrCache.
+#[derive(Clone, Copy)]
+pub enum Store<'a> {
+    Module,
+    Native(&'a dyn Codec),
+}
+
+/// Parses a JSON value from a request.
+pub fn parse_json(json: &str) -> Result<JsonValue, Status> {
+    serde_json::from_str(json)
+        .map_err(|err| Status::invalid_argument(format!("Invalid JSON value: {err}")))
+}
+
+/// Parses the number a `JsonNumIncrBy` adds.
+pub fn parse_number(json: &str) -> Result<Number, Status> {
+    match parse_json(json)? {
+        JsonValue::Number(number) => Ok(number),
+        _ => Err(Status::invalid_argument("Increment is not a JSON number")),
+    }
+}
+
+/// Where a node sits in a document, one object member or array element at a time.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Step {
+    Name(String),
+    Index(usize),
+}
+
+/// An RFC 9535 JSONPath, which RedisJSON and the native documents both understand: names,
+/// indexes counting from the end when negative, slices, wildcards, filters and recursive descent.
+#[derive(Debug)]
+pub struct Path {
+    text: String,
+    query: JpQuery,
+}
+
+impl Path {
+    /// Parses a path, taking an empty one as the root.
+    pub fn parse(text: &str) -> Result<Self, Status> {
+        let text = if text.is_empty() { "$" } else { text };
+        let query = parse_json_path(text)
+            .map_err(|err| Status::invalid_argument(format!("Invalid path {text:?}: {err}")))?;
+
+        Ok(Self {
+            text: text.to_string(),
+            query,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.query.segments.is_empty()
+    }
+
+    /// Where every node the path matches sits in `doc`, in document order.
+    fn resolve(&self, doc: &JsonValue) -> Vec<Vec<Step>> {
+        resolve_query(&self.query, doc)
+    }
+
+    /// Where a missing member would go: the nodes matching everything but the last segment, when
+    /// that segment names a single member.
+    fn parents(&self, doc: &JsonValue) -> Option<(Vec<Vec<Step>>, &str)> {
+        let (last, parent) = self.query.segments.split_last()?;
+        match last {
+            Segment::Selector(Selector::Name(name)) => {
+                let parent = JpQuery::new(parent.to_vec());
+                Some((resolve_query(&parent, doc), name))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn resolve_query(query: &JpQuery, doc: &JsonValue) -> Vec<Vec<Step>> {
+    js_path_process(query, doc)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|found| normalized_steps(&found.path))
+        .collect()
+}
+
+/// Reads the steps back out of a normalized path like `$['a'][0]`, the form matches are reported
+/// in.
+fn normalized_steps(path: &str) -> Option<Vec<Step>> {
+    let mut rest = path.strip_prefix('$')?;
+    let mut steps = Vec::new();
+    while let Some(after) = rest.strip_prefix('[') {
+        let (step, after) = match after.strip_prefix('\'') {
+            Some(quoted) => {
+                let (name, after) = normalized_name(quoted)?;
+                (Step::Name(name), after)
+            }
+            None => {
+                let end = after.find(']')?;
+                (Step::Index(after[..end].parse().ok()?), &after[end..])
+            }
+        };
+        rest = after.strip_prefix(']')?;
+        steps.push(step);
+    }
+    rest.is_empty().then_some(steps)
+}
+
+/// Unescapes a name up to its closing quote, returning it with what follows the quote.
+fn normalized_name(text: &str) -> Option<(String, &str)> {
+    let mut name = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\'' => return Some((name, &text[i + 1..])),
+            '\\' => name.push(match chars.next()?.1 {
+                'b' => '\u{8}',
+                'f' => '\u{c}',
+                'n' => '\n',
+                'r' => '\r',
+                't' => '\t',
+                'u' => {
+                    let hex: String = (0..4)
+                        .filter_map(|_| chars.next().map(|(_, c)| c))
+                        .collect();
+                    char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?
+                }
+                c => c,
+            }),
+            c => name.push(c),
+        }
+    }
+    None
+}
+
+fn lookup<'a>(doc: &'a JsonValue, steps: &[Step]) -> Option<&'a JsonValue> {
+    steps.iter().try_fold(doc, |node, step| match (node, step) {
+        (JsonValue::Object(map), Step::Name(name)) => map.get(name),
+        (JsonValue::Array(array), Step::Index(index)) => array.get(*index),
+        _ => None,
+    })
+}
+
+fn lookup_mut<'a>(doc: &'a mut JsonValue, steps: &[Step]) -> Option<&'a mut JsonValue> {
+    steps.iter().try_fold(doc, |node, step| match (node, step) {
+        (JsonValue::Object(map), Step::Name(name)) => map.get_mut(name),
+        (JsonValue::Array(array), Step::Index(index)) => array.get_mut(*index),
+        _ => None,
+    })
+}
+
+/// A change to a document, applied by RedisJSON or by `Edit::apply`.
+pub enum Edit {
+    Set(JsonValue, JsonCondition),
+    ArrAppend(Vec<JsonValue>),
+    NumIncrBy(Number),
+    Merge(JsonValue),
+}
+
+impl Edit {
+    /// Applies the edit to a document, or to `None` when the key is missing. Returns the document
+    /// to store, if it changed, and the reply: whether a set or merge took effect, or a JSON array
+    /// with an entry per match for the others.
+    fn apply(
+        &self,
+        doc: Option<JsonValue>,
+        path: &Path,
+    ) -> Result<(Option<JsonValue>, JsonValue), Status> {
+        let mut doc = match (doc, self) {
+            (Some(doc), _) => doc,
+            (None, Edit::ArrAppend(_) | Edit::NumIncrBy(_)) => return Ok((None, JsonValue::Null)),
+            (None, _) if !path.is_root() => {
+                return Err(Status::failed_precondition(
+                    "New documents must be set at the root",
+                ))
+            }
+            (None, Edit::Set(_, JsonCondition::Exists)) => return Ok((None, false.into())),
+            (None, Edit::Set(value, _)) => return Ok((Some(value.clone()), true.into())),
+            (None, Edit::Merge(patch)) => return Ok((Some(merged(patch)), true.into())),
+        };
+
+        let found = path.resolve(&doc);
+        let reply: JsonValue = match self {
+            Edit::Set(value, condition) => {
+                set_matches(&mut doc, path, found, value, *condition).into()
+            }
+            Edit::Merge(patch) if found.is_empty() => {
+                let value = merged(patch);
+                set_matches(&mut doc, path, found, &value, JsonCondition::Always).into()
+            }
+            Edit::Merge(patch) => {
+                for steps in &found {
+                    if let Some(node) = lookup_mut(&mut doc, steps) {
+                        merge_patch(node, patch);
+                    }
+                }
+                true.into()
+            }
+            Edit::ArrAppend(values) => found
+                .iter()
+                .map(|steps| match lookup_mut(&mut doc, steps) {
+                    Some(JsonValue::Array(array)) => {
+                        array.extend(values.iter().cloned());
+                        array.len().into()
+                    }
+                    _ => JsonValue::Null,
+                })
+                .collect(),
+            Edit::NumIncrBy(by) => {
+                let mut results = Vec::new();
+                for steps in &found {
+                    results.push(match lookup_mut(&mut doc, steps) {
+                        Some(JsonValue::Number(number)) => {
+                            *number = add(number, by)?;
+                            JsonValue::Number(number.clone())
+                        }
+                        _ => JsonValue::Null,
+                    });
+                }
+                results.into()
+            }
+        };
+
+        let changed = match &reply {
+            JsonValue::Bool(effect) => *effect,
+            JsonValue::Array(results) => results.iter().any(|result| !result.is_null()),
+            _ => false,
+        };
+        Ok((changed.then_some(doc), reply))
+    }
+
+    /// The RedisJSON command for the edit, after the key.
+    fn module_args(&self, path: &Path) -> Vec<String> {
+        let path = path.as_str().to_string();
+        match self {
+            Edit::Set(value, condition) => {
+                let mut args = vec!["JSON.SET".to_string(), path, value.to_string()];
+                match condition {
+                    JsonCondition::Always => {}
+                    JsonCondition::Missing => args.push("NX".to_string()),
+                    JsonCondition::Exists => args.push("XX".to_string()),
+                }
+                args
+            }
+            Edit::ArrAppend(values) => ["JSON.ARRAPPEND".to_string(), path]
+                .into_iter()
+                .chain(values.iter().map(JsonValue::to_string))
+                .collect(),
+            Edit::NumIncrBy(by) => vec!["JSON.NUMINCRBY".to_string(), path, by.to_string()],
+            Edit::Merge(patch) => vec!["JSON.MERGE".to_string(), path, patch.to_string()],
+        }
+    }
+}
+
+/// Replaces every match, or adds the member the path names when nothing matches yet.
+fn set_matches(
+    doc: &mut JsonValue,
+    path: &Path,
+    found: Vec<Vec<Step>>,
+    value: &JsonValue,
+    condition: JsonCondition,
+) -> bool {
+    if found.is_empty() {
+        if condition == JsonCondition::Exists {
+            return false;
+        }
+        let Some((parents, name)) = path.parents(doc) else {
+            return false;
+        };
+        let mut added = false;
+        for steps in parents {
+            if let Some(JsonValue::Object(map)) = lookup_mut(doc, &steps) {
+                map.insert(name.to_string(), value.clone());
+                added = true;
+            }
+        }
+        return added;
+    }
+    if condition == JsonCondition::Missing {
+        return false;
+    }
+    for steps in found {
+        if let Some(node) = lookup_mut(doc, &steps) {
+            *node = value.clone();
+        }
+    }
+    true
+}
+
+/// Removes every match below the root. Later array elements and deeper nodes go first, so the
+/// remaining matches still point where they did.
+fn delete_matches(doc: &mut JsonValue, path: &Path) -> u64 {
+    let mut found = path.resolve(doc);
+    found.sort_unstable_by(|a, b| b.cmp(a));
+    found.dedup();
+
+    let mut deleted = 0;
+    for steps in found {
+        let Some((last, parent)) = steps.split_last() else {
+            continue;
+        };
+        let removed = match (lookup_mut(doc, parent), last) {
+            (Some(JsonValue::Object(map)), Step::Name(name)) => map.shift_remove(name).is_some(),
+            (Some(JsonValue::Array(array)), Step::Index(index)) if *index < array.len() => {
+                array.remove(*index);
+                true
+            }
+            _ => false,
+        };
+        deleted += removed as u64;
+    }
+    deleted
+}
+
+/// RFC 7386 merge patch: objects merge member by member, null members are removed and anything
+/// else replaces the target.
+fn merge_patch(target: &mut JsonValue, patch: &JsonValue) {
+    let JsonValue::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = JsonValue::Object(Map::new());
+    }
+    if let JsonValue::Object(target) = target {
+        for (name, value) in patch {
+            if value.is_null() {
+                target.shift_remove(name);
+            } else {
+                merge_patch(target.entry(name.clone()).or_insert(JsonValue::Null), value);
+            }
+        }
+    }
+}
+
+/// What a patch merges into where there was nothing before.
+fn merged(patch: &JsonValue) -> JsonValue {
+    let mut value = JsonValue::Null;
+    merge_patch(&mut value, patch);
+    value
+}
+
+/// Integers stay integers while they fit in 64 bits, anything else becomes a float.
+fn add(number: &Number, by: &Number) -> Result<Number, Status> {
+    if let Some(sum) = number
+        .as_i64()
+        .zip(by.as_i64())
+        .and_then(|(a, b)| a.checked_add(b))
+    {
+        return Ok(sum.into());
+    }
+    let sum = number.as_f64().unwrap_or_default() + by.as_f64().unwrap_or_default();
+    Number::from_f64(sum)
+        .ok_or_else(|| Status::failed_precondition("Result is not a finite number"))
+}
+
+/// Whether the server has the RedisJSON module loaded. A server refusing `MODULE LIST`, e.g. for
+/// lack of ACL permissions, is taken not to have it.
+pub fn detect<C: ConnectionLike>(con: &mut C) -> RedisResult<bool> {
+    fn has_rejson(value: &redis::Value) -> bool {
+        match value {
+            redis::Value::Data(name) => name.eq_ignore_ascii_case(b"rejson"),
+            redis::Value::Bulk(values) => values.iter().any(has_rejson),
+            _ => false,
+        }
+    }
+
+    match redis::cmd("MODULE").arg("LIST").query(con) {
+        Ok(modules) => Ok(has_rejson(&modules)),
+        Err(err) if err.kind() == ErrorKind::ResponseError => {
+            warn!(error = %err, "Could not list Redis modules, keeping JSON documents as strings");
+            Ok(false)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Reads a document: whole without paths, as an array of matches for one path and as an object of
+/// those arrays keyed by path for several.
+pub fn get<C: ConnectionLike>(
+    con: &mut C,
+    store: Store,
+    key: &str,
+    paths: &[Path],
+) -> RedisResult<Result<Option<String>, Status>> {
+    let Store::Native(codec) = store else {
+        let mut cmd = redis::cmd("JSON.GET");
+        cmd.arg(key);
+        for path in paths {
+            cmd.arg(path.as_str());
+        }
+        return cmd.query(con).map(Ok);
+    };
+
+    let stored: Option<Vec<u8>> = con.get(key)?;
+    let doc = match stored.map(|stored| parse_document(codec, stored)) {
+        Some(Ok(doc)) => doc,
+        Some(Err(status)) => return Ok(Err(status)),
+        None => return Ok(Ok(None)),
+    };
+    let matches = |path: &Path| -> JsonValue {
+        path.resolve(&doc)
+            .iter()
+            .filter_map(|steps| lookup(&doc, steps).cloned())
+            .collect()
+    };
+    let json = match paths {
+        [] => doc.clone(),
+        [path] => matches(path),
+        paths => paths
+            .iter()
+            .map(|path| (path.as_str().to_string(), matches(path)))
+            .collect::<Map<String, JsonValue>>()
+            .into(),
+    };
+    Ok(Ok(Some(json.to_string())))
+}
+
+/// Sets a value at the path. Setting the root replaces the document along with its metadata and
+/// tags, deeper sets keep them.
+pub fn set<C: ConnectionLike>(
+    con: &mut C,
+    store: Store,
+    key: &str,
+    path: &Path,
+    edit: Edit,
+    meta: &Meta,
+    tags: &[String],
+) -> RedisResult<Result<bool, Status>> {
+    let meta = match store {
+        Store::Module => *meta,
+        Store::Native(_) => meta.with_content_type(ContentType::Json),
+    };
+    let rewrite = |pipe: &mut Pipeline| meta.write(pipe, key);
+
+    if let (Store::Module, true) = (store, path.is_root()) {
+        let args = edit.module_args(path);
+        let set: Option<String> = redis::cmd(&args[0]).arg(key).arg(&args[1..]).query(con)?;
+        if set.is_some() {
+            let mut pipe = redis::pipe();
+            rewrite(pipe.atomic());
+            pipe.query::<()>(con)?;
+            tags::write(con, key, tags, meta.hard_ttl_ms)?;
+        }
+        return Ok(Ok(set.is_some()));
+    }
+
+    let reply = match (store, path.is_root()) {
+        (Store::Module, _) => edit_module(con, key, path, &edit)?,
+        (Store::Native(codec), true) => edit_native(con, codec, key, path, &edit, Some(&rewrite))?,
+        (Store::Native(codec), false) => edit_native(con, codec, key, path, &edit, None)?,
+    };
+    let set = reply.map(|reply| reply == JsonValue::Bool(true));
+    if let (Ok(true), true) = (&set, path.is_root()) {
+        tags::write(con, key, tags, meta.hard_ttl_ms)?;
+    }
+    Ok(set)
+}
+
+/// Merges a patch at the path, creating the document when the path is the root.
+pub fn merge<C: ConnectionLike>(
+    con: &mut C,
+    store: Store,
+    key: &str,
+    path: &Path,
+    edit: Edit,
+) -> RedisResult<Result<bool, Status>> {
+    let reply = match (store, path.is_root()) {
+        (Store::Module, true) => {
+            let merged: Option<String> = redis::cmd("JSON.MERGE")
+                .arg(key)
+                .arg(&edit.module_args(path)[1..])
+                .query(con)?;
+            Ok(merged.is_some().into())
+        }
+        (Store::Module, false) => edit_module(con, key, path, &edit)?,
+        (Store::Native(codec), _) => edit_native(con, codec, key, path, &edit, None)?,
+    };
+    Ok(reply.map(|reply| reply == JsonValue::Bool(true)))
+}
+
+/// Appends to arrays or increments numbers, returning a JSON array with the new length or value
+/// for each match (null where the match has the wrong type), or `None` when the key is missing.
+pub fn update<C: ConnectionLike>(
+    con: &mut C,
+    store: Store,
+    key: &str,
+    path: &Path,
+    edit: Edit,
+) -> RedisResult<Result<Option<String>, Status>> {
+    let reply = match store {
+        Store::Module => edit_module(con, key, path, &edit)?,
+        Store::Native(codec) => edit_native(con, codec, key, path, &edit, None)?,
+    };
+    Ok(reply.map(|reply| (!reply.is_null()).then(|| reply.to_string())))
+}
+
+/// Deletes what the path matches, the whole key with its metadata and tags for the root.
+pub fn del<C: ConnectionLike>(
+    con: &mut C,
+    store: Store,
+    key: &str,
+    path: &Path,
+) -> RedisResult<Result<u64, Status>> {
+    if path.is_root() {
+        return items::delete(con, key).map(|deleted| Ok(deleted as u64));
+    }
+    let Store::Native(codec) = store else {
+        return redis::cmd("JSON.DEL")
+            .arg(key)
+            .arg(path.as_str())
+            .query(con)
+            .map(Ok);
+    };
+
+    let meta_key = Meta::key(key);
+    redis::transaction(con, &[key, meta_key.as_str()], |con, pipe| {
+        let stored: Option<Vec<u8>> = con.get(key)?;
+        let mut doc = match stored.map(|stored| parse_document(codec, stored)) {
+            Some(Ok(doc)) => doc,
+            Some(Err(status)) => return Ok(Some(Err(status))),
+            None => return Ok(Some(Ok(0))),
+        };
+        let deleted = delete_matches(&mut doc, path);
+        if deleted == 0 {
+            return Ok(Some(Ok(0)));
+        }
+
+        queue_update(con, pipe, codec, key, &meta_key, &doc)?;
+        let done: Option<()> = pipe.query(con)?;
+        Ok(done.map(|_| Ok(deleted)))
+    })
+}
+
+/// Runs the edit's RedisJSON command, replying null when the key is missing.
+fn edit_module<C: ConnectionLike>(
+    con: &mut C,
+    key: &str,
+    path: &Path,
+    edit: &Edit,
+) -> RedisResult<Result<JsonValue, Status>> {
+    let reply: Option<(redis::Value,)> = Script::new(IF_EXISTS_SCRIPT)
+        .key(key)
+        .arg(edit.module_args(path))
+        .invoke(con)?;
+
+    Ok(match (reply, edit) {
+        (None, Edit::ArrAppend(_) | Edit::NumIncrBy(_)) => Ok(JsonValue::Null),
+        (None, _) => Err(Status::failed_precondition(
+            "New documents must be set at the root",
+        )),
+        (Some((redis::Value::Nil,)), Edit::Set(..) | Edit::Merge(_)) => Ok(false.into()),
+        (Some(_), Edit::Set(..) | Edit::Merge(_)) => Ok(true.into()),
+        (Some((reply,)), _) => Ok(module_json(&reply)),
+    })
+}
+
+/// Converts a RedisJSON reply to JSON: bulk strings are JSON text already, arrays hold integers
+/// and nils.
+fn module_json(reply: &redis::Value) -> JsonValue {
+    match reply {
+        redis::Value::Int(int) => (*int).into(),
+        redis::Value::Data(json) => serde_json::from_slice(json).unwrap_or(JsonValue::Null),
+        redis::Value::Bulk(values) => values.iter().map(module_json).collect(),
+        _ => JsonValue::Null,
+    }
+}
+
+/// Applies the edit to the JSON string at `key` in a transaction, retried whenever the key changes
+/// before it commits. `rewrite` queues fresh metadata for the key, otherwise an existing key keeps
+/// its metadata with the write time moved on.
+fn edit_native<C: ConnectionLike>(
+    con: &mut C,
+    codec: &dyn Codec,
+    key: &str,
+    path: &Path,
+    edit: &Edit,
+    rewrite: Option<&dyn Fn(&mut Pipeline)>,
+) -> RedisResult<Result<JsonValue, Status>> {
+    let meta_key = Meta::key(key);
+    redis::transaction(con, &[key, meta_key.as_str()], |con, pipe| {
+        let stored: Option<Vec<u8>> = con.get(key)?;
+        let doc = match stored.map(|stored| parse_document(codec, stored)) {
+            Some(Ok(doc)) => Some(doc),
+            Some(Err(status)) => return Ok(Some(Err(status))),
+            None => None,
+        };
+        let existed = doc.is_some();
+        let (doc, reply) = match edit.apply(doc, path) {
+            Ok((Some(doc), reply)) => (doc, reply),
+            Ok((None, reply)) => return Ok(Some(Ok(reply))),
+            Err(status) => return Ok(Some(Err(status))),
+        };
+
+        match rewrite {
+            Some(rewrite) => {
+                pipe.set(key, codec.encode(doc.to_string().into_bytes()))
+                    .ignore();
+                rewrite(pipe);
+            }
+            None if existed => queue_update(con, pipe, codec, key, &meta_key, &doc)?,
+            None => {
+                pipe.set(key, codec.encode(doc.to_string().into_bytes()))
+                    .ignore();
+                Meta::new(None, None)
+                    .with_content_type(ContentType::Json)
+                    .write(pipe, key);
+            }
+        }
+        let done: Option<()> = pipe.query(con)?;
+        Ok(done.map(|_| Ok(reply)))
+    })
+}
+
+/// Queues an edited document, keeping the key's TTL and moving its metadata's write time on.
+fn queue_update<C: ConnectionLike>(
+    con: &mut C,
+    pipe: &mut Pipeline,
+    codec: &dyn Codec,
+    key: &str,
+    meta_key: &str,
+    doc: &JsonValue,
+) -> RedisResult<()> {
+    pipe.cmd("SET")
+        .arg(key)
+        .arg(codec.encode(doc.to_string().into_bytes()))
+        .arg("KEEPTTL")
+        .ignore();
+    if con.exists(meta_key)? {
+        pipe.hset(meta_key, "stored_at", now_ms())
+            .ignore()
+            .hset(meta_key, "content_type", ContentType::Json.name())
+            .ignore();
+    }
+    Ok(())
+}
+
+/// Parses a stored document, opening it first as it may be compressed or sealed.
+fn parse_document(codec: &dyn Codec, stored: Vec<u8>) -> Result<JsonValue, Status> {
+    serde_json::from_slice(&codec.decode(stored)?)
+        .map_err(|_| Status::failed_precondition("Key does not hold a JSON document"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Keeps documents as they are.
+    struct Plain;
+
+    impl Codec for Plain {
+        fn encode(&self, json: Vec<u8>) -> Vec<u8> {
+            json
+        }
+
+        fn decode(&self, stored: Vec<u8>) -> Result<Vec<u8>, Status> {
+            Ok(stored)
+        }
+    }
+
+    /// A connection that records the commands sent to it and answers each with `reply`.
+    struct Recorder {
+        sent: Vec<String>,
+        reply: redis::Value,
+    }
+
+    impl ConnectionLike for Recorder {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<redis::Value> {
+            self.sent.push(String::from_utf8_lossy(cmd).into_owned());
+            Ok(self.reply.clone())
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            cmd: &[u8],
+            _offset: usize,
+            count: usize,
+        ) -> RedisResult<Vec<redis::Value>> {
+            self.sent.push(String::from_utf8_lossy(cmd).into_owned());
+            Ok(vec![self.reply.clone(); count])
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    fn doc() -> JsonValue {
+        json!({
+            "items": [
+                {"name": "a", "price": 5},
+                {"name": "b", "price": 15},
+                {"name": "c", "price": 25}
+            ],
+            "a.b": 1,
+            "it's": 2,
+            "line\nbreak": 3,
+            "none": null
+        })
+    }
+
+    /// The nodes a path matches in `doc()`, in document order.
+    fn matches(path: &str) -> JsonValue {
+        let doc = doc();
+        let path = Path::parse(path).unwrap();
+        path.resolve(&doc)
+            .iter()
+            .filter_map(|steps| lookup(&doc, steps).cloned())
+            .collect()
+    }
+
+    fn native_get(doc: &JsonValue, path: &str) -> Option<String> {
+        let mut con = Recorder {
+            sent: Vec::new(),
+            reply: redis::Value::Data(doc.to_string().into_bytes()),
+        };
+        let paths = [Path::parse(path).unwrap()];
+        get(&mut con, Store::Native(&Plain), "k", &paths)
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn parses_paths_and_refuses_broken_ones() {
+        assert!(Path::parse("").unwrap().is_root());
+        assert!(Path::parse("$").unwrap().is_root());
+        assert!(!Path::parse("$.a").unwrap().is_root());
+        for broken in ["a", "$.", "$[", "$['a]", "$[?@.a ==]"] {
+            let refused = Path::parse(broken).unwrap_err();
+            assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn selects_with_filters() {
+        assert_eq!(matches("$.items[?@.price > 10].name"), json!(["b", "c"]));
+        assert_eq!(matches("$.items[?(@.price < 10)].name"), json!(["a"]));
+        assert_eq!(matches("$.items[?@.name == 'c'].price"), json!([25]));
+        assert_eq!(matches("$.items[?@.missing].name"), json!([]));
+    }
+
+    #[test]
+    fn selects_with_wildcards_and_descent() {
+        assert_eq!(matches("$.items[*].price"), json!([5, 15, 25]));
+        assert_eq!(matches("$.items.*.name"), json!(["a", "b", "c"]));
+        assert_eq!(matches("$..price"), json!([5, 15, 25]));
+    }
+
+    #[test]
+    fn counts_negative_indexes_from_the_end() {
+        assert_eq!(matches("$.items[-1].name"), json!(["c"]));
+        assert_eq!(matches("$.items[-3].name"), json!(["a"]));
+        assert_eq!(matches("$.items[-4]"), json!([]));
+        assert_eq!(matches("$.items[0,-1].name"), json!(["a", "c"]));
+    }
+
+    #[test]
+    fn selects_escaped_names() {
+        assert_eq!(matches("$['a.b']"), json!([1]));
+        assert_eq!(matches("$.a.b"), json!([]));
+        assert_eq!(matches(r#"$["it's"]"#), json!([2]));
+        assert_eq!(matches(r"$['it\'s']"), json!([2]));
+        assert_eq!(matches(r"$['line\nbreak']"), json!([3]));
+    }
+
+    #[test]
+    fn reads_normalized_paths_back() {
+        let steps = |path| normalized_steps(path).unwrap();
+        assert_eq!(steps("$"), vec![]);
+        assert_eq!(
+            steps(r"$['a\'b'][2]['\u0001\n']"),
+            vec![
+                Step::Name("a'b".to_string()),
+                Step::Index(2),
+                Step::Name("\u{1}\n".to_string()),
+            ]
+        );
+        assert_eq!(normalized_steps("$['a'"), None);
+    }
+
+    #[test]
+    fn tells_missing_paths_from_null_ones() {
+        assert_eq!(matches("$.none"), json!([null]));
+        assert_eq!(matches("$.gone"), json!([]));
+
+        let exists = |path: &str| {
+            let path = Path::parse(path).unwrap();
+            Edit::Set(json!(1), JsonCondition::Exists)
+                .apply(Some(doc()), &path)
+                .unwrap()
+        };
+        let (changed, reply) = exists("$.none");
+        assert_eq!(
+            (changed.unwrap()["none"].clone(), reply),
+            (json!(1), json!(true))
+        );
+        let (changed, reply) = exists("$.gone");
+        assert_eq!((changed, reply), (None, json!(false)));
+
+        let incr = Edit::NumIncrBy(2.into());
+        let (changed, reply) = incr
+            .apply(Some(doc()), &Path::parse("$.none").unwrap())
+            .unwrap();
+        assert_eq!((changed, reply), (None, json!([null])));
+    }
+
+    #[test]
+    fn edits_what_the_path_matches() {
+        let path = Path::parse("$.items[?@.price > 10].price").unwrap();
+        let (changed, reply) = Edit::NumIncrBy(1.into()).apply(Some(doc()), &path).unwrap();
+        assert_eq!(reply, json!([16, 26]));
+        assert_eq!(changed.unwrap()["items"][0]["price"], json!(5));
+
+        let path = Path::parse("$.items[-1].tag").unwrap();
+        let (changed, _) = Edit::Set(json!("new"), JsonCondition::Always)
+            .apply(Some(doc()), &path)
+            .unwrap();
+        assert_eq!(changed.unwrap()["items"][2]["tag"], json!("new"));
+
+        let mut edited = doc();
+        let deleted = delete_matches(&mut edited, &Path::parse("$.items[*].name").unwrap());
+        assert_eq!(deleted, 3);
+        assert_eq!(
+            edited["items"],
+            json!([{"price": 5}, {"price": 15}, {"price": 25}])
+        );
+    }
+
+    #[test]
+    fn native_documents_answer_paths_like_the_module() {
+        assert_eq!(native_get(&doc(), "$.none").as_deref(), Some("[null]"));
+        assert_eq!(native_get(&doc(), "$.gone").as_deref(), Some("[]"));
+        assert_eq!(
+            native_get(&doc(), "$.items[?@.price > 10].name").as_deref(),
+            Some(r#"["b","c"]"#)
+        );
+    }
+
+    #[test]
+    fn module_documents_get_the_path_as_written() {
+        let path = "$.items[?@.price > 10]['it\\'s'][-1]";
+        let mut con = Recorder {
+            sent: Vec::new(),
+            reply: redis::Value::Data(b"[]".to_vec()),
+        };
+        let paths = [Path::parse(path).unwrap()];
+        let json = get(&mut con, Store::Module, "k", &paths).unwrap().unwrap();
+
+        assert_eq!(json.as_deref(), Some("[]"));
+        assert!(con.sent[0].contains("JSON.GET") && con.sent[0].contains(path));
+        let edit = Edit::NumIncrBy(1.into());
+        assert_eq!(
+            edit.module_args(&paths[0]),
+            vec!["JSON.NUMINCRBY", path, "1"]
+        );
+    }
+}
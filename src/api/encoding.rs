@@ -0,0 +1,560 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{Map, Number};
+use tonic::Status;
+
+use crate::api::mr_cache::{ContentType, KeyValue, Value};
+
+/// Deepest nesting a structured value may have.
+const MAX_DEPTH: usize = 128;
+
+impl ContentType {
+    /// Short name, also how the content type is kept in a key's metadata.
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentType::Unspecified => "",
+            ContentType::Raw => "raw",
+            ContentType::Json => "json",
+            ContentType::Msgpack => "msgpack",
+            ContentType::Cbor => "cbor",
+            ContentType::Protobuf => "protobuf",
+        }
+    }
+
+    /// Parses a short name or a media type.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let content_type = match name.trim().to_ascii_lowercase().as_str() {
+            "" => ContentType::Unspecified,
+            "raw" | "application/octet-stream" => ContentType::Raw,
+            "json" | "application/json" => ContentType::Json,
+            "msgpack" | "application/msgpack" | "application/x-msgpack" => ContentType::Msgpack,
+            "cbor" | "application/cbor" => ContentType::Cbor,
+            "protobuf" | "application/protobuf" | "application/x-protobuf" => ContentType::Protobuf,
+            _ => return None,
+        };
+        Some(content_type)
+    }
+
+    /// JSON, MessagePack and CBOR values can be transcoded between each other.
+    fn is_structured(self) -> bool {
+        matches!(
+            self,
+            ContentType::Json | ContentType::Msgpack | ContentType::Cbor
+        )
+    }
+}
+
+impl KeyValue {
+    /// The value's bytes, from `data` when it is set.
+    pub fn bytes(&self) -> &[u8] {
+        match self.data.is_empty() {
+            true => self.value.as_bytes(),
+            false => &self.data,
+        }
+    }
+}
+
+impl Value {
+    /// A value in `value` when it is valid UTF-8 and in `data` otherwise.
+    pub fn from_bytes(bytes: Vec<u8>, content_type: ContentType) -> Self {
+        let (value, data) = match String::from_utf8(bytes) {
+            Ok(value) => (value, Vec::new()),
+            Err(err) => (String::new(), err.into_bytes()),
+        };
+        Value {
+            value,
+            age_ms: None,
+            stale: false,
+            data,
+            content_type: content_type.into(),
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        match self.data.is_empty() {
+            true => self.value.into_bytes(),
+            false => self.data,
+        }
+    }
+}
+
+/// Checks that a value is what its content type says and returns the bytes to store: JSON with
+/// the whitespace between tokens removed, anything else as it was sent.
+pub fn validate(content_type: ContentType, bytes: Vec<u8>) -> Result<Vec<u8>, Status> {
+    let checked = match content_type {
+        ContentType::Unspecified | ContentType::Raw => Ok(()),
+        ContentType::Json => serde_json::from_slice::<serde_json::Value>(&bytes)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        ContentType::Msgpack => msgpack::decode(&bytes).map(|_| ()),
+        ContentType::Cbor => cbor::decode(&bytes).map(|_| ()),
+        ContentType::Protobuf => protobuf::check(&bytes),
+    };
+
+    match checked {
+        Ok(()) if content_type == ContentType::Json => Ok(minify_json(&bytes)),
+        Ok(()) => Ok(bytes),
+        Err(e) => Err(Status::invalid_argument(format!(
+            "Invalid {} value: {}",
+            content_type.name(),
+            e
+        ))),
+    }
+}
+
+/// Re-encodes a structured value in the accepted content type. Values that can not be, because
+/// the accepted type is unspecified or either side is not structured, are returned as they are.
+pub fn transcode(bytes: Vec<u8>, from: ContentType, accept: ContentType) -> (Vec<u8>, ContentType) {
+    if from == accept || !from.is_structured() || !accept.is_structured() {
+        return (bytes, from);
+    }
+
+    let node = match from {
+        ContentType::Json => serde_json::from_slice(&bytes)
+            .map(Node::from_json)
+            .map_err(|e| e.to_string()),
+        ContentType::Msgpack => msgpack::decode(&bytes),
+        _ => cbor::decode(&bytes),
+    };
+    let encoded = node.and_then(|node| match accept {
+        ContentType::Json => serde_json::to_vec(&node.into_json()).map_err(|e| e.to_string()),
+        ContentType::Msgpack => msgpack::encode(&node),
+        _ => cbor::encode(&node),
+    });
+
+    match encoded {
+        Ok(encoded) => (encoded, accept),
+        Err(_) => (bytes, from),
+    }
+}
+
+/// Drops the whitespace between the tokens of valid JSON, keeping members in their order.
+fn minify_json(json: &[u8]) -> Vec<u8> {
+    let mut minified = Vec::with_capacity(json.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in json {
+        if in_string {
+            minified.push(byte);
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+        } else if !matches!(byte, b' ' | b'\t' | b'\n' | b'\r') {
+            in_string = byte == b'"';
+            minified.push(byte);
+        }
+    }
+    minified
+}
+
+/// A decoded structured value, the common ground JSON, MessagePack and CBOR are transcoded through.
+enum Node {
+    Null,
+    Bool(bool),
+    Int(i128),
+    Float(f64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Array(Vec<Node>),
+    Map(Vec<(Node, Node)>),
+}
+
+impl Node {
+    fn from_json(json: serde_json::Value) -> Self {
+        match json {
+            serde_json::Value::Null => Node::Null,
+            serde_json::Value::Bool(value) => Node::Bool(value),
+            serde_json::Value::Number(number) => match (number.as_i64(), number.as_u64()) {
+                (Some(value), _) => Node::Int(value.into()),
+                (_, Some(value)) => Node::Int(value.into()),
+                _ => Node::Float(number.as_f64().unwrap_or_default()),
+            },
+            serde_json::Value::String(value) => Node::Str(value),
+            serde_json::Value::Array(items) => {
+                Node::Array(items.into_iter().map(Node::from_json).collect())
+            }
+            serde_json::Value::Object(members) => Node::Map(
+                members
+                    .into_iter()
+                    .map(|(key, value)| (Node::Str(key), Node::from_json(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// JSON has no byte strings, no non-finite floats and only string keys, so byte strings
+    /// become base64, NaN and infinities null, and other keys their JSON text.
+    fn into_json(self) -> serde_json::Value {
+        match self {
+            Node::Null => serde_json::Value::Null,
+            Node::Bool(value) => value.into(),
+            Node::Int(value) => match (i64::try_from(value), u64::try_from(value)) {
+                (Ok(value), _) => value.into(),
+                (_, Ok(value)) => value.into(),
+                _ => Node::Float(value as f64).into_json(),
+            },
+            Node::Float(value) => Number::from_f64(value)
+                .map(serde_json::Value::Number)
+                .unwrap_or_default(),
+            Node::Str(value) => value.into(),
+            Node::Bytes(value) => BASE64.encode(value).into(),
+            Node::Array(items) => items.into_iter().map(Node::into_json).collect(),
+            Node::Map(entries) => {
+                let members: Map<String, serde_json::Value> = entries
+                    .into_iter()
+                    .map(|(key, value)| {
+                        let key = match key.into_json() {
+                            serde_json::Value::String(key) => key,
+                            key => key.to_string(),
+                        };
+                        (key, value.into_json())
+                    })
+                    .collect();
+                members.into()
+            }
+        }
+    }
+}
+
+/// Reads through a protobuf message, failing on truncation.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .ok_or("unexpected end of value")?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+/// Fails unless `rest`, what a decoder left of a value, is empty.
+fn done(rest: &[u8]) -> Result<(), String> {
+    match rest.is_empty() {
+        true => Ok(()),
+        false => Err("trailing bytes after value".to_string()),
+    }
+}
+
+mod msgpack {
+    use super::{done, Node, MAX_DEPTH};
+    use rmpv::{Integer, Value};
+
+    pub(super) fn decode(bytes: &[u8]) -> Result<Node, String> {
+        let mut rest = bytes;
+        let value = rmpv::decode::read_value_with_max_depth(&mut rest, MAX_DEPTH)
+            .map_err(|e| e.to_string())?;
+        done(rest)?;
+        from_value(value)
+    }
+
+    fn from_value(value: Value) -> Result<Node, String> {
+        let node = match value {
+            Value::Nil => Node::Null,
+            Value::Boolean(value) => Node::Bool(value),
+            Value::Integer(value) => match (value.as_i64(), value.as_u64()) {
+                (Some(value), _) => Node::Int(value.into()),
+                (_, Some(value)) => Node::Int(value.into()),
+                _ => return Err("integer out of range".into()),
+            },
+            Value::F32(value) => Node::Float(value.into()),
+            Value::F64(value) => Node::Float(value),
+            Value::String(value) => match value.into_str() {
+                Some(value) => Node::Str(value),
+                None => return Err("string is not valid UTF-8".into()),
+            },
+            Value::Binary(value) => Node::Bytes(value),
+            Value::Array(items) => Node::Array(
+                items
+                    .into_iter()
+                    .map(from_value)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Map(entries) => Node::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| Ok((from_value(key)?, from_value(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            Value::Ext(..) => return Err("extension types are not supported".into()),
+        };
+        Ok(node)
+    }
+
+    pub(super) fn encode(node: &Node) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        rmpv::encode::write_value(&mut out, &to_value(node)?).map_err(|e| e.to_string())?;
+        Ok(out)
+    }
+
+    fn to_value(node: &Node) -> Result<Value, String> {
+        let value = match node {
+            Node::Null => Value::Nil,
+            Node::Bool(value) => Value::Boolean(*value),
+            Node::Int(value) => match (i64::try_from(*value), u64::try_from(*value)) {
+                (Ok(value), _) => Value::Integer(Integer::from(value)),
+                (_, Ok(value)) => Value::Integer(Integer::from(value)),
+                _ => return Err("integer out of range for MessagePack".into()),
+            },
+            Node::Float(value) => Value::F64(*value),
+            Node::Str(value) => Value::from(value.as_str()),
+            Node::Bytes(value) => Value::Binary(value.clone()),
+            Node::Array(items) => {
+                Value::Array(items.iter().map(to_value).collect::<Result<_, _>>()?)
+            }
+            Node::Map(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| Ok((to_value(key)?, to_value(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+        };
+        Ok(value)
+    }
+}
+
+mod cbor {
+    use super::{done, Node, MAX_DEPTH};
+    use ciborium::Value;
+
+    pub(super) fn decode(bytes: &[u8]) -> Result<Node, String> {
+        let mut rest = bytes;
+        let value: Value = ciborium::de::from_reader_with_recursion_limit(&mut rest, MAX_DEPTH)
+            .map_err(|e| e.to_string())?;
+        done(rest)?;
+        from_value(value)
+    }
+
+    /// Tags are dropped for the value they wrap, which is all JSON and MessagePack could carry.
+    fn from_value(value: Value) -> Result<Node, String> {
+        let node = match value {
+            Value::Null => Node::Null,
+            Value::Bool(value) => Node::Bool(value),
+            Value::Integer(value) => Node::Int(value.into()),
+            Value::Float(value) => Node::Float(value),
+            Value::Text(value) => Node::Str(value),
+            Value::Bytes(value) => Node::Bytes(value),
+            Value::Tag(_, value) => from_value(*value)?,
+            Value::Array(items) => Node::Array(
+                items
+                    .into_iter()
+                    .map(from_value)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Map(entries) => Node::Map(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| Ok((from_value(key)?, from_value(value)?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            _ => return Err("unsupported CBOR value".into()),
+        };
+        Ok(node)
+    }
+
+    pub(super) fn encode(node: &Node) -> Result<Vec<u8>, String> {
+        let mut out = Vec::new();
+        ciborium::ser::into_writer(&to_value(node), &mut out).map_err(|e| e.to_string())?;
+        Ok(out)
+    }
+
+    fn to_value(node: &Node) -> Value {
+        match node {
+            Node::Null => Value::Null,
+            Node::Bool(value) => Value::Bool(*value),
+            Node::Int(value) => match ciborium::value::Integer::try_from(*value) {
+                Ok(value) => Value::Integer(value),
+                Err(_) => Value::Float(*value as f64),
+            },
+            Node::Float(value) => Value::Float(*value),
+            Node::Str(value) => Value::Text(value.clone()),
+            Node::Bytes(value) => Value::Bytes(value.clone()),
+            Node::Array(items) => Value::Array(items.iter().map(to_value).collect()),
+            Node::Map(entries) => Value::Map(
+                entries
+                    .iter()
+                    .map(|(key, value)| (to_value(key), to_value(value)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Without a schema a protobuf message can only be checked to be well-formed on the wire.
+mod protobuf {
+    use super::Reader;
+
+    fn varint(reader: &mut Reader) -> Result<u64, String> {
+        let mut value = 0;
+        for shift in (0..70).step_by(7) {
+            let byte = reader.byte()?;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint too long".into())
+    }
+
+    pub(super) fn check(bytes: &[u8]) -> Result<(), String> {
+        let mut reader = Reader::new(bytes);
+        let mut groups = Vec::new();
+
+        while !reader.is_empty() {
+            let key = varint(&mut reader)?;
+            let field = key >> 3;
+            if field == 0 {
+                return Err("field number 0".into());
+            }
+            match key & 7 {
+                0 => {
+                    varint(&mut reader)?;
+                }
+                1 => {
+                    reader.take(8)?;
+                }
+                2 => {
+                    let len = usize::try_from(varint(&mut reader)?)
+                        .map_err(|_| "length out of range".to_string())?;
+                    reader.take(len)?;
+                }
+                3 => groups.push(field),
+                4 if groups.pop() != Some(field) => {
+                    return Err(format!("unmatched end of group {field}"));
+                }
+                4 => {}
+                5 => {
+                    reader.take(4)?;
+                }
+                wire_type => return Err(format!("invalid wire type {wire_type} on field {field}")),
+            }
+        }
+        match groups.is_empty() {
+            true => Ok(()),
+            false => Err("unterminated group".into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcoded(json: &str, accept: ContentType) -> Vec<u8> {
+        let (bytes, content_type) = transcode(json.as_bytes().to_vec(), ContentType::Json, accept);
+        assert_eq!(content_type, accept);
+        bytes
+    }
+
+    #[test]
+    fn minifies_valid_json() {
+        let json = br#"{ "b" : [1, 2], "a" : "x y" }"#.to_vec();
+        let stored = validate(ContentType::Json, json).unwrap();
+        assert_eq!(stored, br#"{"b":[1,2],"a":"x y"}"#);
+    }
+
+    #[test]
+    fn refuses_values_that_are_not_their_content_type() {
+        for content_type in [ContentType::Json, ContentType::Msgpack, ContentType::Cbor] {
+            let refused = validate(content_type, vec![0xc1, 0xff, 0x00]).unwrap_err();
+            assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+        }
+        assert!(validate(ContentType::Raw, vec![0xc1, 0xff]).is_ok());
+    }
+
+    #[test]
+    fn transcodes_between_structured_types() {
+        let json = r#"{"a":[1,-2,1.5,"x",true,null],"b":{"c":"d"}}"#;
+        for accept in [ContentType::Msgpack, ContentType::Cbor] {
+            let bytes = transcoded(json, accept);
+            assert!(validate(accept, bytes.clone()).is_ok());
+            let (back, content_type) = transcode(bytes, accept, ContentType::Json);
+            assert_eq!(content_type, ContentType::Json);
+            assert_eq!(back, json.as_bytes());
+        }
+
+        let cbor = transcoded(json, ContentType::Cbor);
+        let (msgpack, _) = transcode(cbor, ContentType::Cbor, ContentType::Msgpack);
+        assert_eq!(msgpack, transcoded(json, ContentType::Msgpack));
+    }
+
+    #[test]
+    fn refuses_trailing_bytes_and_deep_nesting() {
+        let mut msgpack = transcoded("[1]", ContentType::Msgpack);
+        msgpack.push(0xc0);
+        assert!(validate(ContentType::Msgpack, msgpack).is_err());
+        let mut cbor = transcoded("[1]", ContentType::Cbor);
+        cbor.push(0xf6);
+        assert!(validate(ContentType::Cbor, cbor).is_err());
+
+        // Arrays of one array each, down to a null.
+        let nested = |array: u8, null: u8, depth: usize| {
+            let mut bytes = vec![array; depth];
+            bytes.push(null);
+            bytes
+        };
+        assert!(validate(ContentType::Msgpack, nested(0x91, 0xc0, 10)).is_ok());
+        assert!(validate(ContentType::Msgpack, nested(0x91, 0xc0, 10 * MAX_DEPTH)).is_err());
+        assert!(validate(ContentType::Cbor, nested(0x81, 0xf6, 10)).is_ok());
+        assert!(validate(ContentType::Cbor, nested(0x81, 0xf6, 10 * MAX_DEPTH)).is_err());
+    }
+
+    #[test]
+    fn turns_byte_strings_into_base64_for_json() {
+        let msgpack = vec![0x81, 0xa1, b'b', 0xc4, 0x02, 0xff, 0x00];
+        let (json, _) = transcode(msgpack, ContentType::Msgpack, ContentType::Json);
+        assert_eq!(json, br#"{"b":"/wA="}"#);
+    }
+
+    #[test]
+    fn leaves_what_cannot_be_transcoded_alone() {
+        let raw = (b"raw".to_vec(), ContentType::Raw);
+        assert_eq!(transcode(raw.0.clone(), raw.1, ContentType::Json), raw);
+        let json = (b"{}".to_vec(), ContentType::Json);
+        assert_eq!(
+            transcode(json.0.clone(), json.1, ContentType::Unspecified),
+            json
+        );
+        let broken = (b"{".to_vec(), ContentType::Json);
+        assert_eq!(
+            transcode(broken.0.clone(), broken.1, ContentType::Cbor),
+            broken
+        );
+    }
+
+    #[test]
+    fn parses_names_and_media_types() {
+        assert_eq!(ContentType::from_name("JSON"), Some(ContentType::Json));
+        let msgpack = ContentType::from_name(" application/x-msgpack ");
+        assert_eq!(msgpack, Some(ContentType::Msgpack));
+        assert_eq!(ContentType::from_name("text/plain"), None);
+        for content_type in [ContentType::Raw, ContentType::Cbor, ContentType::Protobuf] {
+            assert_eq!(
+                ContentType::from_name(content_type.name()),
+                Some(content_type)
+            );
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use redis::{Commands, Connection, ErrorKind, RedisError, RedisResult, Script};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::Status;
+use tracing::{error, info, warn};
+
+use crate::api::compression::HEADER_PREFIX;
+use crate::api::metrics;
+use crate::api::pool::RedisPool;
+use crate::api::tenant::INTERNAL_PREFIX;
+use crate::config::EncryptionConfig;
+
+/// Starts every sealed value. What follows is the key ID's length and the key ID, the nonce and
+/// sealed data key, then the nonce and sealed value.
+const MAGIC: [u8; 4] = [HEADER_PREFIX[0], HEADER_PREFIX[1], HEADER_PREFIX[2], b'E'];
+
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const SEALED_KEY_LEN: usize = NONCE_LEN + KEY_LEN + TAG_LEN;
+
+/// Replaces a string value with its re-sealed self, unless it was written again in the meantime.
+const RESEAL_STRING_SCRIPT: &str = r"
+if redis.call('GET', KEYS[1]) ~= ARGV[1] then
+  return 0
+end
+redis.call('SET', KEYS[1], ARGV[2], 'KEEPTTL')
+return 1
+";
+
+/// Same for the hash field ARGV[1].
+const RESEAL_FIELD_SCRIPT: &str = r"
+if redis.call('HGET', KEYS[1], ARGV[1]) ~= ARGV[2] then
+  return 0
+end
+redis.call('HSET', KEYS[1], ARGV[1], ARGV[3])
+return 1
+";
+
+/// A sealed value taken apart.
+struct Envelope<'a> {
+    key_id: &'a str,
+    sealed_key: &'a [u8],
+    sealed_value: &'a [u8],
+}
+
+impl<'a> Envelope<'a> {
+    fn parse(value: &'a [u8]) -> Option<Self> {
+        let rest = value.strip_prefix(&MAGIC)?;
+        let (&id_len, rest) = rest.split_first()?;
+        if rest.len() < id_len as usize + SEALED_KEY_LEN + NONCE_LEN + TAG_LEN {
+            return None;
+        }
+        let (key_id, rest) = rest.split_at(id_len as usize);
+        let (sealed_key, sealed_value) = rest.split_at(SEALED_KEY_LEN);
+
+        Some(Self {
+            key_id: std::str::from_utf8(key_id).ok()?,
+            sealed_key,
+            sealed_value,
+        })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut value = MAGIC.to_vec();
+        value.push(self.key_id.len() as u8);
+        value.extend_from_slice(self.key_id.as_bytes());
+        value.extend_from_slice(self.sealed_key);
+        value.extend_from_slice(self.sealed_value);
+        value
+    }
+}
+
+/// Seals values with AES-256-GCM before they are stored and opens them on the way out, see
+/// `EncryptionConfig`. Values are bound to the Redis key they are stored under, and hash values to
+/// their field too, so they cannot be moved to another key or field.
+pub struct Encryption {
+    keys: HashMap<String, LessSafeKey>,
+    active_key: Option<String>,
+    key_hash: Option<hmac::Key>,
+    rng: SystemRandom,
+}
+
+/// What a value is bound to: its Redis key, followed for a hash value by 0xFF and the field. Keys
+/// are UTF-8, which never has 0xFF, so no key and field can pass for another.
+fn associated_data(redis_key: &str, field: Option<&[u8]>) -> Vec<u8> {
+    let mut aad = redis_key.as_bytes().to_vec();
+    if let Some(field) = field {
+        aad.push(0xff);
+        aad.extend_from_slice(field);
+    }
+    aad
+}
+
+fn read_key_file(path: &str) -> Vec<u8> {
+    let contents = fs::read(path).expect("Failed to read encryption key file.");
+    match contents.len() {
+        KEY_LEN => contents,
+        _ => BASE64
+            .decode(String::from_utf8_lossy(&contents).trim())
+            .expect("Failed to decode encryption key file, expected 32 bytes or base64."),
+    }
+}
+
+impl Encryption {
+    pub fn new(config: &EncryptionConfig) -> Self {
+        let keys: HashMap<String, LessSafeKey> = config
+            .keys
+            .iter()
+            .map(|(id, path)| {
+                assert!(
+                    id.len() <= u8::MAX as usize,
+                    "Encryption key ID {id} is too long."
+                );
+                let key = UnboundKey::new(&AES_256_GCM, &read_key_file(path))
+                    .expect("Encryption keys must be 32 bytes long.");
+                (id.clone(), LessSafeKey::new(key))
+            })
+            .collect();
+        if let Some(active) = &config.active_key {
+            assert!(
+                keys.contains_key(active),
+                "Active encryption key {active} is not among the keys."
+            );
+        }
+
+        Self {
+            keys,
+            active_key: config.active_key.clone(),
+            key_hash: config
+                .key_hash_file
+                .as_ref()
+                .map(|path| hmac::Key::new(hmac::HMAC_SHA256, &read_key_file(path))),
+            rng: SystemRandom::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.active_key.is_some()
+    }
+
+    pub fn hashes_keys(&self) -> bool {
+        self.key_hash.is_some()
+    }
+
+    /// The name a key is stored under: its hex HMAC when keys are hashed, the key itself otherwise.
+    pub fn hash_key(&self, key: &str) -> String {
+        let Some(key_hash) = &self.key_hash else {
+            return key.to_string();
+        };
+        let mut hashed = String::with_capacity(64);
+        for byte in hmac::sign(key_hash, key.as_bytes()).as_ref() {
+            let _ = write!(hashed, "{byte:02x}");
+        }
+        hashed
+    }
+
+    /// Seals a value for storing under `redis_key`, in `field` for a hash, with a fresh data key,
+    /// itself sealed with the active key.
+    pub fn seal(&self, redis_key: &str, field: Option<&[u8]>, value: Vec<u8>) -> Vec<u8> {
+        let Some(key_id) = &self.active_key else {
+            return value;
+        };
+
+        let mut data_key = vec![0; KEY_LEN];
+        self.rng
+            .fill(&mut data_key)
+            .expect("Failed to generate a data key.");
+        let sealed_key = self.seal_with(&self.keys[key_id], key_id.as_bytes(), data_key.clone());
+        let data_key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &data_key).unwrap());
+        let aad = associated_data(redis_key, field);
+        let sealed_value = self.seal_with(&data_key, &aad, value);
+
+        Envelope {
+            key_id,
+            sealed_key: &sealed_key,
+            sealed_value: &sealed_value,
+        }
+        .to_bytes()
+    }
+
+    /// Opens a value read from `redis_key`, or from its `field` for a hash. Values that were never
+    /// sealed come back as they are.
+    pub fn open(
+        &self,
+        redis_key: &str,
+        field: Option<&[u8]>,
+        value: Vec<u8>,
+    ) -> Result<Vec<u8>, Status> {
+        if !value.starts_with(&MAGIC) {
+            return Ok(value);
+        }
+
+        let opened = Envelope::parse(&value)
+            .and_then(|envelope| self.open_envelope(&envelope, &associated_data(redis_key, field)));
+        opened.ok_or_else(|| {
+            error!(key = redis_key, "Failed to decrypt value");
+            Status::data_loss("Failed to decrypt value")
+        })
+    }
+
+    fn open_key(&self, envelope: &Envelope) -> Option<Vec<u8>> {
+        let key = self.keys.get(envelope.key_id)?;
+        open_with(key, envelope.key_id.as_bytes(), envelope.sealed_key)
+    }
+
+    fn open_envelope(&self, envelope: &Envelope, aad: &[u8]) -> Option<Vec<u8>> {
+        let data_key = self.open_key(envelope)?;
+        let data_key = LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &data_key).ok()?);
+        open_with(&data_key, aad, envelope.sealed_value)
+    }
+
+    /// Seals `data` under a random nonce, returning the nonce followed by the sealed data.
+    fn seal_with(&self, key: &LessSafeKey, aad: &[u8], mut data: Vec<u8>) -> Vec<u8> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .expect("Failed to generate a nonce.");
+        key.seal_in_place_append_tag(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut data,
+        )
+        .expect("Failed to encrypt value.");
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&data);
+        sealed
+    }
+
+    /// Moves a value read from `redis_key`, or from its `field` for a hash, sealed with an older
+    /// key over to the active key. Only the data key is re-sealed, the value itself is left as it
+    /// is, once it is known to open where it is stored.
+    fn reseal(&self, redis_key: &str, field: Option<&[u8]>, value: &[u8]) -> Option<Vec<u8>> {
+        let active = self.active_key.as_ref()?;
+        let envelope = Envelope::parse(value)?;
+        if envelope.key_id == active {
+            return None;
+        }
+
+        let Some(data_key) = self.open_key(&envelope) else {
+            warn!(
+                key_id = envelope.key_id,
+                "Cannot re-encrypt a value sealed with an unknown key"
+            );
+            return None;
+        };
+        if self
+            .open_envelope(&envelope, &associated_data(redis_key, field))
+            .is_none()
+        {
+            warn!(
+                key = redis_key,
+                "Cannot re-encrypt a value that fails to open"
+            );
+            return None;
+        }
+        let sealed_key = self.seal_with(&self.keys[active], active.as_bytes(), data_key);
+        Some(
+            Envelope {
+                key_id: active,
+                sealed_key: &sealed_key,
+                ..envelope
+            }
+            .to_bytes(),
+        )
+    }
+
+    /// Re-seals every value in a pool that was sealed with another key than the active one.
+    /// Returns how many values were moved over.
+    fn reencrypt_pool(&self, pool: &RedisPool, batch: u64) -> RedisResult<u64> {
+        let mut con = pool
+            .get()
+            .map_err(|e| RedisError::from((ErrorKind::IoError, "pool", e.to_string())))?;
+        let mut resealed = 0;
+        let mut master = 0;
+        while let Some(moved) = con.on_master(master, |node| self.reencrypt_node(node, batch))? {
+            resealed += moved;
+            master += 1;
+        }
+
+        metrics::record_reencrypted(resealed);
+        Ok(resealed)
+    }
+
+    /// Re-seals the values of one node, which holds every key its `SCAN` lists.
+    fn reencrypt_node(&self, con: &mut Connection, batch: u64) -> RedisResult<u64> {
+        let mut cursor: u64 = 0;
+        let mut resealed = 0;
+
+        loop {
+            let (next, keys): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("COUNT")
+                .arg(batch)
+                .query(&mut *con)?;
+
+            for key in keys.iter().filter(|key| !key.starts_with(INTERNAL_PREFIX)) {
+                let kind: String = redis::cmd("TYPE").arg(key).query(&mut *con)?;
+                match kind.as_str() {
+                    "string" => {
+                        let value: Option<Vec<u8>> = con.get(key)?;
+                        let Some(value) = value else {
+                            continue;
+                        };
+                        if let Some(new) = self.reseal(key, None, &value) {
+                            let swapped: u64 = Script::new(RESEAL_STRING_SCRIPT)
+                                .key(key)
+                                .arg(value)
+                                .arg(new)
+                                .invoke(&mut *con)?;
+                            resealed += swapped;
+                        }
+                    }
+                    "hash" => {
+                        let fields: Vec<(Vec<u8>, Vec<u8>)> = con.hgetall(key)?;
+                        for (field, value) in fields {
+                            if let Some(new) = self.reseal(key, Some(&field), &value) {
+                                let swapped: u64 = Script::new(RESEAL_FIELD_SCRIPT)
+                                    .key(key)
+                                    .arg(field)
+                                    .arg(value)
+                                    .arg(new)
+                                    .invoke(&mut *con)?;
+                                resealed += swapped;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            cursor = next;
+            if cursor == 0 {
+                return Ok(resealed);
+            }
+        }
+    }
+
+    /// Re-seals values left over from older keys in the background. Passes repeat every
+    /// `interval` until one finds nothing left to move over, as values written meanwhile already
+    /// use the active key.
+    pub fn spawn_reencryption(
+        self: Arc<Self>,
+        pools: Vec<Arc<RedisPool>>,
+        interval: Duration,
+        batch: u64,
+    ) {
+        if !self.is_enabled() || self.keys.len() < 2 {
+            return;
+        }
+
+        tokio::spawn(async move {
+            loop {
+                let encryption = self.clone();
+                let pools = pools.clone();
+                let pass = tokio::task::spawn_blocking(move || {
+                    let mut resealed = 0;
+                    for pool in &pools {
+                        resealed += encryption.reencrypt_pool(pool, batch)?;
+                    }
+                    Ok::<u64, RedisError>(resealed)
+                })
+                .await;
+
+                match pass {
+                    Ok(Ok(0)) => {
+                        info!("Every value is sealed with the active encryption key");
+                        return;
+                    }
+                    Ok(Ok(resealed)) => info!(resealed, "Re-encrypted values"),
+                    Ok(Err(e)) => warn!(error = %e, "Failed to re-encrypt values"),
+                    Err(e) => error!(error = %e, "Re-encryption panicked"),
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+fn open_with(key: &LessSafeKey, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+    let (nonce, data) = sealed.split_at(NONCE_LEN);
+    let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut data = data.to_vec();
+    let opened = key.open_in_place(nonce, Aad::from(aad), &mut data).ok()?;
+    Some(opened.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    /// Encryption with the given keys, written out as key files, and `active` as the active one.
+    fn encryption(keys: &[(&str, u8)], active: &str) -> Encryption {
+        let dir = std::env::temp_dir().join(format!("mrcache-encryption-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let keys = keys
+            .iter()
+            .map(|&(id, byte)| {
+                let path = dir.join(format!("{id}-{byte}.key"));
+                fs::write(&path, [byte; KEY_LEN]).unwrap();
+                (id.to_string(), path.display().to_string())
+            })
+            .collect();
+        Encryption::new(&EncryptionConfig {
+            keys,
+            active_key: Some(active.to_string()),
+            ..EncryptionConfig::default()
+        })
+    }
+
+    #[test]
+    fn opens_what_it_seals() {
+        let encryption = encryption(&[("k1", 1)], "k1");
+        let sealed = encryption.seal("key", None, b"value".to_vec());
+        assert!(sealed.starts_with(&MAGIC));
+        assert_eq!(encryption.open("key", None, sealed).unwrap(), b"value");
+
+        let sealed = encryption.seal("hash", Some(b"field"), b"value".to_vec());
+        let opened = encryption.open("hash", Some(b"field"), sealed).unwrap();
+        assert_eq!(opened, b"value");
+    }
+
+    #[test]
+    fn passes_unsealed_values_through() {
+        let encryption = encryption(&[("k1", 1)], "k1");
+        assert_eq!(
+            encryption.open("key", None, b"plain".to_vec()).unwrap(),
+            b"plain"
+        );
+    }
+
+    #[test]
+    fn refuses_values_moved_to_another_key_or_field() {
+        let encryption = encryption(&[("k1", 1)], "k1");
+        let sealed = encryption.seal("key", None, b"value".to_vec());
+        let moved = encryption.open("other", None, sealed.clone());
+        assert_eq!(moved.unwrap_err().code(), Code::DataLoss);
+        assert!(encryption.open("key", Some(b""), sealed).is_err());
+
+        let sealed = encryption.seal("hash", Some(b"a"), b"value".to_vec());
+        assert!(encryption.open("hash", Some(b"b"), sealed.clone()).is_err());
+        assert!(encryption.open("hash", None, sealed).is_err());
+    }
+
+    #[test]
+    fn refuses_tampered_values() {
+        let encryption = encryption(&[("k1", 1)], "k1");
+        let mut sealed = encryption.seal("key", None, b"value".to_vec());
+        *sealed.last_mut().unwrap() ^= 1;
+        assert!(encryption.open("key", None, sealed).is_err());
+
+        let sealed = encryption.seal("key", None, b"value".to_vec());
+        assert!(encryption
+            .open("key", None, sealed[..sealed.len() - 1].to_vec())
+            .is_err());
+    }
+
+    #[test]
+    fn refuses_values_sealed_with_another_key() {
+        let sealed = encryption(&[("k1", 1)], "k1").seal("key", None, b"value".to_vec());
+        assert!(encryption(&[("k1", 2)], "k1")
+            .open("key", None, sealed.clone())
+            .is_err());
+        assert!(encryption(&[("k2", 1)], "k2")
+            .open("key", None, sealed)
+            .is_err());
+    }
+
+    #[test]
+    fn reseals_values_under_the_active_key() {
+        let sealed = encryption(&[("old", 1)], "old").seal("hash", Some(b"f"), b"value".to_vec());
+        let rotated = encryption(&[("old", 1), ("new", 2)], "new");
+        assert!(rotated.reseal("other", Some(b"f"), &sealed).is_none());
+
+        let resealed = rotated.reseal("hash", Some(b"f"), &sealed).unwrap();
+        assert!(rotated.reseal("hash", Some(b"f"), &resealed).is_none());
+        let opened = encryption(&[("new", 2)], "new").open("hash", Some(b"f"), resealed);
+        assert_eq!(opened.unwrap(), b"value");
+    }
+}
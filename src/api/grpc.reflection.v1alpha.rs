@@ -0,0 +1,300 @@
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ServerReflectionRequest {
+    #[prost(string, tag = "1")]
+    pub host: ::prost::alloc::string::String,
+    #[prost(oneof = "server_reflection_request::MessageRequest", tags = "3, 4, 5, 6, 7")]
+    pub message_request: ::core::option::Option<
+        server_reflection_request::MessageRequest,
+    >,
+}
+/// Nested message and enum types in `ServerReflectionRequest`.
+pub mod server_reflection_request {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum MessageRequest {
+        /// Find a proto file by the file name.
+        #[prost(string, tag = "3")]
+        FileByFilename(::prost::alloc::string::String),
+        /// Find the proto file that declares the given fully-qualified symbol name.
+        #[prost(string, tag = "4")]
+        FileContainingSymbol(::prost::alloc::string::String),
+        /// Find the proto file which defines an extension extending the given message type.
+        #[prost(message, tag = "5")]
+        FileContainingExtension(super::ExtensionRequest),
+        /// Finds the tag numbers used by all known extensions of the given message type.
+        #[prost(string, tag = "6")]
+        AllExtensionNumbersOfType(::prost::alloc::string::String),
+        /// List the full names of registered services.
+        #[prost(string, tag = "7")]
+        ListServices(::prost::alloc::string::String),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExtensionRequest {
+    #[prost(string, tag = "1")]
+    pub containing_type: ::prost::alloc::string::String,
+    #[prost(int32, tag = "2")]
+    pub extension_number: i32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ServerReflectionResponse {
+    #[prost(string, tag = "1")]
+    pub valid_host: ::prost::alloc::string::String,
+    #[prost(message, optional, tag = "2")]
+    pub original_request: ::core::option::Option<ServerReflectionRequest>,
+    #[prost(oneof = "server_reflection_response::MessageResponse", tags = "4, 5, 6, 7")]
+    pub message_response: ::core::option::Option<
+        server_reflection_response::MessageResponse,
+    >,
+}
+/// Nested message and enum types in `ServerReflectionResponse`.
+pub mod server_reflection_response {
+    #[allow(clippy::derive_partial_eq_without_eq)]
+    #[derive(Clone, PartialEq, ::prost::Oneof)]
+    pub enum MessageResponse {
+        /// Serialized FileDescriptorProtos for the requested file and its dependencies.
+        #[prost(message, tag = "4")]
+        FileDescriptorResponse(super::FileDescriptorResponse),
+        #[prost(message, tag = "5")]
+        AllExtensionNumbersResponse(super::ExtensionNumberResponse),
+        #[prost(message, tag = "6")]
+        ListServicesResponse(super::ListServiceResponse),
+        #[prost(message, tag = "7")]
+        ErrorResponse(super::ErrorResponse),
+    }
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FileDescriptorResponse {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    pub file_descriptor_proto: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ExtensionNumberResponse {
+    #[prost(string, tag = "1")]
+    pub base_type_name: ::prost::alloc::string::String,
+    #[prost(int32, repeated, tag = "2")]
+    pub extension_number: ::prost::alloc::vec::Vec<i32>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ListServiceResponse {
+    #[prost(message, repeated, tag = "1")]
+    pub service: ::prost::alloc::vec::Vec<ServiceResponse>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ServiceResponse {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ErrorResponse {
+    #[prost(int32, tag = "1")]
+    pub error_code: i32,
+    #[prost(string, tag = "2")]
+    pub error_message: ::prost::alloc::string::String,
+}
+/// Generated server implementations.
+pub mod server_reflection_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with ServerReflectionServer.
+    #[async_trait]
+    pub trait ServerReflection: Send + Sync + 'static {
+        /// Server streaming response type for the ServerReflectionInfo method.
+        type ServerReflectionInfoStream: tonic::codegen::tokio_stream::Stream<
+                Item = std::result::Result<
+                    super::ServerReflectionResponse,
+                    tonic::Status,
+                >,
+            >
+            + Send
+            + 'static;
+        /// Answers each request on the stream with the matching response, in order.
+        async fn server_reflection_info(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::ServerReflectionRequest>>,
+        ) -> std::result::Result<
+            tonic::Response<Self::ServerReflectionInfoStream>,
+            tonic::Status,
+        >;
+    }
+    #[derive(Debug)]
+    pub struct ServerReflectionServer<T: ServerReflection> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: ServerReflection> ServerReflectionServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for ServerReflectionServer<T>
+    where
+        T: ServerReflection,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo" => {
+                    #[allow(non_camel_case_types)]
+                    struct ServerReflectionInfoSvc<T: ServerReflection>(pub Arc<T>);
+                    impl<
+                        T: ServerReflection,
+                    > tonic::server::StreamingService<super::ServerReflectionRequest>
+                    for ServerReflectionInfoSvc<T> {
+                        type Response = super::ServerReflectionResponse;
+                        type ResponseStream = T::ServerReflectionInfoStream;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::ResponseStream>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                tonic::Streaming<super::ServerReflectionRequest>,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as ServerReflection>::server_reflection_info(
+                                        &inner,
+                                        request,
+                                    )
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ServerReflectionInfoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        Ok(
+                            http::Response::builder()
+                                .status(200)
+                                .header("grpc-status", "12")
+                                .header("content-type", "application/grpc")
+                                .body(empty_body())
+                                .unwrap(),
+                        )
+                    })
+                }
+            }
+        }
+    }
+    impl<T: ServerReflection> Clone for ServerReflectionServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    impl<T: ServerReflection> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(Arc::clone(&self.0))
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: ServerReflection> tonic::server::NamedService for ServerReflectionServer<T> {
+        const NAME: &'static str = "grpc.reflection.v1alpha.ServerReflection";
+    }
+}
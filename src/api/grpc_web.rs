@@ -0,0 +1,48 @@
+use axum::http::header::{HeaderName, AUTHORIZATION, CONTENT_TYPE};
+use axum::http::HeaderValue;
+use std::time::Duration;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::warn;
+
+use crate::api::auth::API_KEY_HEADER;
+use crate::api::tenant::TENANT_HEADER;
+use crate::config::GrpcWebConfig;
+
+/// Headers browsers may send: the gRPC-Web ones plus the metadata the server reads.
+const ALLOWED_HEADERS: [&str; 5] = [
+    "x-grpc-web",
+    "x-user-agent",
+    "grpc-timeout",
+    "traceparent",
+    "tracestate",
+];
+
+/// Trailers gRPC-Web responses carry as headers, which scripts can only read when exposed.
+const EXPOSED_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+
+/// CORS for gRPC-Web calls, answering preflights and letting pages on the allowed origins read
+/// the responses. Only listed origins are allowed, as browsers send credentials along.
+pub fn cors(config: &GrpcWebConfig) -> CorsLayer {
+    let origins = config
+        .allowed_origins
+        .iter()
+        .filter_map(|origin| {
+            HeaderValue::from_str(origin)
+                .map_err(|e| warn!(origin, error = %e, "Ignoring invalid gRPC-Web origin"))
+                .ok()
+        })
+        .collect::<Vec<_>>();
+
+    let allowed_headers = ALLOWED_HEADERS
+        .into_iter()
+        .chain([API_KEY_HEADER, TENANT_HEADER])
+        .map(HeaderName::from_static)
+        .chain([AUTHORIZATION, CONTENT_TYPE]);
+
+    CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_credentials(true)
+        .allow_headers(allowed_headers.collect::<Vec<_>>())
+        .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static))
+        .max_age(Duration::from_secs(config.max_age_s))
+}
@@ -0,0 +1,123 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio_stream::wrappers::WatchStream;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status};
+use tracing::{info, warn};
+
+use crate::api::health_proto::health_check_response::ServingStatus;
+use crate::api::health_proto::health_server::{Health as HealthRpc, HealthServer};
+use crate::api::health_proto::{HealthCheckRequest, HealthCheckResponse};
+use crate::api::pool::RedisPool;
+
+/// The service name clients can check besides the empty name for the whole server.
+const SERVICE_NAME: &str = "mr_cache.MrCache";
+
+/// Whether Redis is reachable, re-checked with a `PING` to every pool, the default one and those
+/// of tenants with their own Redis, every interval. Both the gRPC health
+/// service and the HTTP `/readyz` probe report from it.
+#[derive(Clone)]
+pub struct Health {
+    ready: watch::Receiver<bool>,
+}
+
+fn ping(pool: &RedisPool) -> Result<(), String> {
+    let mut con = pool.get().map_err(|e| e.to_string())?;
+    redis::cmd("PING")
+        .query::<String>(&mut *con)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+impl Health {
+    pub fn spawn(pools: Vec<Arc<RedisPool>>, interval: Duration) -> Self {
+        let (sender, ready) = watch::channel(false);
+
+        tokio::spawn(async move {
+            let mut first = true;
+            loop {
+                let pools = pools.clone();
+                let pinged = tokio::task::spawn_blocking(move || {
+                    pools.iter().try_for_each(|pool| ping(pool))
+                })
+                .await
+                .unwrap_or_else(|e| Err(e.to_string()));
+
+                let was_ready = *sender.borrow();
+                match (&pinged, was_ready) {
+                    (Ok(()), false) => info!("Redis is reachable, serving"),
+                    (Err(e), true) => warn!(error = %e, "Redis is unreachable, not serving"),
+                    (Err(e), false) if first => warn!(error = %e, "Waiting for Redis"),
+                    _ => {}
+                }
+                first = false;
+                sender.send_if_modified(|ready| {
+                    let changed = *ready != pinged.is_ok();
+                    *ready = pinged.is_ok();
+                    changed
+                });
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+
+        Self { ready }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    pub fn service(&self) -> HealthServer<Self> {
+        HealthServer::new(self.clone())
+    }
+}
+
+fn status(ready: bool) -> HealthCheckResponse {
+    let status = if ready {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    };
+
+    HealthCheckResponse {
+        status: status as i32,
+    }
+}
+
+fn is_known(service: &str) -> bool {
+    service.is_empty() || service == SERVICE_NAME
+}
+
+#[tonic::async_trait]
+impl HealthRpc for Health {
+    async fn check(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<HealthCheckResponse>, Status> {
+        if !is_known(&request.get_ref().service) {
+            return Err(Status::not_found("Unknown service"));
+        }
+
+        Ok(Response::new(status(self.is_ready())))
+    }
+
+    type WatchStream = Pin<Box<dyn Stream<Item = Result<HealthCheckResponse, Status>> + Send>>;
+
+    async fn watch(
+        &self,
+        request: Request<HealthCheckRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        if !is_known(&request.get_ref().service) {
+            let unknown = HealthCheckResponse {
+                status: ServingStatus::ServiceUnknown as i32,
+            };
+            return Ok(Response::new(Box::pin(tokio_stream::once(Ok(unknown)))));
+        }
+
+        let updates = WatchStream::new(self.ready.clone()).map(|ready| Ok(status(ready)));
+        Ok(Response::new(Box::pin(updates)))
+    }
+}
@@ -0,0 +1,82 @@
+use axum::extract::connect_info::Connected;
+use axum::extract::State;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use axum::Router;
+use hyper::server::conn::AddrStream;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::server::TlsStream;
+use tonic::transport::Certificate;
+use tracing::error;
+
+use crate::api::health::Health;
+use crate::api::metrics;
+use crate::api::tls::{self, Tls};
+
+/// The certificate chain the client presented on its connection, if it is over TLS and sent one.
+/// Handlers get it as `ConnectInfo<PeerCerts>`.
+#[derive(Clone, Default)]
+pub struct PeerCerts(pub Option<Arc<Vec<Certificate>>>);
+
+impl Connected<&TlsStream<TcpStream>> for PeerCerts {
+    fn connect_info(stream: &TlsStream<TcpStream>) -> Self {
+        Self(tls::peer_certs(stream).map(Arc::new))
+    }
+}
+
+impl Connected<&AddrStream> for PeerCerts {
+    fn connect_info(_: &AddrStream) -> Self {
+        Self(None)
+    }
+}
+
+/// Plain HTTP endpoints for orchestrators and scrapers: `/healthz` answers as long as the process
+/// is up, `/readyz` only while Redis is reachable, and `/metrics` serves Prometheus metrics.
+pub fn router(health: Health, with_metrics: bool) -> Router {
+    let router = Router::new()
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(readyz))
+        .with_state(health);
+
+    if with_metrics {
+        router.route("/metrics", get(render_metrics))
+    } else {
+        router
+    }
+}
+
+async fn readyz(State(health): State<Health>) -> impl IntoResponse {
+    if health.is_ready() {
+        (StatusCode::OK, "ready")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "redis unreachable")
+    }
+}
+
+async fn render_metrics() -> impl IntoResponse {
+    let (content_type, body) = metrics::render();
+    ([(CONTENT_TYPE, content_type)], body)
+}
+
+/// Serves `router` on `address`, over TLS when given it.
+pub async fn serve(router: Router, address: SocketAddr, tls: Option<Arc<Tls>>) {
+    let service = router.into_make_service_with_connect_info::<PeerCerts>();
+    let served = match tls {
+        Some(tls) => {
+            let listener = TcpListener::bind(address)
+                .await
+                .expect("Failed to bind HTTP port!");
+            let incoming = hyper::server::accept::from_stream(tls.incoming(listener));
+            axum::Server::builder(incoming).serve(service).await
+        }
+        None => axum::Server::bind(&address).serve(service).await,
+    };
+
+    if let Err(e) = served {
+        error!(error = %e, "HTTP server failed");
+    }
+}
@@ -0,0 +1,287 @@
+use redis::{Commands, ConnectionLike, RedisResult, Script};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::Status;
+
+use crate::api::meta::{now_ms, Meta};
+use crate::api::slots;
+use crate::api::tags;
+
+/// A string value with the memcached flags and CAS version kept in its metadata.
+pub struct Item {
+    pub value: Vec<u8>,
+    pub flags: u32,
+    pub cas: u64,
+}
+
+/// How a store treats the key's current value.
+#[derive(Clone, Copy)]
+pub enum StoreMode {
+    Set,
+    Add,
+    Replace,
+    /// Only store while the key's CAS version is still the given one.
+    Cas(u64),
+}
+
+pub enum StoreOutcome {
+    /// The item was stored, with its new CAS version.
+    Stored(u64),
+    NotStored,
+    Exists,
+    NotFound,
+}
+
+pub enum CountOutcome {
+    Value(u64),
+    NotFound,
+    NonNumeric,
+}
+
+pub struct StoreItem {
+    pub key: String,
+    pub value: Vec<u8>,
+    pub flags: u32,
+    pub hard_ttl_ms: Option<u64>,
+    pub mode: StoreMode,
+}
+
+pub struct CountItem {
+    pub key: String,
+    pub delta: u64,
+    pub decrement: bool,
+}
+
+pub struct TouchItem {
+    pub key: String,
+    pub hard_ttl_ms: Option<u64>,
+}
+
+/// Reads a value with its flags and CAS version. Keys written without a CAS version, e.g. by the
+/// `SET` RPC, are given one on the way out so they can be compared against later.
+///
+/// KEYS[1] is the key and KEYS[2] its metadata, ARGV[1] a fresh CAS version.
+const GET_SCRIPT: &str = r"
+local value = redis.call('GET', KEYS[1])
+if not value then
+  return false
+end
+local fields = redis.call('HMGET', KEYS[2], 'flags', 'cas')
+local cas = fields[2]
+if not cas then
+  cas = ARGV[1]
+  if redis.call('EXISTS', KEYS[2]) == 1 then
+    redis.call('HSET', KEYS[2], 'cas', cas)
+  end
+end
+return {value, fields[1] or '0', cas}
+";
+
+/// Writes a value and its metadata the way `Meta::write` does, plus the flags and a CAS version
+/// above the previous one, unless the mode's condition on the current value fails. Whatever else
+/// the metadata held, like a soft TTL or content type, goes.
+///
+/// KEYS[1] is the key and KEYS[2] its metadata. ARGV[1] is the mode, ARGV[2] the value, ARGV[3]
+/// the flags, ARGV[4] the hard TTL in ms (empty for none), ARGV[5] the expected CAS version,
+/// ARGV[6] the write time in ms and ARGV[7] a fresh CAS version.
+const STORE_SCRIPT: &str = r"
+local mode = ARGV[1]
+local exists = redis.call('EXISTS', KEYS[1]) == 1
+local old = redis.call('HGET', KEYS[2], 'cas')
+if mode == 'add' and exists then
+  return 'NOT_STORED'
+elseif mode == 'replace' and not exists then
+  return 'NOT_STORED'
+elseif mode == 'cas' then
+  if not exists then
+    return 'NOT_FOUND'
+  elseif old ~= ARGV[5] then
+    return 'EXISTS'
+  end
+end
+local cas = ARGV[7]
+if old and tonumber(cas) <= tonumber(old) then
+  cas = string.format('%.0f', tonumber(old) + 1)
+end
+redis.call('SET', KEYS[1], ARGV[2])
+redis.call('DEL', KEYS[2])
+redis.call('HSET', KEYS[2], 'stored_at', ARGV[6], 'flags', ARGV[3], 'cas', cas)
+local ttl = tonumber(ARGV[4])
+if ttl then
+  redis.call('HSET', KEYS[2], 'hard_ttl_ms', ttl)
+  redis.call('PEXPIRE', KEYS[1], ttl)
+  redis.call('PEXPIRE', KEYS[2], ttl)
+end
+return cas
+";
+
+/// Moves the hard TTL of a key and its metadata, or drops it when ARGV[1] is empty.
+/// Returns 0 when the key does not exist.
+const TOUCH_SCRIPT: &str = r"
+if redis.call('EXISTS', KEYS[1]) == 0 then
+  return 0
+end
+local ttl = tonumber(ARGV[1])
+local has_meta = redis.call('EXISTS', KEYS[2]) == 1
+if ttl then
+  redis.call('PEXPIRE', KEYS[1], ttl)
+  if has_meta then
+    redis.call('HSET', KEYS[2], 'hard_ttl_ms', ttl)
+    redis.call('PEXPIRE', KEYS[2], ttl)
+  end
+else
+  redis.call('PERSIST', KEYS[1])
+  if has_meta then
+    redis.call('HDEL', KEYS[2], 'hard_ttl_ms')
+    redis.call('PERSIST', KEYS[2])
+  end
+end
+return 1
+";
+
+/// CAS versions are the write time in microseconds, bumped past the previous version when two
+/// writes land within the same microsecond.
+fn fresh_cas() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or_default()
+}
+
+pub fn get<C: ConnectionLike>(con: &mut C, keys: &[&str]) -> RedisResult<Vec<Option<Item>>> {
+    let cas = fresh_cas();
+    let results: Vec<Option<(Vec<u8>, u32, u64)>> = slots::split(keys, |keys| {
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.cmd("EVAL")
+                .arg(GET_SCRIPT)
+                .arg(2)
+                .arg(*key)
+                .arg(Meta::key(key))
+                .arg(cas);
+        }
+        pipe.query(con)
+    })?;
+    Ok(results
+        .into_iter()
+        .map(|item| item.map(|(value, flags, cas)| Item { value, flags, cas }))
+        .collect())
+}
+
+/// Stores an item in place of the key's value, metadata and tags, like the `SET` RPC.
+pub fn store<C: ConnectionLike>(
+    con: &mut C,
+    key: &str,
+    item: &StoreItem,
+) -> RedisResult<StoreOutcome> {
+    let (mode, expected) = match item.mode {
+        StoreMode::Set => ("set", 0),
+        StoreMode::Add => ("add", 0),
+        StoreMode::Replace => ("replace", 0),
+        StoreMode::Cas(cas) => ("cas", cas),
+    };
+    let hard_ttl_ms = item
+        .hard_ttl_ms
+        .map(|ttl| ttl.to_string())
+        .unwrap_or_default();
+
+    let stored: String = Script::new(STORE_SCRIPT)
+        .key(key)
+        .key(Meta::key(key))
+        .arg(mode)
+        .arg(&item.value)
+        .arg(item.flags)
+        .arg(hard_ttl_ms)
+        .arg(expected)
+        .arg(now_ms())
+        .arg(fresh_cas())
+        .invoke(con)?;
+
+    let outcome = match stored.as_str() {
+        "NOT_STORED" => StoreOutcome::NotStored,
+        "EXISTS" => StoreOutcome::Exists,
+        "NOT_FOUND" => StoreOutcome::NotFound,
+        cas => StoreOutcome::Stored(cas.parse().unwrap_or_default()),
+    };
+    if let StoreOutcome::Stored(_) = outcome {
+        tags::write(con, key, &[], item.hard_ttl_ms)?;
+    }
+    Ok(outcome)
+}
+
+/// Deletes a key with its metadata and drops it from its tags.
+pub fn delete<C: ConnectionLike>(con: &mut C, key: &str) -> RedisResult<bool> {
+    let mut pipe = redis::pipe();
+    pipe.atomic().del(key).del(Meta::key(key)).ignore();
+
+    let (deleted,): (u64,) = pipe.query(con)?;
+    tags::write(con, key, &[], None)?;
+    Ok(deleted > 0)
+}
+
+/// Adds to or subtracts from a decimal value like memcached does: increments wrap around at
+/// 2^64 and decrements stop at 0. The value keeps its TTL. Values are read through `decode` and
+/// written through `encode`, which undo and redo any compression and encryption, and a value
+/// `decode` fails on is left as it is with the error returned.
+pub fn count<C, D, E>(
+    con: &mut C,
+    key: &str,
+    item: &CountItem,
+    decode: D,
+    encode: E,
+) -> RedisResult<Result<CountOutcome, Status>>
+where
+    C: ConnectionLike,
+    D: Fn(Vec<u8>) -> Result<Vec<u8>, Status>,
+    E: Fn(Vec<u8>) -> Vec<u8>,
+{
+    let meta_key = Meta::key(key);
+
+    redis::transaction(con, &[key, meta_key.as_str()], |con, pipe| {
+        let value = match con.get::<_, Option<Vec<u8>>>(key)?.map(&decode) {
+            Some(Ok(value)) => value,
+            Some(Err(status)) => return Ok(Some(Err(status))),
+            None => return Ok(Some(Ok(CountOutcome::NotFound))),
+        };
+        let parsed = std::str::from_utf8(&value)
+            .ok()
+            .and_then(|value| value.trim_end().parse::<u64>().ok());
+        let Some(current) = parsed else {
+            return Ok(Some(Ok(CountOutcome::NonNumeric)));
+        };
+        let next = if item.decrement {
+            current.saturating_sub(item.delta)
+        } else {
+            current.wrapping_add(item.delta)
+        };
+
+        pipe.cmd("SET")
+            .arg(key)
+            .arg(encode(next.to_string().into_bytes()))
+            .arg("KEEPTTL")
+            .ignore();
+        if con.exists(&meta_key)? {
+            let old: Option<u64> = con.hget(&meta_key, "cas")?;
+            let cas = fresh_cas().max(old.unwrap_or_default() + 1);
+            pipe.hset(&meta_key, "stored_at", now_ms())
+                .ignore()
+                .hset(&meta_key, "cas", cas)
+                .ignore();
+        }
+
+        let done: Option<()> = pipe.query(con)?;
+        Ok(done.map(|_| Ok(CountOutcome::Value(next))))
+    })
+}
+
+pub fn touch<C: ConnectionLike>(con: &mut C, key: &str, item: &TouchItem) -> RedisResult<bool> {
+    let hard_ttl_ms = item
+        .hard_ttl_ms
+        .map(|ttl| ttl.to_string())
+        .unwrap_or_default();
+
+    Script::new(TOUCH_SCRIPT)
+        .key(key)
+        .key(Meta::key(key))
+        .arg(hard_ttl_ms)
+        .invoke(con)
+}
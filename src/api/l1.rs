@@ -0,0 +1,303 @@
+use moka::sync::Cache;
+use moka::Expiry;
+use redis::{ConnectionInfo, ErrorKind, RedisError, RedisResult};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+use crate::api::meta::{now_ms, Meta};
+use crate::api::pool::{self, RedisPool};
+use crate::config::L1Config;
+
+/// Where Redis sends the names of changed keys to a RESP2 connection that tracking is redirected
+/// to.
+const INVALIDATE_CHANNEL: &str = "__redis__:invalidate";
+
+/// How long the subscribers wait for an invalidation before checking the tracking connections,
+/// and how often the cluster's masters are looked up again.
+const HEARTBEAT: Duration = Duration::from_secs(5);
+
+/// How long to wait before subscribing again after losing a connection.
+const RETRY: Duration = Duration::from_secs(1);
+
+/// Bookkeeping counted against `max_bytes` on top of each entry's key and value.
+const ENTRY_OVERHEAD: usize = 64;
+
+/// A value read from Redis, decoded, with the metadata of its key.
+#[derive(Clone)]
+pub struct Entry {
+    pub value: Vec<u8>,
+    pub meta: Option<Meta>,
+}
+
+/// Expires entries after the configured TTL, or sooner when their key's hard TTL runs out first.
+struct EntryExpiry {
+    ttl: Duration,
+}
+
+/// An entry's pool, as its index in `Tenants::pools`, and its Redis key there.
+type EntryKey = (usize, String);
+
+impl Expiry<EntryKey, Entry> for EntryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &EntryKey,
+        entry: &Entry,
+        _now: Instant,
+    ) -> Option<Duration> {
+        let remaining = entry.meta.and_then(|meta| {
+            let expires_at = meta.stored_at + meta.hard_ttl_ms?;
+            Some(Duration::from_millis(expires_at.saturating_sub(now_ms())))
+        });
+        Some(remaining.map_or(self.ttl, |remaining| remaining.min(self.ttl)))
+    }
+}
+
+/// In-process cache of string values in front of Redis, see `L1Config`. Entries are keyed by
+/// pool and Redis key and dropped when that pool's Redis reports the key changed, and the cache
+/// is only used while every pool's invalidations are being received.
+pub struct L1 {
+    cache: Cache<EntryKey, Entry>,
+    namespaces: Vec<String>,
+    /// Bumped on every invalidation, so values read before one are not cached after it.
+    epoch: AtomicU64,
+    subscribed: AtomicUsize,
+    pools: usize,
+}
+
+impl L1 {
+    pub fn from_config(config: &L1Config, pools: usize) -> Option<Self> {
+        if config.namespaces.is_empty() {
+            return None;
+        }
+
+        let cache = Cache::builder()
+            .max_capacity(config.max_bytes)
+            .weigher(|(_, key): &EntryKey, entry: &Entry| {
+                (key.len() + entry.value.len() + ENTRY_OVERHEAD)
+                    .try_into()
+                    .unwrap_or(u32::MAX)
+            })
+            .expire_after(EntryExpiry {
+                ttl: Duration::from_millis(config.ttl_ms),
+            })
+            .support_invalidation_closures()
+            .build();
+
+        Some(Self {
+            cache,
+            namespaces: config.namespaces.clone(),
+            epoch: AtomicU64::new(0),
+            subscribed: AtomicUsize::new(0),
+            pools,
+        })
+    }
+
+    /// Whether values of `name`, the key as its tenant knows it, are kept.
+    pub fn caches(&self, name: &str) -> bool {
+        self.namespaces
+            .iter()
+            .any(|prefix| name.starts_with(prefix.as_str()))
+    }
+
+    fn is_live(&self) -> bool {
+        self.subscribed.load(Ordering::SeqCst) == self.pools
+    }
+
+    /// To be taken before reading from Redis and handed back to `insert`.
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::SeqCst)
+    }
+
+    /// The entry for `key` on the pool `pool_id` of `Tenants::pools`.
+    pub fn get(&self, pool_id: usize, key: &str) -> Option<Entry> {
+        match self.is_live() {
+            true => self.cache.get(&(pool_id, key.to_string())),
+            false => None,
+        }
+    }
+
+    /// Caches a value read from Redis, unless an invalidation came in since `epoch` was taken and
+    /// may have been for it.
+    pub fn insert(&self, pool_id: usize, key: &str, entry: Entry, epoch: u64) {
+        if !self.is_live() || self.epoch() != epoch {
+            return;
+        }
+
+        let key = (pool_id, key.to_string());
+        self.cache.insert(key.clone(), entry);
+        // An invalidation bumps the epoch before evicting, so one that raced the insert either
+        // evicts the entry itself or is seen here.
+        if self.epoch() != epoch {
+            self.cache.invalidate(&key);
+        }
+    }
+
+    pub fn invalidate<K: AsRef<str>>(&self, pool_id: usize, keys: &[K]) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        for key in keys {
+            self.cache.invalidate(&(pool_id, key.as_ref().to_string()));
+        }
+    }
+
+    /// Drops every entry of the pool `pool_id`.
+    fn clear(&self, pool_id: usize) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        let cleared = self
+            .cache
+            .invalidate_entries_if(move |(entry_pool, _), _| *entry_pool == pool_id);
+        if let Err(e) = cleared {
+            warn!(error = %e, "Failed to clear one pool from the L1 cache, clearing it all");
+            self.cache.invalidate_all();
+        }
+    }
+
+    /// Turns on broadcast client tracking on every master of the pool `pool_id`, and evicts what
+    /// their invalidations name from its entries until a connection fails or the cluster's
+    /// masters change. The pool only counts as subscribed while every master is.
+    fn listen(&self, pool_id: usize, pool: &RedisPool) -> RedisResult<()> {
+        let masters = || {
+            let mut con = pool
+                .get()
+                .map_err(|e| RedisError::from((ErrorKind::IoError, "pool", e.to_string())))?;
+            con.masters()
+        };
+        let nodes = masters()?;
+        let same_nodes = |current: &[ConnectionInfo]| {
+            let addresses = |infos: &[ConnectionInfo]| -> Vec<String> {
+                infos.iter().map(|info| info.addr.to_string()).collect()
+            };
+            addresses(current) == addresses(&nodes)
+        };
+
+        let stop = AtomicBool::new(false);
+        let (events, received) = mpsc::channel();
+        let listened = thread::scope(|scope| {
+            let listeners: Vec<_> = nodes
+                .iter()
+                .map(|info| {
+                    let (events, stop) = (events.clone(), &stop);
+                    scope.spawn(move || {
+                        let listened = self.listen_node(pool_id, info, &events, stop);
+                        stop.store(true, Ordering::SeqCst);
+                        let _ = events.send(false);
+                        listened
+                    })
+                })
+                .collect();
+            drop(events);
+
+            let subscribed = (0..nodes.len()).all(|_| received.recv() == Ok(true));
+            let mut checked = Ok(());
+            if subscribed {
+                self.subscribed.fetch_add(1, Ordering::SeqCst);
+                info!("Receiving L1 cache invalidations");
+                while let Err(RecvTimeoutError::Timeout) = received.recv_timeout(HEARTBEAT) {
+                    match masters() {
+                        Ok(current) if same_nodes(&current) => {}
+                        Ok(_) => {
+                            info!("Redis masters changed, subscribing to them again");
+                            break;
+                        }
+                        Err(e) => {
+                            checked = Err(e);
+                            break;
+                        }
+                    }
+                }
+                // Invalidations may be missed from here on, so nothing cached so far can be
+                // trusted.
+                self.subscribed.fetch_sub(1, Ordering::SeqCst);
+            }
+            stop.store(true, Ordering::SeqCst);
+
+            let joined = listeners
+                .into_iter()
+                .try_for_each(|listener| listener.join().expect("L1 cache listener panicked."));
+            checked.and(joined)
+        });
+
+        self.clear(pool_id);
+        listened
+    }
+
+    /// Redirects one node's tracking to a connection subscribed to its invalidations, both opened
+    /// for the purpose, and reports on `events` once it is set up. Runs until `stop` is set or
+    /// either connection fails.
+    fn listen_node(
+        &self,
+        pool_id: usize,
+        info: &ConnectionInfo,
+        events: &Sender<bool>,
+        stop: &AtomicBool,
+    ) -> RedisResult<()> {
+        let mut subscriber = pool::connect(info)?;
+        let mut tracker = pool::connect(info)?;
+
+        let id: u64 = redis::cmd("CLIENT").arg("ID").query(&mut subscriber)?;
+        redis::cmd("CLIENT")
+            .arg("TRACKING")
+            .arg("ON")
+            .arg("REDIRECT")
+            .arg(id)
+            .arg("BCAST")
+            .query::<()>(&mut tracker)?;
+
+        let mut pubsub = subscriber.as_pubsub();
+        pubsub.subscribe(INVALIDATE_CHANNEL)?;
+        pubsub.set_read_timeout(Some(HEARTBEAT))?;
+        let _ = events.send(true);
+
+        let listened = loop {
+            match pubsub.get_message() {
+                // A nil payload means the whole DB was flushed.
+                Ok(message) => match message.get_payload::<Option<Vec<String>>>() {
+                    Ok(Some(keys)) => self.invalidate(pool_id, &keys),
+                    Ok(None) => self.clear(pool_id),
+                    Err(e) => break Err(e),
+                },
+                Err(e) if e.is_timeout() => {
+                    if stop.load(Ordering::SeqCst) {
+                        break Ok(());
+                    }
+                    if let Err(e) = redis::cmd("PING").query::<()>(&mut tracker) {
+                        break Err(e);
+                    }
+                }
+                Err(e) => break Err(e),
+            }
+        };
+
+        let _ = redis::cmd("CLIENT")
+            .arg("TRACKING")
+            .arg("OFF")
+            .query::<()>(&mut tracker);
+        listened
+    }
+
+    /// Listens for invalidations on each of `Tenants::pools` in the background, subscribing again
+    /// whenever a connection fails.
+    pub fn spawn_invalidation(self: Arc<Self>, pools: Vec<Arc<RedisPool>>) {
+        for (pool_id, pool) in pools.into_iter().enumerate() {
+            let l1 = self.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (l1, pool) = (l1.clone(), pool.clone());
+                    let listened =
+                        tokio::task::spawn_blocking(move || l1.listen(pool_id, &pool)).await;
+
+                    match listened {
+                        Ok(Ok(())) => {}
+                        Ok(Err(e)) => warn!(error = %e, "Lost L1 cache invalidations"),
+                        Err(e) => error!(error = %e, "L1 cache invalidation panicked"),
+                    }
+
+                    tokio::time::sleep(RETRY).await;
+                }
+            });
+        }
+    }
+}
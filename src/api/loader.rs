@@ -0,0 +1,218 @@
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Request, StatusCode};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::api::compression::Compressor;
+use crate::api::encoding;
+use crate::api::encryption::Encryption;
+use crate::api::meta::Meta;
+use crate::api::tags;
+use crate::api::tenant::{Tenant, TENANT_HEADER};
+use crate::config::LoaderConfig;
+
+const REFRESH_PREFIX: &str = "mrcache:refresh:";
+
+/// Everything but the unreserved characters of RFC 3986 less `.`, so that a key is always a single
+/// path segment and never `.` or `..`.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'_').remove(b'~');
+
+type LoadResult = Result<Option<Vec<u8>>, Box<dyn Error + Send + Sync>>;
+
+/// Fetches fresh values for stale keys from an HTTP origin. `GET {url}{key}` answers 200 with the
+/// new value as the body, or 404 if the origin no longer has one. Keys are sent as the tenant
+/// knows them, percent-encoded, with the tenant in the same header mrCache takes it from.
+pub struct Loader {
+    url: String,
+    timeout: Duration,
+    client: Client<HttpConnector>,
+    compressor: Arc<Compressor>,
+    encryption: Arc<Encryption>,
+}
+
+impl Loader {
+    pub fn from_config(
+        config: &LoaderConfig,
+        compressor: Arc<Compressor>,
+        encryption: Arc<Encryption>,
+    ) -> Option<Self> {
+        config.url.as_ref().map(|url| Self {
+            url: url.clone(),
+            timeout: Duration::from_millis(config.timeout_ms),
+            client: Client::new(),
+            compressor,
+            encryption,
+        })
+    }
+
+    async fn load(&self, tenant: Option<&str>, key: &str) -> LoadResult {
+        let url = format!("{}{}", self.url, utf8_percent_encode(key, PATH_SEGMENT));
+        let mut request = Request::get(url);
+        if let Some(name) = tenant {
+            request = request.header(TENANT_HEADER, name);
+        }
+
+        let request = request.body(Body::empty())?;
+        let response = tokio::time::timeout(self.timeout, self.client.request(request)).await??;
+
+        match response.status() {
+            StatusCode::OK => {
+                let body = hyper::body::to_bytes(response.into_body()).await?;
+                Ok(Some(body.to_vec()))
+            }
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(format!("loader answered {}", status).into()),
+        }
+    }
+
+    /// Reloads a stale key and stores it with the TTLs, content type and tags it was originally set
+    /// with. `key` is where the value is stored and `name` the key as the tenant knows it. Only
+    /// the first instance to claim the key within the loader timeout does the refresh.
+    pub async fn refresh(
+        self: Arc<Self>,
+        tenant: Arc<Tenant>,
+        key: String,
+        name: String,
+        meta: Meta,
+    ) {
+        let claimed = tenant
+            .pool
+            .get()
+            .map_err(|e| e.to_string())
+            .and_then(|mut con| {
+                redis::cmd("SET")
+                    .arg(REFRESH_PREFIX.to_string() + &key)
+                    .arg(std::process::id())
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(self.timeout.as_millis() as u64)
+                    .query::<Option<String>>(&mut *con)
+                    .map_err(|e| e.to_string())
+            });
+
+        match claimed {
+            Ok(Some(_)) => {}
+            Ok(None) => return,
+            Err(e) => {
+                warn!(key, error = %e, "Failed to claim refresh");
+                return;
+            }
+        }
+
+        let loaded = self.load(tenant.name(), &name).await.and_then(|value| {
+            value
+                .map(|value| encoding::validate(meta.content_type, value))
+                .transpose()
+                .map_err(|status| status.message().into())
+        });
+        let value = match loaded {
+            Ok(Some(value)) => {
+                self.encryption
+                    .seal(&key, None, self.compressor.compress(&name, value))
+            }
+            Ok(None) => {
+                debug!(key, "Loader has no value, serving stale until hard TTL");
+                return;
+            }
+            Err(e) => {
+                warn!(key, error = %e, "Failed to load");
+                return;
+            }
+        };
+
+        let stored = tenant
+            .pool
+            .get()
+            .map_err(|e| e.to_string())
+            .and_then(|mut con| {
+                let mut pipe = redis::pipe();
+                pipe.atomic().set(&key, value).ignore();
+                Meta::new(meta.soft_ttl_ms, meta.hard_ttl_ms)
+                    .with_content_type(meta.content_type)
+                    .write(&mut pipe, &key);
+                pipe.query::<()>(&mut *con)
+                    .and_then(|()| tags::renew(&mut *con, &key, meta.hard_ttl_ms))
+                    .map_err(|e| e.to_string())
+            });
+
+        if let Err(e) = stored {
+            warn!(key, error = %e, "Failed to store refreshed value");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CompressionConfig, EncryptionConfig};
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Response, Server};
+    use std::convert::Infallible;
+
+    /// An origin answering 404 for `/missing`, 500 for `/broken` and otherwise 200 with the path
+    /// and tenant it was asked for.
+    fn origin() -> String {
+        let make = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|request: Request<Body>| async move {
+                let status = match request.uri().path() {
+                    "/missing" => StatusCode::NOT_FOUND,
+                    "/broken" => StatusCode::INTERNAL_SERVER_ERROR,
+                    _ => StatusCode::OK,
+                };
+                let tenant = request
+                    .headers()
+                    .get(TENANT_HEADER)
+                    .map_or("-", |name| name.to_str().unwrap());
+                let body = format!("{} {}", request.uri().path(), tenant);
+                Ok::<_, Infallible>(
+                    Response::builder()
+                        .status(status)
+                        .body(Body::from(body))
+                        .unwrap(),
+                )
+            }))
+        });
+        let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+        let url = format!("http://{}/", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    fn loader(url: String) -> Loader {
+        let config = LoaderConfig {
+            url: Some(url),
+            ..LoaderConfig::default()
+        };
+        let compressor = Arc::new(Compressor::new(&CompressionConfig::default()));
+        let encryption = Arc::new(Encryption::new(&EncryptionConfig::default()));
+        Loader::from_config(&config, compressor, encryption).unwrap()
+    }
+
+    #[test]
+    fn is_off_without_a_url() {
+        let compressor = Arc::new(Compressor::new(&CompressionConfig::default()));
+        let encryption = Arc::new(Encryption::new(&EncryptionConfig::default()));
+        assert!(Loader::from_config(&LoaderConfig::default(), compressor, encryption).is_none());
+    }
+
+    #[tokio::test]
+    async fn loads_keys_as_single_path_segments_with_the_tenant() {
+        let loader = loader(origin());
+
+        let value = loader.load(Some("acme"), "user:1").await.unwrap();
+        assert_eq!(value.as_deref(), Some(&b"/user%3A1 acme"[..]));
+        let value = loader.load(None, "../a b").await.unwrap();
+        assert_eq!(value.as_deref(), Some(&b"/%2E%2E%2Fa%20b -"[..]));
+    }
+
+    #[tokio::test]
+    async fn tells_missing_values_from_failures() {
+        let loader = loader(origin());
+
+        assert_eq!(loader.load(None, "missing").await.unwrap(), None);
+        assert!(loader.load(None, "broken").await.is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use redis::{ConnectionLike, RedisResult};
+use std::time::Duration;
+
+use crate::api::slots;
+use crate::config::RecomputeLockConfig;
+
+const LOCK_PREFIX: &str = "mrcache:lock:";
+
+/// Cross-instance lock on a missing key. The instance that takes the lock returns the miss to its
+/// caller so it can recompute the value, the others poll for the value until `wait` runs out.
+/// A `SET` of the key releases the lock, otherwise it expires after `ttl`.
+pub struct RecomputeLock {
+    pub ttl: Duration,
+    pub wait: Duration,
+    pub poll: Duration,
+}
+
+impl RecomputeLock {
+    pub fn from_config(config: &RecomputeLockConfig) -> Option<Self> {
+        config.enabled.then(|| Self {
+            ttl: Duration::from_millis(config.ttl_ms),
+            wait: Duration::from_millis(config.wait_ms),
+            poll: Duration::from_millis(config.poll_ms),
+        })
+    }
+
+    fn lock_key(key: &str) -> String {
+        slots::companion(LOCK_PREFIX, key)
+    }
+
+    /// Tries to take the lock for every key, returning whether each one was acquired.
+    pub fn try_acquire<C: ConnectionLike>(
+        &self,
+        con: &mut C,
+        keys: &[&str],
+    ) -> RedisResult<Vec<bool>> {
+        let results: Vec<Option<String>> = slots::split(keys, |keys| {
+            let mut pipe = redis::pipe();
+            for key in keys {
+                pipe.cmd("SET")
+                    .arg(Self::lock_key(key))
+                    .arg(std::process::id())
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(self.ttl.as_millis() as u64);
+            }
+            pipe.query(con)
+        })?;
+
+        Ok(results.into_iter().map(|r| r.is_some()).collect())
+    }
+
+    pub fn release<C: ConnectionLike>(&self, con: &mut C, keys: &[&str]) -> RedisResult<()> {
+        let lock_keys: Vec<String> = keys.iter().map(|k| Self::lock_key(k)).collect();
+        for group in slots::groups(&lock_keys) {
+            let group: Vec<&str> = group.iter().map(|&i| lock_keys[i].as_str()).collect();
+            redis::cmd("DEL").arg(group).query::<()>(con)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Value;
+
+    /// A connection that records the commands sent to it and answers `SET`s with `replies`.
+    struct Recorder {
+        sent: Vec<String>,
+        replies: Vec<Value>,
+    }
+
+    impl ConnectionLike for Recorder {
+        fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+            self.sent.push(String::from_utf8_lossy(cmd).into_owned());
+            Ok(Value::Int(1))
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            cmd: &[u8],
+            offset: usize,
+            count: usize,
+        ) -> RedisResult<Vec<Value>> {
+            self.sent.push(String::from_utf8_lossy(cmd).into_owned());
+            Ok(self.replies.drain(..).skip(offset).take(count).collect())
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    fn lock() -> RecomputeLock {
+        RecomputeLock::from_config(&RecomputeLockConfig {
+            enabled: true,
+            ttl_ms: 5000,
+            ..RecomputeLockConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn is_off_unless_enabled() {
+        let config = RecomputeLockConfig {
+            enabled: false,
+            ..RecomputeLockConfig::default()
+        };
+        assert!(RecomputeLock::from_config(&config).is_none());
+    }
+
+    #[test]
+    fn acquires_only_the_keys_nobody_holds() {
+        let mut con = Recorder {
+            sent: Vec::new(),
+            replies: vec![Value::Okay, Value::Nil],
+        };
+        let acquired = lock().try_acquire(&mut con, &["a", "b"]).unwrap();
+
+        assert_eq!(acquired, vec![true, false]);
+        let sent = con.sent.concat();
+        assert!(sent.contains("mrcache:lock:a") && sent.contains("mrcache:lock:b"));
+        assert!(sent.contains("NX") && sent.contains("5000"));
+    }
+
+    #[test]
+    fn releases_by_deleting_the_lock_keys() {
+        let mut con = Recorder {
+            sent: Vec::new(),
+            replies: Vec::new(),
+        };
+        lock().release(&mut con, &["a", "b"]).unwrap();
+
+        assert_eq!(con.sent.len(), 1);
+        assert!(con.sent[0].contains("DEL"));
+        assert!(con.sent[0].contains("mrcache:lock:a") && con.sent[0].contains("mrcache:lock:b"));
+    }
+}
@@ -0,0 +1,1033 @@
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+};
+use tokio::io::{BufReader, BufWriter};
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use tonic::transport::Certificate;
+use tonic::{Code, Request, Status};
+use tracing::{debug, info_span, warn};
+
+use crate::api::auth::{Auth, Caller};
+use crate::api::client::MrCacheService;
+use crate::api::items::{
+    CountItem, CountOutcome, Item, StoreItem, StoreMode, StoreOutcome, TouchItem,
+};
+use crate::api::meta::now_ms;
+use crate::api::metrics;
+use crate::api::mr_cache::{Key, Keys};
+use crate::api::tls::{self, Tls};
+use crate::config::MemcachedConfig;
+
+/// Longest text command line, enough for a `get` of several maximum-length keys.
+const MAX_LINE_LEN: u64 = 8 * 1024;
+
+/// Longest key memcached accepts.
+const MAX_KEY_LEN: usize = 250;
+
+/// Expiration times up to 30 days are relative, later ones are Unix timestamps.
+const MAX_RELATIVE_EXPTIME: i64 = 30 * 24 * 60 * 60;
+
+const REQUEST_MAGIC: u8 = 0x80;
+const RESPONSE_MAGIC: u8 = 0x81;
+const HEADER_LEN: usize = 24;
+
+mod opcode {
+    pub const GET: u8 = 0x00;
+    pub const SET: u8 = 0x01;
+    pub const ADD: u8 = 0x02;
+    pub const REPLACE: u8 = 0x03;
+    pub const DELETE: u8 = 0x04;
+    pub const INCREMENT: u8 = 0x05;
+    pub const DECREMENT: u8 = 0x06;
+    pub const QUIT: u8 = 0x07;
+    pub const GETQ: u8 = 0x09;
+    pub const NOOP: u8 = 0x0a;
+    pub const VERSION: u8 = 0x0b;
+    pub const GETK: u8 = 0x0c;
+    pub const GETKQ: u8 = 0x0d;
+    pub const SETQ: u8 = 0x11;
+    pub const ADDQ: u8 = 0x12;
+    pub const REPLACEQ: u8 = 0x13;
+    pub const DELETEQ: u8 = 0x14;
+    pub const INCREMENTQ: u8 = 0x15;
+    pub const DECREMENTQ: u8 = 0x16;
+    pub const QUITQ: u8 = 0x17;
+    pub const TOUCH: u8 = 0x1c;
+    pub const SASL_LIST_MECHS: u8 = 0x20;
+    pub const SASL_AUTH: u8 = 0x21;
+}
+
+mod status {
+    pub const OK: u16 = 0x00;
+    pub const KEY_NOT_FOUND: u16 = 0x01;
+    pub const KEY_EXISTS: u16 = 0x02;
+    pub const VALUE_TOO_LARGE: u16 = 0x03;
+    pub const INVALID_ARGUMENTS: u16 = 0x04;
+    pub const NOT_STORED: u16 = 0x05;
+    pub const NON_NUMERIC: u16 = 0x06;
+    pub const AUTH_ERROR: u16 = 0x20;
+    pub const UNKNOWN_COMMAND: u16 = 0x81;
+    pub const OUT_OF_MEMORY: u16 = 0x82;
+    pub const INTERNAL_ERROR: u16 = 0x84;
+}
+
+/// Turns a memcached expiration time into a hard TTL. Times in the past expire the key at once.
+fn hard_ttl_ms(exptime: i64) -> Option<u64> {
+    match exptime {
+        0 => None,
+        exptime if exptime < 0 => Some(0),
+        exptime if exptime <= MAX_RELATIVE_EXPTIME => Some(exptime as u64 * 1000),
+        exptime => Some(
+            (exptime as u64)
+                .saturating_mul(1000)
+                .saturating_sub(now_ms()),
+        ),
+    }
+}
+
+/// Times a call and counts it by its status under the memcached command's name.
+fn call<T, R>(
+    command: &str,
+    request: Result<Request<T>, Status>,
+    call: impl FnOnce(Request<T>) -> Result<R, Status>,
+) -> Result<R, Status> {
+    let _span = info_span!("memcached", command).entered();
+    let start = Instant::now();
+    let result = request.and_then(call);
+    let code = result.as_ref().map_or_else(Status::code, |_| Code::Ok);
+    metrics::observe_rpc(command, start, code);
+
+    result
+}
+
+fn text_error(status: &Status) -> String {
+    let kind = match status.code() {
+        Code::InvalidArgument | Code::Unauthenticated | Code::PermissionDenied => "CLIENT_ERROR",
+        _ => "SERVER_ERROR",
+    };
+    format!(
+        "{} {}\r\n",
+        kind,
+        status.message().replace(['\r', '\n'], " ")
+    )
+}
+
+fn binary_status(status: &Status) -> u16 {
+    match status.code() {
+        Code::Unauthenticated | Code::PermissionDenied => status::AUTH_ERROR,
+        Code::InvalidArgument => status::INVALID_ARGUMENTS,
+        Code::ResourceExhausted => status::OUT_OF_MEMORY,
+        _ => status::INTERNAL_ERROR,
+    }
+}
+
+/// A binary protocol request, with the fields of its header that matter here.
+struct Packet {
+    opcode: u8,
+    opaque: u32,
+    cas: u64,
+    extras: Vec<u8>,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Packet {
+    fn extra_u32(&self, at: usize) -> Option<u32> {
+        let bytes = self.extras.get(at..at + 4)?;
+        Some(u32::from_be_bytes(bytes.try_into().ok()?))
+    }
+
+    fn extra_u64(&self, at: usize) -> Option<u64> {
+        let bytes = self.extras.get(at..at + 8)?;
+        Some(u64::from_be_bytes(bytes.try_into().ok()?))
+    }
+
+    fn key(&self) -> Result<String, Status> {
+        String::from_utf8(self.key.clone())
+            .ok()
+            .filter(|key| !key.is_empty() && key.len() <= MAX_KEY_LEN)
+            .ok_or_else(|| Status::invalid_argument("Invalid key"))
+    }
+}
+
+/// A binary protocol response.
+struct Reply {
+    status: u16,
+    cas: u64,
+    extras: Vec<u8>,
+    key: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Reply {
+    fn status(status: u16) -> Self {
+        Self {
+            status,
+            cas: 0,
+            extras: Vec::new(),
+            key: Vec::new(),
+            value: Vec::new(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        Self {
+            value: message.as_bytes().to_vec(),
+            ..Self::status(status)
+        }
+    }
+
+    fn value(value: impl Into<Vec<u8>>) -> Self {
+        Self {
+            value: value.into(),
+            ..Self::status(status::OK)
+        }
+    }
+
+    fn encode(&self, request: &Packet, out: &mut Vec<u8>) {
+        let body_len = self.extras.len() + self.key.len() + self.value.len();
+        out.push(RESPONSE_MAGIC);
+        out.push(request.opcode);
+        out.extend_from_slice(&(self.key.len() as u16).to_be_bytes());
+        out.push(self.extras.len() as u8);
+        out.push(0);
+        out.extend_from_slice(&self.status.to_be_bytes());
+        out.extend_from_slice(&(body_len as u32).to_be_bytes());
+        out.extend_from_slice(&request.opaque.to_be_bytes());
+        out.extend_from_slice(&self.cas.to_be_bytes());
+        out.extend_from_slice(&self.extras);
+        out.extend_from_slice(&self.key);
+        out.extend_from_slice(&self.value);
+    }
+}
+
+/// Reads a text command line without its `\r\n`, or `None` once the client has hung up.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "line too long"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    Ok(Some(line))
+}
+
+/// Reads `len` bytes and the `\r\n` after them, or `None` when they do not end that way.
+async fn read_data<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    len: usize,
+) -> io::Result<Option<Vec<u8>>> {
+    let mut data = Vec::new();
+    reader.take(len as u64 + 2).read_to_end(&mut data).await?;
+    if data.len() != len + 2 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    if !data.ends_with(b"\r\n") {
+        return Ok(None);
+    }
+    data.truncate(len);
+
+    Ok(Some(data))
+}
+
+/// Serves memcached clients from the string keys of the gRPC service, with the same auth,
+/// tenants, metrics and Redis logic. Both the text and the binary protocol are spoken, picked by
+/// the first byte a client sends. Flags and CAS versions are kept in the keys' metadata.
+///
+/// With auth on, text clients log in the way memcached's own ASCII auth does, with a first `set`
+/// whose data is `<username> <password>`, and binary clients with SASL `PLAIN`. The username is
+/// the tenant and the password an API key or JWT, like RESP's `AUTH`.
+pub struct Memcached {
+    service: Arc<MrCacheService>,
+    auth: Auth,
+    max_value_bytes: usize,
+}
+
+impl Memcached {
+    pub fn new(service: Arc<MrCacheService>, auth: Auth, config: &MemcachedConfig) -> Arc<Self> {
+        Arc::new(Self {
+            service,
+            auth,
+            max_value_bytes: config.max_value_bytes,
+        })
+    }
+
+    pub async fn serve(self: Arc<Self>, address: SocketAddr, tls: Option<Arc<Tls>>) {
+        let listener = TcpListener::bind(address)
+            .await
+            .expect("Failed to bind memcached port!");
+
+        match tls {
+            Some(tls) => {
+                let mut incoming = tls.incoming(listener);
+                while let Some(stream) = incoming.next().await {
+                    let Ok(stream) = stream else { continue };
+                    let peer_certs = tls::peer_certs(&stream);
+                    tokio::spawn(self.clone().connection(stream, peer_certs));
+                }
+            }
+            None => loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(self.clone().connection(stream, None));
+                    }
+                    Err(e) => warn!(error = %e, "Failed to accept memcached connection"),
+                }
+            },
+        }
+    }
+
+    async fn connection<S>(self: Arc<Self>, stream: S, peer_certs: Option<Vec<Certificate>>)
+    where
+        S: AsyncRead + AsyncWrite,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        let mut writer = BufWriter::new(writer);
+        let mut caller = Caller::new(self.auth.clone(), peer_certs);
+
+        let binary = match reader.fill_buf().await {
+            Ok([first, ..]) => *first == REQUEST_MAGIC,
+            _ => return,
+        };
+        let served = if binary {
+            self.binary(&mut reader, &mut writer, &mut caller).await
+        } else {
+            self.text(&mut reader, &mut writer, &mut caller).await
+        };
+
+        if let Err(e) = served {
+            debug!(error = %e, "Closing memcached connection");
+        }
+    }
+
+    async fn text<R, W>(
+        &self,
+        reader: &mut BufReader<R>,
+        writer: &mut W,
+        caller: &mut Caller,
+    ) -> io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        loop {
+            let line = match read_line(reader).await {
+                Ok(Some(line)) => line,
+                Ok(None) => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    writer.write_all(b"CLIENT_ERROR line too long\r\n").await?;
+                    return writer.flush().await;
+                }
+                Err(e) => return Err(e),
+            };
+            let line = String::from_utf8_lossy(&line);
+            let tokens: Vec<&str> = line.split(' ').filter(|t| !t.is_empty()).collect();
+            let Some((command, args)) = tokens.split_first() else {
+                continue;
+            };
+            if *command == "quit" {
+                return writer.flush().await;
+            }
+
+            let reply = self.text_command(command, args, reader, caller).await?;
+            if let Some(reply) = reply {
+                writer.write_all(&reply).await?;
+            }
+            if reader.buffer().is_empty() {
+                writer.flush().await?;
+            }
+        }
+    }
+
+    /// Answers one text command, or `None` for a successful `noreply` one.
+    async fn text_command<R>(
+        &self,
+        command: &str,
+        args: &[&str],
+        reader: &mut R,
+        caller: &mut Caller,
+    ) -> io::Result<Option<Vec<u8>>>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let bad_format = || Ok(Some(b"CLIENT_ERROR bad command line format\r\n".to_vec()));
+        let (args, noreply) = match args.split_last() {
+            Some((&"noreply", args)) => (args, true),
+            _ => (args, false),
+        };
+        if args.first().is_some_and(|key| key.len() > MAX_KEY_LEN) {
+            return bad_format();
+        }
+
+        let reply = match (command, args) {
+            ("get" | "gets", keys) if !keys.is_empty() => {
+                let keys = Keys {
+                    keys: keys
+                        .iter()
+                        .map(|key| Key {
+                            key: key.to_string(),
+                        })
+                        .collect(),
+                    ..Default::default()
+                };
+                let found = call(command, caller.request(keys.clone()), |r| {
+                    self.service.get_items(r)
+                });
+                return Ok(Some(match found {
+                    Ok(found) => text_values(&keys, found, command == "gets"),
+                    Err(status) => text_error(&status).into_bytes(),
+                }));
+            }
+            ("set" | "add" | "replace" | "cas", [key, flags, exptime, bytes, cas @ ..])
+                if cas.len() == usize::from(command == "cas") =>
+            {
+                let parsed = (
+                    flags.parse::<u32>(),
+                    exptime.parse::<i64>(),
+                    bytes.parse::<usize>(),
+                    cas.first().map(|cas| cas.parse::<u64>()).transpose(),
+                );
+                let (Ok(flags), Ok(exptime), Ok(bytes), Ok(cas)) = parsed else {
+                    return bad_format();
+                };
+                if bytes > self.max_value_bytes {
+                    tokio::io::copy(&mut reader.take(bytes as u64 + 2), &mut tokio::io::sink())
+                        .await?;
+                    return Ok(Some(
+                        b"SERVER_ERROR object too large for cache\r\n".to_vec(),
+                    ));
+                }
+                let Some(value) = read_data(reader, bytes).await? else {
+                    return Ok(Some(b"CLIENT_ERROR bad data chunk\r\n".to_vec()));
+                };
+
+                if !caller.is_authenticated() {
+                    return Ok(Some(text_login(caller, &value).into_bytes()));
+                }
+
+                let mode = match (command, cas) {
+                    ("add", _) => StoreMode::Add,
+                    ("replace", _) => StoreMode::Replace,
+                    (_, Some(cas)) => StoreMode::Cas(cas),
+                    _ => StoreMode::Set,
+                };
+                let item = StoreItem {
+                    key: key.to_string(),
+                    value,
+                    flags,
+                    hard_ttl_ms: hard_ttl_ms(exptime),
+                    mode,
+                };
+                call(command, caller.request(item), |r| {
+                    self.service.store_item(r)
+                })
+                .map(|stored| match stored {
+                    StoreOutcome::Stored(_) => "STORED",
+                    StoreOutcome::NotStored => "NOT_STORED",
+                    StoreOutcome::Exists => "EXISTS",
+                    StoreOutcome::NotFound => "NOT_FOUND",
+                })
+            }
+            ("delete", [key]) => {
+                let key = Key {
+                    key: key.to_string(),
+                };
+                call(command, caller.request(key), |r| {
+                    self.service.delete_item(r)
+                })
+                .map(|deleted| if deleted { "DELETED" } else { "NOT_FOUND" })
+            }
+            ("incr" | "decr", [key, delta]) => {
+                let Ok(delta) = delta.parse::<u64>() else {
+                    return Ok(Some(
+                        b"CLIENT_ERROR invalid numeric delta argument\r\n".to_vec(),
+                    ));
+                };
+                let item = CountItem {
+                    key: key.to_string(),
+                    delta,
+                    decrement: command == "decr",
+                };
+                let counted = call(command, caller.request(item), |r| {
+                    self.service.count_item(r)
+                });
+                let reply = match counted {
+                    Ok(CountOutcome::Value(_)) if noreply => return Ok(None),
+                    Ok(CountOutcome::Value(value)) => format!("{}\r\n", value),
+                    Ok(CountOutcome::NotFound) if noreply => return Ok(None),
+                    Ok(CountOutcome::NotFound) => "NOT_FOUND\r\n".to_string(),
+                    Ok(CountOutcome::NonNumeric) => {
+                        "CLIENT_ERROR cannot increment or decrement non-numeric value\r\n"
+                            .to_string()
+                    }
+                    Err(status) => text_error(&status),
+                };
+                return Ok(Some(reply.into_bytes()));
+            }
+            ("touch", [key, exptime]) => {
+                let Ok(exptime) = exptime.parse::<i64>() else {
+                    return bad_format();
+                };
+                let item = TouchItem {
+                    key: key.to_string(),
+                    hard_ttl_ms: hard_ttl_ms(exptime),
+                };
+                call(command, caller.request(item), |r| {
+                    self.service.touch_item(r)
+                })
+                .map(|touched| if touched { "TOUCHED" } else { "NOT_FOUND" })
+            }
+            ("version", []) => Ok("VERSION mrcache-0.1.0"),
+            ("get" | "gets" | "set" | "add" | "replace" | "cas" | "delete", _)
+            | ("incr" | "decr" | "touch" | "version", _) => return bad_format(),
+            _ => return Ok(Some(b"ERROR\r\n".to_vec())),
+        };
+
+        Ok(match reply {
+            Ok(_) if noreply => None,
+            Ok(reply) => Some(format!("{}\r\n", reply).into_bytes()),
+            Err(status) => Some(text_error(&status).into_bytes()),
+        })
+    }
+
+    async fn binary<R, W>(
+        &self,
+        reader: &mut BufReader<R>,
+        writer: &mut W,
+        caller: &mut Caller,
+    ) -> io::Result<()>
+    where
+        R: AsyncRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut out = Vec::new();
+        loop {
+            let mut header = [0; HEADER_LEN];
+            match reader.read_exact(&mut header).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            if header[0] != REQUEST_MAGIC {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "bad magic"));
+            }
+
+            let key_len = u16::from_be_bytes([header[2], header[3]]) as usize;
+            let extras_len = header[4] as usize;
+            let body_len = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+            let mut packet = Packet {
+                opcode: header[1],
+                opaque: u32::from_be_bytes(header[12..16].try_into().unwrap()),
+                cas: u64::from_be_bytes(header[16..24].try_into().unwrap()),
+                extras: Vec::new(),
+                key: Vec::new(),
+                value: Vec::new(),
+            };
+
+            out.clear();
+            if key_len + extras_len > body_len {
+                Reply::error(status::INVALID_ARGUMENTS, "Invalid arguments")
+                    .encode(&packet, &mut out);
+                writer.write_all(&out).await?;
+                return writer.flush().await;
+            }
+            if body_len - key_len - extras_len > self.max_value_bytes {
+                tokio::io::copy(&mut reader.take(body_len as u64), &mut tokio::io::sink()).await?;
+                Reply::error(status::VALUE_TOO_LARGE, "Too large").encode(&packet, &mut out);
+                writer.write_all(&out).await?;
+                continue;
+            }
+
+            let mut body = vec![0; body_len];
+            reader.read_exact(&mut body).await?;
+            packet.value = body.split_off(extras_len + key_len);
+            packet.key = body.split_off(extras_len);
+            packet.extras = body;
+
+            let quit = matches!(packet.opcode, opcode::QUIT | opcode::QUITQ);
+            if let Some(reply) = self.binary_command(&packet, caller) {
+                reply.encode(&packet, &mut out);
+                writer.write_all(&out).await?;
+            }
+            if quit {
+                return writer.flush().await;
+            }
+            if reader.buffer().is_empty() {
+                writer.flush().await?;
+            }
+        }
+    }
+
+    /// Answers one binary request, or `None` for a quiet one with nothing worth reporting.
+    fn binary_command(&self, packet: &Packet, caller: &mut Caller) -> Option<Reply> {
+        let quiet = matches!(
+            packet.opcode,
+            opcode::GETQ
+                | opcode::GETKQ
+                | opcode::SETQ
+                | opcode::ADDQ
+                | opcode::REPLACEQ
+                | opcode::DELETEQ
+                | opcode::INCREMENTQ
+                | opcode::DECREMENTQ
+                | opcode::QUITQ
+        );
+
+        let reply = match packet.opcode {
+            opcode::GET | opcode::GETQ | opcode::GETK | opcode::GETKQ => {
+                self.binary_get(packet, caller)
+            }
+            opcode::SET
+            | opcode::SETQ
+            | opcode::ADD
+            | opcode::ADDQ
+            | opcode::REPLACE
+            | opcode::REPLACEQ => self.binary_store(packet, caller),
+            opcode::DELETE | opcode::DELETEQ => {
+                let key = packet.key().map(|key| Key { key });
+                call("delete", key.and_then(|key| caller.request(key)), |r| {
+                    self.service.delete_item(r)
+                })
+                .map(|deleted| {
+                    if deleted {
+                        Reply::status(status::OK)
+                    } else {
+                        Reply::error(status::KEY_NOT_FOUND, "Not found")
+                    }
+                })
+            }
+            opcode::INCREMENT | opcode::INCREMENTQ | opcode::DECREMENT | opcode::DECREMENTQ => {
+                self.binary_count(packet, caller)
+            }
+            opcode::TOUCH => {
+                let item = packet.key().and_then(|key| {
+                    let exptime = packet
+                        .extra_u32(0)
+                        .ok_or_else(|| Status::invalid_argument("Missing expiration"))?;
+                    Ok(TouchItem {
+                        key,
+                        hard_ttl_ms: hard_ttl_ms(exptime as i64),
+                    })
+                });
+                call("touch", item.and_then(|item| caller.request(item)), |r| {
+                    self.service.touch_item(r)
+                })
+                .map(|touched| {
+                    if touched {
+                        Reply::status(status::OK)
+                    } else {
+                        Reply::error(status::KEY_NOT_FOUND, "Not found")
+                    }
+                })
+            }
+            opcode::QUIT | opcode::QUITQ | opcode::NOOP => Ok(Reply::status(status::OK)),
+            opcode::VERSION => Ok(Reply::value("mrcache-0.1.0")),
+            opcode::SASL_LIST_MECHS => Ok(Reply::value("PLAIN")),
+            opcode::SASL_AUTH => Ok(binary_login(packet, caller)),
+            _ => Ok(Reply::error(status::UNKNOWN_COMMAND, "Unknown command")),
+        };
+
+        match reply {
+            Ok(reply) if quiet && matches!(reply.status, status::OK | status::KEY_NOT_FOUND) => {
+                // Quiet gets only stay silent on a miss, the other quiet commands on success.
+                let is_get = matches!(packet.opcode, opcode::GETQ | opcode::GETKQ);
+                let silent = (reply.status == status::KEY_NOT_FOUND) == is_get;
+                (!silent).then_some(reply)
+            }
+            Ok(reply) => Some(reply),
+            Err(status) => Some(Reply::error(binary_status(&status), status.message())),
+        }
+    }
+
+    fn binary_get(&self, packet: &Packet, caller: &Caller) -> Result<Reply, Status> {
+        let keys = packet.key().map(|key| Keys {
+            keys: vec![Key { key }],
+            ..Default::default()
+        });
+        let found = call("get", keys.and_then(|keys| caller.request(keys)), |r| {
+            self.service.get_items(r)
+        })?;
+        let with_key = matches!(packet.opcode, opcode::GETK | opcode::GETKQ);
+        let key = if with_key {
+            packet.key.clone()
+        } else {
+            Vec::new()
+        };
+
+        Ok(match found.into_iter().next().flatten() {
+            Some(item) => Reply {
+                status: status::OK,
+                cas: item.cas,
+                extras: item.flags.to_be_bytes().to_vec(),
+                key,
+                value: item.value,
+            },
+            None => Reply {
+                key,
+                ..Reply::error(status::KEY_NOT_FOUND, "Not found")
+            },
+        })
+    }
+
+    fn binary_store(&self, packet: &Packet, caller: &Caller) -> Result<Reply, Status> {
+        let (Some(flags), Some(exptime)) = (packet.extra_u32(0), packet.extra_u32(4)) else {
+            return Err(Status::invalid_argument("Missing flags or expiration"));
+        };
+        let (command, mode) = match (packet.opcode, packet.cas) {
+            (opcode::ADD | opcode::ADDQ, _) => ("add", StoreMode::Add),
+            (_, cas) if cas != 0 => ("cas", StoreMode::Cas(cas)),
+            (opcode::REPLACE | opcode::REPLACEQ, _) => ("replace", StoreMode::Replace),
+            _ => ("set", StoreMode::Set),
+        };
+        let item = packet.key().map(|key| StoreItem {
+            key,
+            value: packet.value.clone(),
+            flags,
+            hard_ttl_ms: hard_ttl_ms(exptime as i64),
+            mode,
+        });
+
+        let stored = call(command, item.and_then(|item| caller.request(item)), |r| {
+            self.service.store_item(r)
+        })?;
+        Ok(match (stored, mode) {
+            (StoreOutcome::Stored(cas), _) => Reply {
+                cas,
+                ..Reply::status(status::OK)
+            },
+            (StoreOutcome::NotStored, StoreMode::Add) | (StoreOutcome::Exists, _) => {
+                Reply::error(status::KEY_EXISTS, "Data exists for key.")
+            }
+            (StoreOutcome::NotStored, StoreMode::Replace) | (StoreOutcome::NotFound, _) => {
+                Reply::error(status::KEY_NOT_FOUND, "Not found")
+            }
+            (StoreOutcome::NotStored, _) => Reply::error(status::NOT_STORED, "Not stored."),
+        })
+    }
+
+    /// Increments or decrements a counter, creating it with the initial value from the extras
+    /// when it is missing unless the expiration is `0xffffffff`.
+    fn binary_count(&self, packet: &Packet, caller: &Caller) -> Result<Reply, Status> {
+        let (Some(delta), Some(initial), Some(exptime)) = (
+            packet.extra_u64(0),
+            packet.extra_u64(8),
+            packet.extra_u32(16),
+        ) else {
+            return Err(Status::invalid_argument(
+                "Missing delta, initial value or expiration",
+            ));
+        };
+        let key = packet.key()?;
+        let decrement = matches!(packet.opcode, opcode::DECREMENT | opcode::DECREMENTQ);
+        let command = if decrement { "decr" } else { "incr" };
+        let item = CountItem {
+            key: key.clone(),
+            delta,
+            decrement,
+        };
+
+        let counted = call(command, caller.request(item), |r| {
+            self.service.count_item(r)
+        })?;
+        let value = match counted {
+            CountOutcome::Value(value) => value,
+            CountOutcome::NonNumeric => {
+                return Ok(Reply::error(
+                    status::NON_NUMERIC,
+                    "Non-numeric server-side value for incr or decr",
+                ))
+            }
+            CountOutcome::NotFound if exptime == u32::MAX => {
+                return Ok(Reply::error(status::KEY_NOT_FOUND, "Not found"))
+            }
+            CountOutcome::NotFound => {
+                let item = StoreItem {
+                    key,
+                    value: initial.to_string().into_bytes(),
+                    flags: 0,
+                    hard_ttl_ms: hard_ttl_ms(exptime as i64),
+                    mode: StoreMode::Add,
+                };
+                match call("add", caller.request(item), |r| self.service.store_item(r))? {
+                    StoreOutcome::Stored(_) => initial,
+                    _ => return Ok(Reply::error(status::KEY_EXISTS, "Data exists for key.")),
+                }
+            }
+        };
+
+        Ok(Reply::value(value.to_be_bytes()))
+    }
+}
+
+/// Renders `VALUE` lines for the keys that were found, in the order they were asked for.
+fn text_values(keys: &Keys, found: Vec<Option<Item>>, with_cas: bool) -> Vec<u8> {
+    let mut reply = Vec::new();
+    for (key, item) in keys.keys.iter().zip(found) {
+        let Some(item) = item else { continue };
+        let header = if with_cas {
+            format!(
+                "VALUE {} {} {} {}\r\n",
+                key.key,
+                item.flags,
+                item.value.len(),
+                item.cas
+            )
+        } else {
+            format!("VALUE {} {} {}\r\n", key.key, item.flags, item.value.len())
+        };
+        reply.extend_from_slice(header.as_bytes());
+        reply.extend_from_slice(&item.value);
+        reply.extend_from_slice(b"\r\n");
+    }
+    reply.extend_from_slice(b"END\r\n");
+
+    reply
+}
+
+/// Memcached's ASCII auth: the first `set` on a connection carries `<username> <password>`.
+fn text_login(caller: &mut Caller, data: &[u8]) -> String {
+    let credentials = String::from_utf8_lossy(data);
+    let logged_in = match credentials.split_once(' ') {
+        Some((username, password)) => caller.login(Some(username), password).is_ok(),
+        None => false,
+    };
+
+    if logged_in {
+        "STORED\r\n".to_string()
+    } else {
+        "CLIENT_ERROR authentication failure\r\n".to_string()
+    }
+}
+
+/// SASL `PLAIN`, whose value is `authzid \0 username \0 password`.
+fn binary_login(packet: &Packet, caller: &mut Caller) -> Reply {
+    if packet.key != b"PLAIN" {
+        return Reply::error(status::AUTH_ERROR, "Only PLAIN is supported");
+    }
+
+    let value = String::from_utf8_lossy(&packet.value);
+    let mut parts = value.split('\0').skip(1);
+    let logged_in = match (parts.next(), parts.next()) {
+        (Some(username), Some(password)) => caller.login(Some(username), password).is_ok(),
+        _ => false,
+    };
+
+    if logged_in {
+        Reply::value("Authenticated")
+    } else {
+        Reply::error(status::AUTH_ERROR, "Auth failure")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::pool::Pool;
+    use crate::config::{AuthConfig, Config, RedisConfig};
+
+    /// A listener taking values of up to 16 bytes, with auth on and no credentials anyone could
+    /// log in with. Every command is parsed, but none gets as far as Redis, which is never
+    /// connected to.
+    fn memcached() -> Arc<Memcached> {
+        let pool = Pool::new(&RedisConfig {
+            host: "127.0.0.1".to_string(),
+            ..RedisConfig::default()
+        });
+        let service = Arc::new(MrCacheService::new(&pool, &Config::default()));
+        let auth = Auth::from_config(&AuthConfig {
+            enabled: true,
+            ..AuthConfig::default()
+        });
+        let config = MemcachedConfig {
+            max_value_bytes: 16,
+            ..MemcachedConfig::default()
+        };
+        Memcached::new(service, auth, &config)
+    }
+
+    /// Sends `input` on a connection of its own and returns all that was written back by the
+    /// time the listener closed it.
+    async fn exchange(input: &[u8]) -> Vec<u8> {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let served = tokio::spawn(memcached().connection(server, None));
+        let (mut reader, mut writer) = tokio::io::split(client);
+        writer.write_all(input).await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).await.unwrap();
+        served.await.unwrap();
+        output
+    }
+
+    async fn text(input: &str) -> String {
+        String::from_utf8(exchange(input.as_bytes()).await).unwrap()
+    }
+
+    fn packet(opcode: u8, opaque: u32, extras: &[u8], key: &[u8], value: &[u8]) -> Vec<u8> {
+        let mut packet = vec![REQUEST_MAGIC, opcode];
+        packet.extend_from_slice(&(key.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[extras.len() as u8, 0, 0, 0]);
+        let body_len = extras.len() + key.len() + value.len();
+        packet.extend_from_slice(&(body_len as u32).to_be_bytes());
+        packet.extend_from_slice(&opaque.to_be_bytes());
+        packet.extend_from_slice(&0u64.to_be_bytes());
+        packet.extend_from_slice(extras);
+        packet.extend_from_slice(key);
+        packet.extend_from_slice(value);
+        packet
+    }
+
+    /// The opcode, status, opaque and value of each binary response.
+    fn responses(mut output: &[u8]) -> Vec<(u8, u16, u32, Vec<u8>)> {
+        let mut responses = Vec::new();
+        while !output.is_empty() {
+            assert_eq!(output[0], RESPONSE_MAGIC);
+            let key_len = u16::from_be_bytes([output[2], output[3]]) as usize;
+            let extras_len = output[4] as usize;
+            let body_len = u32::from_be_bytes(output[8..12].try_into().unwrap()) as usize;
+            let (response, rest) = output.split_at(HEADER_LEN + body_len);
+            responses.push((
+                response[1],
+                u16::from_be_bytes([response[6], response[7]]),
+                u32::from_be_bytes(response[12..16].try_into().unwrap()),
+                response[HEADER_LEN + extras_len + key_len..].to_vec(),
+            ));
+            output = rest;
+        }
+        responses
+    }
+
+    #[test]
+    fn expiration_times_become_hard_ttls() {
+        assert_eq!(hard_ttl_ms(0), None);
+        assert_eq!(hard_ttl_ms(-1), Some(0));
+        assert_eq!(hard_ttl_ms(60), Some(60_000));
+        assert_eq!(hard_ttl_ms(1), Some(1000));
+        let absolute = (now_ms() / 1000) as i64 + 60;
+        let ttl = hard_ttl_ms(absolute).unwrap();
+        assert!(ttl > 58_000 && ttl <= 60_000);
+        assert_eq!(hard_ttl_ms(MAX_RELATIVE_EXPTIME + 1), Some(0));
+    }
+
+    #[tokio::test]
+    async fn answers_pipelined_text_commands_in_order() {
+        let output =
+            text("version\r\nbogus\r\n\r\nget\r\nincr a x\r\ntouch a soon\r\nversion\r\n").await;
+        assert_eq!(
+            output,
+            "VERSION mrcache-0.1.0\r\n\
+             ERROR\r\n\
+             CLIENT_ERROR bad command line format\r\n\
+             CLIENT_ERROR invalid numeric delta argument\r\n\
+             CLIENT_ERROR bad command line format\r\n\
+             VERSION mrcache-0.1.0\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_malformed_text_commands() {
+        let long_key = "k".repeat(MAX_KEY_LEN + 1);
+        let output = text(&format!("get {long_key}\r\nset a 0 0\r\ncas a 0 0 1\r\n")).await;
+        assert_eq!(output, "CLIENT_ERROR bad command line format\r\n".repeat(3));
+
+        let output = text(&"k".repeat(MAX_LINE_LEN as usize + 1)).await;
+        assert_eq!(output, "CLIENT_ERROR line too long\r\n");
+    }
+
+    #[tokio::test]
+    async fn reads_text_data_blocks_by_their_length() {
+        let output = text("set a 0 0 20\r\n01234567890123456789\r\nversion\r\n").await;
+        assert_eq!(
+            output,
+            "SERVER_ERROR object too large for cache\r\nVERSION mrcache-0.1.0\r\n"
+        );
+
+        let output = text("set a 0 0 2\r\nabcd\r\nversion\r\n").await;
+        assert_eq!(
+            output,
+            "CLIENT_ERROR bad data chunk\r\nVERSION mrcache-0.1.0\r\n"
+        );
+
+        let output = text("set a 0 0 9\r\nuser pass\r\nget a\r\ndelete a noreply\r\n").await;
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines[0], "CLIENT_ERROR authentication failure");
+        assert!(lines[1].starts_with("CLIENT_ERROR "));
+        assert!(lines[2].starts_with("CLIENT_ERROR "));
+    }
+
+    #[tokio::test]
+    async fn stops_at_quit() {
+        assert_eq!(text("quit\r\nversion\r\n").await, "");
+    }
+
+    #[tokio::test]
+    async fn answers_pipelined_binary_requests_in_order() {
+        let mut input = packet(opcode::NOOP, 1, &[], b"", b"");
+        input.extend(packet(opcode::VERSION, 2, &[], b"", b""));
+        input.extend(packet(0x50, 3, &[], b"", b""));
+        input.extend(packet(opcode::SASL_LIST_MECHS, 4, &[], b"", b""));
+        input.extend(packet(opcode::GETQ, 5, &[], b"a", b""));
+        input.extend(packet(opcode::SET, 6, &[], b"a", b"b"));
+        let output = exchange(&input).await;
+
+        let responses = responses(&output);
+        assert_eq!(responses.len(), 6);
+        assert_eq!(responses[0], (opcode::NOOP, status::OK, 1, Vec::new()));
+        assert_eq!(
+            responses[1],
+            (opcode::VERSION, status::OK, 2, b"mrcache-0.1.0".to_vec())
+        );
+        assert_eq!(responses[2].1, status::UNKNOWN_COMMAND);
+        assert_eq!(responses[3].3, b"PLAIN");
+        assert_eq!(responses[4].1, status::AUTH_ERROR);
+        assert_eq!(responses[5].1, status::INVALID_ARGUMENTS);
+    }
+
+    #[tokio::test]
+    async fn refuses_malformed_binary_requests() {
+        let mut input = packet(opcode::SET, 1, &[0; 8], b"a", &[b'v'; 20]);
+        input.extend(packet(opcode::SASL_AUTH, 2, &[], b"PLAIN", b"\0user\0pass"));
+        let mut bad_lengths = packet(opcode::GET, 3, &[], b"key", b"");
+        bad_lengths[8..12].copy_from_slice(&1u32.to_be_bytes());
+        input.extend(bad_lengths);
+        input.extend(packet(opcode::NOOP, 4, &[], b"", b""));
+        let output = exchange(&input).await;
+
+        let statuses: Vec<(u16, u32)> = responses(&output)
+            .into_iter()
+            .map(|(_, status, opaque, _)| (status, opaque))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (status::VALUE_TOO_LARGE, 1),
+                (status::AUTH_ERROR, 2),
+                (status::INVALID_ARGUMENTS, 3),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_at_binary_quit() {
+        let mut input = packet(opcode::QUITQ, 1, &[], b"", b"");
+        input.extend(packet(opcode::NOOP, 2, &[], b"", b""));
+        assert!(exchange(&input).await.is_empty());
+    }
+}
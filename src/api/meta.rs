@@ -0,0 +1,229 @@
+use redis::{ConnectionLike, FromRedisValue, Pipeline, RedisResult};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tonic::Status;
+
+use crate::api::mr_cache::ContentType;
+use crate::api::slots;
+
+const META_PREFIX: &str = "mrcache:meta:";
+
+const FIELDS: [&str; 4] = ["stored_at", "soft_ttl_ms", "hard_ttl_ms", "content_type"];
+
+type Fields = (Option<u64>, Option<u64>, Option<u64>, Option<String>);
+
+/// A string key's value and metadata, either missing.
+pub type Stored = (Option<Vec<u8>>, Option<Meta>);
+
+/// When a key was last written, the soft/hard TTLs and content type it was written with. Kept in a
+/// hash next to the key and expired together with it.
+#[derive(Clone, Copy, Debug)]
+pub struct Meta {
+    pub stored_at: u64,
+    pub soft_ttl_ms: Option<u64>,
+    pub hard_ttl_ms: Option<u64>,
+    pub content_type: ContentType,
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Meta {
+    pub fn new(soft_ttl_ms: Option<u64>, hard_ttl_ms: Option<u64>) -> Self {
+        Self {
+            stored_at: now_ms(),
+            soft_ttl_ms,
+            hard_ttl_ms,
+            content_type: ContentType::Unspecified,
+        }
+    }
+
+    /// Metadata for TTLs given in a request, refusing a hard TTL of 0, which would delete the key
+    /// as it is written, and a soft TTL past the hard one, as the key would expire before going stale.
+    pub fn checked(soft_ttl_ms: Option<u64>, hard_ttl_ms: Option<u64>) -> Result<Self, Status> {
+        match (soft_ttl_ms, hard_ttl_ms) {
+            (_, Some(0)) => Err(Status::invalid_argument("Hard TTL must be above 0")),
+            (Some(soft), Some(hard)) if soft > hard => Err(Status::invalid_argument(
+                "Soft TTL must not be longer than the hard TTL",
+            )),
+            _ => Ok(Self::new(soft_ttl_ms, hard_ttl_ms)),
+        }
+    }
+
+    pub fn with_content_type(self, content_type: ContentType) -> Self {
+        Self {
+            content_type,
+            ..self
+        }
+    }
+
+    pub fn key(key: &str) -> String {
+        slots::companion(META_PREFIX, key)
+    }
+
+    pub fn age_ms(&self) -> u64 {
+        now_ms().saturating_sub(self.stored_at)
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.soft_ttl_ms.is_some_and(|soft| self.age_ms() >= soft)
+    }
+
+    /// Queues the metadata for `key`, applying the hard TTL to both the key and its metadata.
+    /// Without a hard TTL the key is made persistent, matching what a plain `SET` does.
+    pub fn write(&self, pipe: &mut Pipeline, key: &str) {
+        let meta_key = Self::key(key);
+
+        pipe.del(&meta_key).ignore();
+        pipe.hset(&meta_key, "stored_at", self.stored_at).ignore();
+        if let Some(soft) = self.soft_ttl_ms {
+            pipe.hset(&meta_key, "soft_ttl_ms", soft).ignore();
+        }
+        if self.content_type != ContentType::Unspecified {
+            pipe.hset(&meta_key, "content_type", self.content_type.name())
+                .ignore();
+        }
+        match self.hard_ttl_ms {
+            Some(hard) => {
+                pipe.hset(&meta_key, "hard_ttl_ms", hard).ignore();
+                pipe.pexpire(key, hard as i64).ignore();
+                pipe.pexpire(&meta_key, hard as i64).ignore();
+            }
+            None => {
+                pipe.persist(key).ignore();
+            }
+        }
+    }
+
+    pub fn read<C: ConnectionLike>(con: &mut C, keys: &[&str]) -> RedisResult<Vec<Option<Self>>> {
+        let results: Vec<Fields> = slots::split(keys, |keys| {
+            let mut pipe = redis::pipe();
+            for key in keys {
+                pipe.hget(Self::key(key), &FIELDS);
+            }
+            pipe.query(con)
+        })?;
+
+        Ok(results.into_iter().map(Self::from_fields).collect())
+    }
+
+    /// `MGET`s string keys along with their metadata, in one pipeline per slot.
+    pub fn read_values<C: ConnectionLike>(con: &mut C, keys: &[&str]) -> RedisResult<Vec<Stored>> {
+        slots::split(keys, |keys| {
+            let mut pipe = redis::pipe();
+            pipe.cmd("MGET").arg(keys);
+            for key in keys {
+                pipe.hget(Self::key(key), &FIELDS);
+            }
+            let replies: Vec<redis::Value> = pipe.query(con)?;
+
+            let (values, metas) = replies.split_first().unwrap_or((&redis::Value::Nil, &[]));
+            let values: Vec<Option<Vec<u8>>> = FromRedisValue::from_redis_value(values)?;
+            let metas = metas
+                .iter()
+                .map(|fields| Fields::from_redis_value(fields).map(Self::from_fields))
+                .collect::<RedisResult<Vec<_>>>()?;
+            Ok(values.into_iter().zip(metas).collect())
+        })
+    }
+
+    fn from_fields((stored_at, soft_ttl_ms, hard_ttl_ms, content_type): Fields) -> Option<Self> {
+        stored_at.map(|stored_at| Self {
+            stored_at,
+            soft_ttl_ms,
+            hard_ttl_ms,
+            content_type: content_type
+                .and_then(|name| ContentType::from_name(&name))
+                .unwrap_or(ContentType::Unspecified),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Value;
+
+    /// A connection answering every pipeline with `replies`.
+    struct Canned(Vec<Value>);
+
+    impl ConnectionLike for Canned {
+        fn req_packed_command(&mut self, _: &[u8]) -> RedisResult<Value> {
+            Ok(Value::Nil)
+        }
+
+        fn req_packed_commands(
+            &mut self,
+            _: &[u8],
+            offset: usize,
+            count: usize,
+        ) -> RedisResult<Vec<Value>> {
+            Ok(self.0.drain(..).skip(offset).take(count).collect())
+        }
+
+        fn get_db(&self) -> i64 {
+            0
+        }
+
+        fn check_connection(&mut self) -> bool {
+            true
+        }
+
+        fn is_open(&self) -> bool {
+            true
+        }
+    }
+
+    fn data(value: &str) -> Value {
+        Value::Data(value.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn goes_stale_once_older_than_the_soft_ttl() {
+        let mut meta = Meta::new(Some(1000), Some(5000));
+        assert!(!meta.is_stale());
+
+        meta.stored_at -= 999;
+        assert!(!meta.is_stale());
+        meta.stored_at -= 1;
+        assert!(meta.is_stale());
+
+        meta.soft_ttl_ms = None;
+        assert!(!meta.is_stale());
+    }
+
+    #[test]
+    fn refuses_ttls_that_could_never_be_served() {
+        let refused = Meta::checked(None, Some(0)).unwrap_err();
+        assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+        assert!(Meta::checked(Some(2000), Some(1000)).is_err());
+
+        assert!(Meta::checked(Some(1000), Some(1000)).is_ok());
+        assert!(Meta::checked(Some(1000), None).is_ok());
+        assert!(Meta::checked(None, None).is_ok());
+    }
+
+    #[test]
+    fn reads_values_with_their_metadata() {
+        let mut con = Canned(vec![
+            Value::Bulk(vec![data("a"), data("b"), Value::Nil]),
+            Value::Bulk(vec![data("100"), data("10"), data("20"), data("json")]),
+            Value::Bulk(vec![Value::Nil, Value::Nil, Value::Nil, Value::Nil]),
+            Value::Bulk(vec![Value::Nil, Value::Nil, Value::Nil, Value::Nil]),
+        ]);
+        let read = Meta::read_values(&mut con, &["x", "y", "z"]).unwrap();
+
+        assert_eq!(read.len(), 3);
+        let (value, meta) = &read[0];
+        assert_eq!(value.as_deref(), Some(&b"a"[..]));
+        let meta = meta.unwrap();
+        assert_eq!((meta.stored_at, meta.soft_ttl_ms), (100, Some(10)));
+        assert_eq!(meta.content_type, ContentType::Json);
+        assert_eq!(read[1].0.as_deref(), Some(&b"b"[..]));
+        assert!(read[1].1.is_none());
+        assert!(read[2].0.is_none() && read[2].1.is_none());
+    }
+}
@@ -0,0 +1,306 @@
+use axum::extract::MatchedPath;
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_gauge_vec, register_histogram, register_histogram_vec, register_int_counter,
+    register_int_counter_vec, Encoder, GaugeVec, Histogram, HistogramVec, IntCounter,
+    IntCounterVec, TextEncoder,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tonic::codegen::http;
+use tonic::Code;
+use tower::{Layer, Service};
+use tracing::error;
+
+use crate::api::pool::RedisPool;
+use crate::api::reflection::METHOD_PATHS;
+
+/// The `rpc` label of calls to paths that are neither a gRPC method nor a REST route.
+const UNKNOWN_RPC: &str = "unknown";
+
+static RPC_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "mrcache_rpc_duration_seconds",
+        "Time taken to answer each gRPC call, including those made for RESP commands, and each \
+         REST call.",
+        &["rpc"]
+    )
+    .unwrap()
+});
+
+static RPC_REQUESTS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "mrcache_rpc_requests_total",
+        "gRPC calls answered, including those made for RESP commands, and REST calls, by status \
+         code.",
+        &["rpc", "code"]
+    )
+    .unwrap()
+});
+
+static LOOKUPS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "mrcache_lookups_total",
+        "Keys and fields read, by whether they were found.",
+        &["rpc", "result"]
+    )
+    .unwrap()
+});
+
+static TIER_HITS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "mrcache_cache_hits_total",
+        "String keys found by Get with the L1 cache on, by whether the in-process cache or Redis \
+         had them.",
+        &["tier"]
+    )
+    .unwrap()
+});
+
+static COMPRESSED_BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "mrcache_compression_bytes_total",
+        "Bytes of values compressed on their way into Redis, before and after compression.",
+        &["codec", "stage"]
+    )
+    .unwrap()
+});
+
+static COMPRESSION_RATIO: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "mrcache_compression_ratio",
+        "Original size over stored size of each value compressed, counting values kept \
+         uncompressed because compressing did not shrink them as 1.",
+        &["codec"],
+        vec![1.0, 1.25, 1.5, 2.0, 3.0, 5.0, 10.0, 20.0]
+    )
+    .unwrap()
+});
+
+static REENCRYPTED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "mrcache_reencrypted_values_total",
+        "Values moved over to the active encryption key in the background."
+    )
+    .unwrap()
+});
+
+static REDIS_SECONDS: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "mrcache_redis_command_duration_seconds",
+        "Time taken by each Redis command, excluding the wait for a connection.",
+        &["command"]
+    )
+    .unwrap()
+});
+
+static REDIS_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "mrcache_redis_errors_total",
+        "Redis commands that failed.",
+        &["command"]
+    )
+    .unwrap()
+});
+
+static POOL_WAIT_SECONDS: Lazy<Histogram> = Lazy::new(|| {
+    register_histogram!(
+        "mrcache_pool_wait_seconds",
+        "Time spent waiting for a pooled Redis connection.",
+        vec![0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.2, 0.5]
+    )
+    .unwrap()
+});
+
+static POOL_CONNECTIONS: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "mrcache_pool_connections",
+        "Connections held by each Redis pool.",
+        &["pool", "state"]
+    )
+    .unwrap()
+});
+
+static REPLICA_LAG_SECONDS: Lazy<GaugeVec> = Lazy::new(|| {
+    register_gauge_vec!(
+        "mrcache_replica_lag_seconds",
+        "How far each Redis replica was behind the primary's heartbeat when last checked.",
+        &["replica"]
+    )
+    .unwrap()
+});
+
+/// Pools whose size is reported on each scrape. Pools that have been dropped are skipped.
+static POOLS: Mutex<Vec<(String, Weak<RedisPool>)>> = Mutex::new(Vec::new());
+
+pub fn watch_pool(name: &str, pool: &Arc<RedisPool>) {
+    POOLS
+        .lock()
+        .unwrap()
+        .push((name.to_string(), Arc::downgrade(pool)));
+}
+
+pub fn observe_pool_wait(start: Instant) {
+    POOL_WAIT_SECONDS.observe(start.elapsed().as_secs_f64());
+}
+
+pub fn observe_redis(command: &str, start: Instant, ok: bool) {
+    REDIS_SECONDS
+        .with_label_values(&[command])
+        .observe(start.elapsed().as_secs_f64());
+    if !ok {
+        REDIS_ERRORS.with_label_values(&[command]).inc();
+    }
+}
+
+pub fn observe_rpc(rpc: &str, start: Instant, code: Code) {
+    RPC_SECONDS
+        .with_label_values(&[rpc])
+        .observe(start.elapsed().as_secs_f64());
+    RPC_REQUESTS
+        .with_label_values(&[rpc, &format!("{:?}", code)])
+        .inc();
+}
+
+/// Counts each value of a read as a hit or a miss.
+pub fn record_lookups<T>(rpc: &str, values: &[Option<T>]) {
+    let hits = values.iter().filter(|value| value.is_some()).count() as u64;
+    LOOKUPS.with_label_values(&[rpc, "hit"]).inc_by(hits);
+    LOOKUPS
+        .with_label_values(&[rpc, "miss"])
+        .inc_by(values.len() as u64 - hits);
+}
+
+pub fn observe_compression(codec: &str, original: usize, stored: usize) {
+    COMPRESSED_BYTES
+        .with_label_values(&[codec, "original"])
+        .inc_by(original as u64);
+    COMPRESSED_BYTES
+        .with_label_values(&[codec, "stored"])
+        .inc_by(stored as u64);
+    COMPRESSION_RATIO
+        .with_label_values(&[codec])
+        .observe(original as f64 / stored.max(1) as f64);
+}
+
+pub fn record_reencrypted(values: u64) {
+    REENCRYPTED.inc_by(values);
+}
+
+pub fn record_tier_hits(l1: usize, l2: usize) {
+    TIER_HITS.with_label_values(&["l1"]).inc_by(l1 as u64);
+    TIER_HITS.with_label_values(&["l2"]).inc_by(l2 as u64);
+}
+
+pub fn observe_replica_lag(replica: &str, lag_ms: u64) {
+    REPLICA_LAG_SECONDS
+        .with_label_values(&[replica])
+        .set(lag_ms as f64 / 1000.0);
+}
+
+fn update_pools() {
+    let mut pools = POOLS.lock().unwrap();
+    pools.retain(|(_, pool)| pool.strong_count() > 0);
+
+    POOL_CONNECTIONS.reset();
+    for (name, pool) in pools.iter() {
+        if let Some(pool) = pool.upgrade() {
+            let state = pool.state();
+            let idle = state.idle_connections as f64;
+            let active = state.connections as f64 - idle;
+            POOL_CONNECTIONS
+                .with_label_values(&[name, "idle"])
+                .add(idle);
+            POOL_CONNECTIONS
+                .with_label_values(&[name, "active"])
+                .add(active);
+        }
+    }
+}
+
+/// Renders every metric in the Prometheus text format, returning it with its content type.
+pub fn render() -> (String, Vec<u8>) {
+    update_pools();
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        error!(error = %e, "Failed to encode metrics");
+    }
+
+    (encoder.format_type().to_string(), buffer)
+}
+
+/// Times every gRPC call and counts it by the status it ended with, including calls the auth
+/// interceptor turned away. REST calls are counted by method and route, with the code of the
+/// status they failed with. Calls to any other path are counted as `unknown`.
+#[derive(Clone, Default)]
+pub struct RpcMetricsLayer;
+
+impl<S> Layer<S> for RpcMetricsLayer {
+    type Service = RpcMetrics<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcMetrics { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RpcMetrics<S> {
+    inner: S,
+}
+
+impl<S, B, R> Service<http::Request<B>> for RpcMetrics<S>
+where
+    S: Service<http::Request<B>, Response = http::Response<R>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let rpc = match request.extensions().get::<MatchedPath>() {
+            Some(route) => format!("{} {}", request.method(), route.as_str()),
+            None if METHOD_PATHS.contains(request.uri().path()) => request
+                .uri()
+                .path()
+                .rsplit('/')
+                .next()
+                .unwrap_or_default()
+                .to_string(),
+            // Paths no method is served on, which callers can make up without end.
+            None => UNKNOWN_RPC.to_string(),
+        };
+        let start = Instant::now();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await;
+            // Failed calls carry their status in the headers, successful ones only in the
+            // trailers once the body is done.
+            let code = match &response {
+                Ok(response) => response
+                    .extensions()
+                    .get::<Code>()
+                    .copied()
+                    .or_else(|| {
+                        let status = response.headers().get("grpc-status")?;
+                        status.to_str().ok()?.parse::<i32>().ok().map(Code::from)
+                    })
+                    .unwrap_or(Code::Ok),
+                Err(_) => Code::Unknown,
+            };
+
+            observe_rpc(&rpc, start, code);
+
+            response
+        })
+    }
+}
@@ -9,12 +9,29 @@ pub struct Key {
 pub struct Keys {
     #[prost(message, repeated, tag = "1")]
     pub keys: ::prost::alloc::vec::Vec<Key>,
+    /// Encoding to return values in. JSON, MessagePack and CBOR values are transcoded to it, others
+    /// come back as stored. Values come back as stored when unset.
+    #[prost(enumeration = "ContentType", tag = "2")]
+    pub accept: i32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Value {
+    /// The value, when it is valid UTF-8.
     #[prost(string, tag = "1")]
     pub value: ::prost::alloc::string::String,
+    /// Milliseconds since the value was last set, when mrCache knows it.
+    #[prost(uint64, optional, tag = "2")]
+    pub age_ms: ::core::option::Option<u64>,
+    /// The value is past its soft TTL. For string keys a refresh from the loader has been requested.
+    #[prost(bool, tag = "3")]
+    pub stale: bool,
+    /// The value, when it is not valid UTF-8.
+    #[prost(bytes = "vec", tag = "4")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+    /// How the value is encoded, unspecified for values set without a content type.
+    #[prost(enumeration = "ContentType", tag = "5")]
+    pub content_type: i32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -29,12 +46,27 @@ pub struct KeyValue {
     pub key: ::prost::alloc::string::String,
     #[prost(string, tag = "2")]
     pub value: ::prost::alloc::string::String,
+    /// Binary value, used instead of value when not empty.
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+    #[prost(enumeration = "ContentType", tag = "4")]
+    pub content_type: i32,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct KeyValues {
     #[prost(message, repeated, tag = "1")]
     pub key_values: ::prost::alloc::vec::Vec<KeyValue>,
+    /// After the soft TTL values are still served but flagged stale, and string keys are refreshed in
+    /// the background.
+    #[prost(uint64, optional, tag = "2")]
+    pub soft_ttl_ms: ::core::option::Option<u64>,
+    /// After the hard TTL values are removed.
+    #[prost(uint64, optional, tag = "3")]
+    pub hard_ttl_ms: ::core::option::Option<u64>,
+    /// Tags to index the keys under for InvalidateTags, replacing any tags they had before.
+    #[prost(string, repeated, tag = "4")]
+    pub tags: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -58,6 +90,190 @@ pub struct Effect {
     #[prost(bool, tag = "1")]
     pub effect: bool,
 }
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Scan {
+    /// Glob-style pattern, matching every key when empty.
+    #[prost(string, tag = "1")]
+    pub pattern: ::prost::alloc::string::String,
+    /// Cursor from the previous page, 0 to start.
+    #[prost(uint64, tag = "2")]
+    pub cursor: u64,
+    /// Hint for how many keys to look at, defaults to 10.
+    #[prost(uint64, tag = "3")]
+    pub count: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScanPage {
+    /// Cursor for the next page, 0 once the scan is complete.
+    #[prost(uint64, tag = "1")]
+    pub cursor: u64,
+    #[prost(message, optional, tag = "2")]
+    pub keys: ::core::option::Option<Keys>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Tags {
+    #[prost(string, repeated, tag = "1")]
+    pub tags: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Count {
+    #[prost(uint64, tag = "1")]
+    pub count: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonPath {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonPaths {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    /// No paths returns the whole document, one path a JSON array of what it matched, and several
+    /// an object of those arrays keyed by path.
+    #[prost(string, repeated, tag = "2")]
+    pub paths: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonDocument {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    /// New documents must be set at the root.
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub json: ::prost::alloc::string::String,
+    #[prost(enumeration = "JsonCondition", tag = "4")]
+    pub condition: i32,
+    /// TTLs and tags only apply when setting the root, deeper sets keep the document's.
+    #[prost(uint64, optional, tag = "5")]
+    pub soft_ttl_ms: ::core::option::Option<u64>,
+    #[prost(uint64, optional, tag = "6")]
+    pub hard_ttl_ms: ::core::option::Option<u64>,
+    #[prost(string, repeated, tag = "7")]
+    pub tags: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonArrayItems {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    /// JSON values appended to every array the path matches.
+    #[prost(string, repeated, tag = "3")]
+    pub json: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonIncrement {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    /// JSON number added to every number the path matches.
+    #[prost(string, tag = "3")]
+    pub by: ::prost::alloc::string::String,
+}
+/// RFC 7386 merge patch applied at the path. Nulls in the patch delete members.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct JsonPatch {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub path: ::prost::alloc::string::String,
+    #[prost(string, tag = "3")]
+    pub json: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Json {
+    /// JSON text, unset when the key does not exist.
+    #[prost(string, optional, tag = "1")]
+    pub json: ::core::option::Option<::prost::alloc::string::String>,
+}
+/// How a value is encoded. Structured values are validated when they are set.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ContentType {
+    Unspecified = 0,
+    Raw = 1,
+    Json = 2,
+    Msgpack = 3,
+    Cbor = 4,
+    /// Checked to be well-formed, but without a schema never transcoded.
+    Protobuf = 5,
+}
+impl ContentType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ContentType::Unspecified => "CONTENT_TYPE_UNSPECIFIED",
+            ContentType::Raw => "CONTENT_TYPE_RAW",
+            ContentType::Json => "CONTENT_TYPE_JSON",
+            ContentType::Msgpack => "CONTENT_TYPE_MSGPACK",
+            ContentType::Cbor => "CONTENT_TYPE_CBOR",
+            ContentType::Protobuf => "CONTENT_TYPE_PROTOBUF",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CONTENT_TYPE_UNSPECIFIED" => Some(Self::Unspecified),
+            "CONTENT_TYPE_RAW" => Some(Self::Raw),
+            "CONTENT_TYPE_JSON" => Some(Self::Json),
+            "CONTENT_TYPE_MSGPACK" => Some(Self::Msgpack),
+            "CONTENT_TYPE_CBOR" => Some(Self::Cbor),
+            "CONTENT_TYPE_PROTOBUF" => Some(Self::Protobuf),
+            _ => None,
+        }
+    }
+}
+/// Whether a JsonSet depends on the path already holding a value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum JsonCondition {
+    Always = 0,
+    /// Only set when the path does not exist yet, like NX.
+    Missing = 1,
+    /// Only set when the path exists, like XX.
+    Exists = 2,
+}
+impl JsonCondition {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            JsonCondition::Always => "JSON_CONDITION_ALWAYS",
+            JsonCondition::Missing => "JSON_CONDITION_MISSING",
+            JsonCondition::Exists => "JSON_CONDITION_EXISTS",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "JSON_CONDITION_ALWAYS" => Some(Self::Always),
+            "JSON_CONDITION_MISSING" => Some(Self::Missing),
+            "JSON_CONDITION_EXISTS" => Some(Self::Exists),
+            _ => None,
+        }
+    }
+}
 /// Generated server implementations.
 pub mod mr_cache_server {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -65,6 +281,11 @@ pub mod mr_cache_server {
     /// Generated trait containing gRPC methods that should be implemented for use with MrCacheServer.
     #[async_trait]
     pub trait MrCache: Send + Sync + 'static {
+        /// Keys
+        async fn scan(
+            &self,
+            request: tonic::Request<super::Scan>,
+        ) -> std::result::Result<tonic::Response<super::ScanPage>, tonic::Status>;
         /// Strings
         async fn set(
             &self,
@@ -95,6 +316,36 @@ pub mod mr_cache_server {
             &self,
             request: tonic::Request<super::Key>,
         ) -> std::result::Result<tonic::Response<super::Values>, tonic::Status>;
+        /// Tags
+        async fn invalidate_tags(
+            &self,
+            request: tonic::Request<super::Tags>,
+        ) -> std::result::Result<tonic::Response<super::Count>, tonic::Status>;
+        /// JSON documents, kept by RedisJSON when the server has it and as JSON strings otherwise
+        async fn json_set(
+            &self,
+            request: tonic::Request<super::JsonDocument>,
+        ) -> std::result::Result<tonic::Response<super::Effect>, tonic::Status>;
+        async fn json_get(
+            &self,
+            request: tonic::Request<super::JsonPaths>,
+        ) -> std::result::Result<tonic::Response<super::Json>, tonic::Status>;
+        async fn json_del(
+            &self,
+            request: tonic::Request<super::JsonPath>,
+        ) -> std::result::Result<tonic::Response<super::Count>, tonic::Status>;
+        async fn json_arr_append(
+            &self,
+            request: tonic::Request<super::JsonArrayItems>,
+        ) -> std::result::Result<tonic::Response<super::Json>, tonic::Status>;
+        async fn json_num_incr_by(
+            &self,
+            request: tonic::Request<super::JsonIncrement>,
+        ) -> std::result::Result<tonic::Response<super::Json>, tonic::Status>;
+        async fn json_merge(
+            &self,
+            request: tonic::Request<super::JsonPatch>,
+        ) -> std::result::Result<tonic::Response<super::Effect>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct MrCacheServer<T: MrCache> {
@@ -175,6 +426,50 @@ pub mod mr_cache_server {
         fn call(&mut self, req: http::Request<B>) -> Self::Future {
             let inner = self.inner.clone();
             match req.uri().path() {
+                "/mr_cache.MrCache/SCAN" => {
+                    #[allow(non_camel_case_types)]
+                    struct SCANSvc<T: MrCache>(pub Arc<T>);
+                    impl<T: MrCache> tonic::server::UnaryService<super::Scan>
+                    for SCANSvc<T> {
+                        type Response = super::ScanPage;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::Scan>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MrCache>::scan(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SCANSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/mr_cache.MrCache/SET" => {
                     #[allow(non_camel_case_types)]
                     struct SETSvc<T: MrCache>(pub Arc<T>);
@@ -483,6 +778,314 @@ pub mod mr_cache_server {
                     };
                     Box::pin(fut)
                 }
+                "/mr_cache.MrCache/InvalidateTags" => {
+                    #[allow(non_camel_case_types)]
+                    struct InvalidateTagsSvc<T: MrCache>(pub Arc<T>);
+                    impl<T: MrCache> tonic::server::UnaryService<super::Tags>
+                    for InvalidateTagsSvc<T> {
+                        type Response = super::Count;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::Tags>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MrCache>::invalidate_tags(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = InvalidateTagsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/mr_cache.MrCache/JsonSet" => {
+                    #[allow(non_camel_case_types)]
+                    struct JsonSetSvc<T: MrCache>(pub Arc<T>);
+                    impl<T: MrCache> tonic::server::UnaryService<super::JsonDocument>
+                    for JsonSetSvc<T> {
+                        type Response = super::Effect;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::JsonDocument>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MrCache>::json_set(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = JsonSetSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/mr_cache.MrCache/JsonGet" => {
+                    #[allow(non_camel_case_types)]
+                    struct JsonGetSvc<T: MrCache>(pub Arc<T>);
+                    impl<T: MrCache> tonic::server::UnaryService<super::JsonPaths>
+                    for JsonGetSvc<T> {
+                        type Response = super::Json;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::JsonPaths>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MrCache>::json_get(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = JsonGetSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/mr_cache.MrCache/JsonDel" => {
+                    #[allow(non_camel_case_types)]
+                    struct JsonDelSvc<T: MrCache>(pub Arc<T>);
+                    impl<T: MrCache> tonic::server::UnaryService<super::JsonPath>
+                    for JsonDelSvc<T> {
+                        type Response = super::Count;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::JsonPath>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MrCache>::json_del(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = JsonDelSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/mr_cache.MrCache/JsonArrAppend" => {
+                    #[allow(non_camel_case_types)]
+                    struct JsonArrAppendSvc<T: MrCache>(pub Arc<T>);
+                    impl<T: MrCache> tonic::server::UnaryService<super::JsonArrayItems>
+                    for JsonArrAppendSvc<T> {
+                        type Response = super::Json;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::JsonArrayItems>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MrCache>::json_arr_append(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = JsonArrAppendSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/mr_cache.MrCache/JsonNumIncrBy" => {
+                    #[allow(non_camel_case_types)]
+                    struct JsonNumIncrBySvc<T: MrCache>(pub Arc<T>);
+                    impl<T: MrCache> tonic::server::UnaryService<super::JsonIncrement>
+                    for JsonNumIncrBySvc<T> {
+                        type Response = super::Json;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::JsonIncrement>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MrCache>::json_num_incr_by(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = JsonNumIncrBySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/mr_cache.MrCache/JsonMerge" => {
+                    #[allow(non_camel_case_types)]
+                    struct JsonMergeSvc<T: MrCache>(pub Arc<T>);
+                    impl<T: MrCache> tonic::server::UnaryService<super::JsonPatch>
+                    for JsonMergeSvc<T> {
+                        type Response = super::Effect;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::JsonPatch>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as MrCache>::json_merge(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = JsonMergeSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
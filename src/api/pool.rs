@@ -1,39 +1,530 @@
-use std::sync::Arc;
+use redis::cluster::{ClusterClientBuilder, ClusterConnection};
+use redis::{
+    from_redis_value, Client, ClientTlsConfig, Cmd, Connection, ConnectionAddr, ConnectionInfo,
+    ConnectionLike, IntoConnectionInfo, RedisError, RedisResult, TlsCertificates, Value,
+};
+use std::env;
+use std::fs;
+use std::io;
+use std::sync::{Arc, RwLock};
 use std::time::Duration;
+use tracing::{error, info};
 
-pub type RedisPool = r2d2::Pool<redis::Client>;
+use crate::api::metrics;
+use crate::api::sentinel::Sentinel;
+use crate::api::slots;
+use crate::config::RedisConfig;
+
+pub type RedisPool = r2d2::Pool<Connector>;
+
+/// An ACL username and password. Only ever handed to the Redis client, never printed.
+#[derive(Clone, Default, PartialEq)]
+struct Secret {
+    username: Option<String>,
+    password: Option<String>,
+}
+
+/// The credentials shared by every pool, read from secret files or the environment. Re-reading
+/// them lets the files be rotated in place: connections opened afterwards use the new ones.
+struct Credentials {
+    config: RedisConfig,
+    current: RwLock<Secret>,
+}
+
+impl Credentials {
+    fn read_secret(file: &Option<String>, env_name: &str) -> io::Result<Option<String>> {
+        match file {
+            Some(path) => fs::read_to_string(path).map(|s| Some(s.trim_end().to_string())),
+            None => Ok(env::var(env_name).ok()),
+        }
+    }
+
+    fn read(config: &RedisConfig) -> io::Result<Secret> {
+        Ok(Secret {
+            username: Self::read_secret(&config.username_file, &config.username_env)?,
+            password: Self::read_secret(&config.password_file, &config.password_env)?,
+        })
+    }
+
+    fn load(config: &RedisConfig) -> Self {
+        Self {
+            config: config.clone(),
+            current: RwLock::new(Self::read(config).expect("Failed to read Redis credentials.")),
+        }
+    }
+
+    fn current(&self) -> Secret {
+        self.current.read().unwrap().clone()
+    }
+
+    fn reload(&self) {
+        match Self::read(&self.config) {
+            Ok(secret) => {
+                let mut current = self.current.write().unwrap();
+                if *current != secret {
+                    *current = secret;
+                    info!("Redis credentials rotated");
+                }
+            }
+            Err(e) => error!(error = %e, "Failed to re-read Redis credentials"),
+        }
+    }
+}
+
+/// A connection to a single Redis node, or to a whole cluster with each command routed to the
+/// node serving its key's slot. `info` is how the node, or the first cluster seed, was reached.
+pub enum RedisConnection {
+    Node {
+        con: Connection,
+        info: ConnectionInfo,
+    },
+    Cluster {
+        con: Box<ClusterConnection>,
+        info: ConnectionInfo,
+        /// The masters from the last `CLUSTER SLOTS`, with the connections `on_master` opened to
+        /// them. Both are dropped when a command on a master fails, so the slots are read again.
+        masters: Vec<Master>,
+    },
+}
+
+/// A cluster master, connected to once a command is run on it.
+pub struct Master {
+    info: ConnectionInfo,
+    con: Option<Connection>,
+}
+
+impl RedisConnection {
+    fn info(&self) -> &ConnectionInfo {
+        match self {
+            Self::Node { info, .. } | Self::Cluster { info, .. } => info,
+        }
+    }
+
+    /// How to reach each master, in a stable order: the node itself, or every master the cluster
+    /// lists in `CLUSTER SLOTS`, as last read on this connection.
+    pub fn masters(&mut self) -> RedisResult<Vec<ConnectionInfo>> {
+        match self {
+            Self::Node { info, .. } => Ok(vec![info.clone()]),
+            Self::Cluster { con, info, masters } => {
+                let masters = Self::cluster_masters(con, info, masters)?;
+                Ok(masters.iter().map(|master| master.info.clone()).collect())
+            }
+        }
+    }
+
+    /// The masters of a cluster, listed with `CLUSTER SLOTS` unless they already are.
+    fn cluster_masters<'a>(
+        con: &mut ClusterConnection,
+        info: &ConnectionInfo,
+        masters: &'a mut Vec<Master>,
+    ) -> RedisResult<&'a mut [Master]> {
+        if masters.is_empty() {
+            *masters = Self::list_masters(con, info)?
+                .into_iter()
+                .map(|info| Master { info, con: None })
+                .collect();
+        }
+        Ok(masters)
+    }
+
+    fn list_masters(
+        con: &mut ClusterConnection,
+        info: &ConnectionInfo,
+    ) -> RedisResult<Vec<ConnectionInfo>> {
+        let ranges: Vec<Vec<Value>> = redis::cmd("CLUSTER").arg("SLOTS").query(con)?;
+        let mut addresses = Vec::new();
+        for range in ranges {
+            if let Some(master) = range.get(2) {
+                let (host, port): (String, u16) = match from_redis_value::<Vec<Value>>(master)? {
+                    node if node.len() >= 2 => {
+                        (from_redis_value(&node[0])?, from_redis_value(&node[1])?)
+                    }
+                    _ => continue,
+                };
+                addresses.push((host, port));
+            }
+        }
+        addresses.sort();
+        addresses.dedup();
+
+        Ok(addresses
+            .into_iter()
+            .map(|(host, port)| ConnectionInfo {
+                addr: match &info.addr {
+                    ConnectionAddr::TcpTls {
+                        insecure,
+                        tls_params,
+                        ..
+                    } => ConnectionAddr::TcpTls {
+                        host,
+                        port,
+                        insecure: *insecure,
+                        tls_params: tls_params.clone(),
+                    },
+                    _ => ConnectionAddr::Tcp(host, port),
+                },
+                redis: info.redis.clone(),
+            })
+            .collect())
+    }
+
+    /// Runs `command` on the master at `index` in `masters`: on this very connection for a
+    /// single node, on a connection of its own kept with this one for a cluster. `None` past the
+    /// last master.
+    pub fn on_master<T, F>(&mut self, index: usize, command: F) -> RedisResult<Option<T>>
+    where
+        F: FnOnce(&mut Connection) -> RedisResult<T>,
+    {
+        let (con, info, masters) = match self {
+            Self::Node { con, .. } => {
+                return match index {
+                    0 => command(con).map(Some),
+                    _ => Ok(None),
+                }
+            }
+            Self::Cluster { con, info, masters } => (con, info, masters),
+        };
+
+        let Some(master) = Self::cluster_masters(con, info, masters)?.get_mut(index) else {
+            return Ok(None);
+        };
+        let result = match &mut master.con {
+            Some(con) => command(con),
+            None => connect(&master.info).and_then(|con| command(master.con.insert(con))),
+        };
+        if result.is_err() {
+            masters.clear();
+        }
+        result.map(Some)
+    }
+}
+
+impl ConnectionLike for RedisConnection {
+    fn req_packed_command(&mut self, cmd: &[u8]) -> RedisResult<Value> {
+        match self {
+            Self::Node { con, .. } => con.req_packed_command(cmd),
+            Self::Cluster { con, .. } => con.req_packed_command(cmd),
+        }
+    }
+
+    fn req_packed_commands(
+        &mut self,
+        cmd: &[u8],
+        offset: usize,
+        count: usize,
+    ) -> RedisResult<Vec<Value>> {
+        match self {
+            Self::Node { con, .. } => con.req_packed_commands(cmd, offset, count),
+            Self::Cluster { con, .. } => con.req_packed_commands(cmd, offset, count),
+        }
+    }
+
+    fn req_command(&mut self, cmd: &Cmd) -> RedisResult<Value> {
+        match self {
+            Self::Node { con, .. } => con.req_command(cmd),
+            Self::Cluster { con, .. } => con.req_command(cmd),
+        }
+    }
+
+    fn get_db(&self) -> i64 {
+        match self {
+            Self::Node { con, .. } => con.get_db(),
+            Self::Cluster { con, .. } => con.get_db(),
+        }
+    }
+
+    fn supports_pipelining(&self) -> bool {
+        match self {
+            Self::Node { con, .. } => con.supports_pipelining(),
+            Self::Cluster { con, .. } => con.supports_pipelining(),
+        }
+    }
+
+    fn check_connection(&mut self) -> bool {
+        match self {
+            Self::Node { con, .. } => con.check_connection(),
+            Self::Cluster { con, .. } => con.check_connection(),
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        match self {
+            Self::Node { con, .. } => con.is_open(),
+            Self::Cluster { con, .. } => con.is_open(),
+        }
+    }
+}
+
+/// Opens a connection of its own to one node, as listed by `RedisConnection::masters`.
+pub fn connect(info: &ConnectionInfo) -> RedisResult<Connection> {
+    Client::open(info.clone())?.get_connection()
+}
+
+/// What a pool connects to: a single node, a cluster discovered from its seeds, or whichever
+/// node the sentinels say is the master.
+#[derive(Clone)]
+enum Topology {
+    Node,
+    Cluster(Vec<ConnectionInfo>),
+    Sentinel(Arc<Sentinel>),
+}
+
+/// Opens connections for a pool, authenticating with the current shared credentials unless its
+/// URL carries its own.
+pub struct Connector {
+    info: ConnectionInfo,
+    topology: Topology,
+    certificates: Option<TlsCertificates>,
+    credentials: Arc<Credentials>,
+}
+
+impl Connector {
+    fn authenticated(&self, mut info: ConnectionInfo) -> ConnectionInfo {
+        if info.redis.password.is_none() {
+            let secret = self.credentials.current();
+            info.redis.username = secret.username;
+            info.redis.password = secret.password;
+        }
+        info
+    }
+}
+
+impl r2d2::ManageConnection for Connector {
+    type Connection = RedisConnection;
+    type Error = RedisError;
+
+    fn connect(&self) -> RedisResult<Self::Connection> {
+        let info = self.authenticated(self.info.clone());
+        match &self.topology {
+            Topology::Node => Ok(RedisConnection::Node {
+                con: connect(&info)?,
+                info,
+            }),
+            Topology::Cluster(seeds) => {
+                let seeds = seeds.iter().map(|seed| self.authenticated(seed.clone()));
+                let mut builder = ClusterClientBuilder::new(seeds);
+                if let Some(certificates) = &self.certificates {
+                    builder = builder.certs(certificates.clone());
+                }
+                Ok(RedisConnection::Cluster {
+                    con: Box::new(builder.build()?.get_connection()?),
+                    info,
+                    masters: Vec::new(),
+                })
+            }
+            Topology::Sentinel(sentinel) => {
+                let info = sentinel.locate(info)?;
+                let mut con = connect(&info)?;
+                sentinel.verify(&mut con)?;
+                Ok(RedisConnection::Node { con, info })
+            }
+        }
+    }
+
+    fn is_valid(&self, con: &mut Self::Connection) -> RedisResult<()> {
+        if !self.has_broken(con) && con.check_connection() {
+            Ok(())
+        } else {
+            Err(RedisError::from(io::Error::from(io::ErrorKind::BrokenPipe)))
+        }
+    }
+
+    /// Connections to a master the sentinels moved away from count as broken, so they are
+    /// replaced by ones to the new master.
+    fn has_broken(&self, con: &mut Self::Connection) -> bool {
+        match &self.topology {
+            Topology::Sentinel(sentinel) => !con.is_open() || !sentinel.is_master(con.info()),
+            _ => !con.is_open(),
+        }
+    }
+}
 
 pub struct Pool {
     pool: Arc<RedisPool>,
+    info: ConnectionInfo,
+    topology: Topology,
+    credentials: Arc<Credentials>,
+    certificates: Option<TlsCertificates>,
+}
+
+/// Embeds the certificates in a `rediss://` connection's details.
+fn with_certificates(
+    info: ConnectionInfo,
+    certificates: &Option<TlsCertificates>,
+) -> ConnectionInfo {
+    match (&info.addr, certificates) {
+        (ConnectionAddr::TcpTls { .. }, Some(certificates)) => {
+            Client::build_with_tls(info, certificates.clone())
+                .expect("Failed to load Redis TLS certificates.")
+                .get_connection_info()
+                .clone()
+        }
+        _ => info,
+    }
 }
 
 impl Pool {
-    pub fn new() -> Self {
-        let ip = "host.docker.internal"; //TODO: Extract to config
-        let port = "6379"; //TODO: Extract to config
-        let connection_info = "redis://".to_string() + ip + ":" + port;
+    pub fn new(config: &RedisConfig) -> Self {
+        let scheme = if config.tls { "rediss" } else { "redis" };
+        let url = format!("{}://{}:{}/{}", scheme, config.host, config.port, config.db);
 
-        let start = std::time::Instant::now();
+        let read = |path: &String| fs::read(path).expect("Failed to read Redis TLS file.");
+        let client_tls = match (&config.client_cert_file, &config.client_key_file) {
+            (Some(cert), Some(key)) => Some(ClientTlsConfig {
+                client_cert: read(cert),
+                client_key: read(key),
+            }),
+            _ => None,
+        };
+        let root_cert = config.ca_file.as_ref().map(read);
+        let certificates =
+            (client_tls.is_some() || root_cert.is_some()).then_some(TlsCertificates {
+                client_tls,
+                root_cert,
+            });
 
-        let client: redis::Client =
-            redis::Client::open(connection_info).expect("Failed to open connection manager.");
-        let pool: r2d2::Pool<redis::Client> = r2d2::Pool::builder()
-            .connection_timeout(Duration::from_millis(200))
-            .build(client)
-            .expect("Failed to create/connect Redis pool.");
+        let info = url
+            .into_connection_info()
+            .expect("Failed to parse Redis URL.");
+        let credentials = Arc::new(Credentials::load(config));
+
+        let parse = |nodes: &[String], what: &str| -> Vec<ConnectionInfo> {
+            nodes
+                .iter()
+                .map(|node| {
+                    format!("{}://{}", scheme, node)
+                        .into_connection_info()
+                        .map(|node| with_certificates(node, &certificates))
+                        .unwrap_or_else(|e| panic!("Failed to parse {what}: {e}."))
+                })
+                .collect()
+        };
+        let topology = match (config.cluster_nodes.is_empty(), config.sentinels.is_empty()) {
+            (true, true) => Topology::Node,
+            (false, true) => {
+                assert!(config.db == 0, "Redis Cluster only has DB 0.");
+                slots::enable_cluster();
+                Topology::Cluster(parse(&config.cluster_nodes, "Redis Cluster node"))
+            }
+            (true, false) => Topology::Sentinel(Arc::new(Sentinel::new(
+                parse(&config.sentinels, "Redis Sentinel"),
+                config.sentinel_service.clone(),
+            ))),
+            (false, false) => panic!("Redis Cluster and Sentinel cannot be used together."),
+        };
+        Self::open_with(info, topology, credentials, certificates)
+    }
+
+    fn open_with(
+        info: ConnectionInfo,
+        topology: Topology,
+        credentials: Arc<Credentials>,
+        certificates: Option<TlsCertificates>,
+    ) -> Self {
+        let info = match &topology {
+            Topology::Cluster(seeds) => seeds[0].clone(),
+            _ => with_certificates(info, &certificates),
+        };
 
-        println!("Redis New Pool - Time elapsed: {:?}", start.elapsed());
+        let connector = Connector {
+            info: info.clone(),
+            topology: topology.clone(),
+            certificates: certificates.clone(),
+            credentials: credentials.clone(),
+        };
+        // Built without connecting, so the server can come up and report itself not ready until
+        // Redis is reachable rather than failing to start.
+        let pool: RedisPool = r2d2::Pool::builder()
+            .connection_timeout(Duration::from_millis(200))
+            .build_unchecked(connector);
+        let pool = Arc::new(pool);
+        let name = match &topology {
+            Topology::Node => format!("{}/{}", info.addr, info.redis.db),
+            Topology::Cluster(_) => format!("cluster {}", info.addr),
+            Topology::Sentinel(sentinel) => {
+                format!("sentinel {}/{}", sentinel.service(), info.redis.db)
+            }
+        };
+        metrics::watch_pool(&name, &pool);
 
         Self {
-            pool: Arc::new(pool),
+            pool,
+            info,
+            topology,
+            credentials,
+            certificates,
         }
     }
 
-    pub fn get_pool(&self) -> Arc<RedisPool> {
-        let start = std::time::Instant::now();
-        let cloned = self.pool.clone();
-        println!("Redis Clone Pool - Time elapsed: {:?}", start.elapsed());
+    /// Opens another pool sharing this one's credentials and certificates, on `url` if given and
+    /// otherwise on the same server or master, optionally switching to another logical DB. A
+    /// cluster has no other DBs, so tenants on it need a `url` of their own to switch.
+    pub fn open(&self, url: Option<&str>, db: Option<i64>) -> Self {
+        let (mut info, topology) = match url {
+            Some(url) => (
+                url.into_connection_info()
+                    .expect("Failed to parse tenant Redis URL."),
+                Topology::Node,
+            ),
+            None => {
+                assert!(
+                    !matches!(self.topology, Topology::Cluster(_)),
+                    "Redis Cluster only has DB 0."
+                );
+                (self.info.clone(), self.topology.clone())
+            }
+        };
+        if let Some(db) = db {
+            info.redis.db = db;
+        }
+
+        Self::open_with(
+            info,
+            topology,
+            self.credentials.clone(),
+            self.certificates.clone(),
+        )
+    }
+
+    /// Opens a pool on another node with the same DB, credentials and certificates, like a
+    /// replica, given as `host:port`.
+    pub fn open_node(&self, node: &str) -> Self {
+        let scheme = match self.info.addr {
+            ConnectionAddr::TcpTls { .. } => "rediss",
+            _ => "redis",
+        };
+        let mut info = format!("{}://{}", scheme, node)
+            .into_connection_info()
+            .expect("Failed to parse Redis node.");
+        info.redis.db = self.info.redis.db;
+
+        Self::open_with(
+            info,
+            Topology::Node,
+            self.credentials.clone(),
+            self.certificates.clone(),
+        )
+    }
 
-        cloned
+    /// Follows the sentinels' announcements of failovers, when connecting through them.
+    pub fn spawn_sentinel_watch(&self) {
+        if let Topology::Sentinel(sentinel) = &self.topology {
+            sentinel.clone().spawn_watch();
+        }
+    }
+
+    /// Re-reads the credential files every `interval` so rotated secrets are picked up.
+    pub fn spawn_credential_reload(&self, interval: Duration) {
+        let credentials = self.credentials.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                credentials.reload();
+            }
+        });
+    }
+
+    pub fn get_pool(&self) -> Arc<RedisPool> {
+        self.pool.clone()
     }
 }
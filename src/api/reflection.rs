@@ -0,0 +1,217 @@
+use once_cell::sync::Lazy;
+use prost::Message;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::Arc;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+
+use crate::api::reflection_proto::server_reflection_request::MessageRequest;
+use crate::api::reflection_proto::server_reflection_response::MessageResponse;
+use crate::api::reflection_proto::server_reflection_server::{
+    ServerReflection, ServerReflectionServer,
+};
+use crate::api::reflection_proto::{
+    ErrorResponse, ExtensionNumberResponse, FileDescriptorResponse, ListServiceResponse,
+    ServerReflectionRequest, ServerReflectionResponse, ServiceResponse,
+};
+
+/// Descriptors of every proto the server was built from, written by `build.rs`.
+const FILE_DESCRIPTOR_SET: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/mr_cache.bin"));
+
+/// Every gRPC method the protos declare, by the `/package.Service/Method` path it is called on.
+pub static METHOD_PATHS: Lazy<HashSet<String>> = Lazy::new(|| {
+    let set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET)
+        .expect("Failed to decode the file descriptor set.");
+    let mut paths = HashSet::new();
+    for file in &set.file {
+        for service in &file.service {
+            let service_name = qualify(file.package(), service.name());
+            for method in &service.method {
+                paths.insert(format!("/{}/{}", service_name, method.name()));
+            }
+        }
+    }
+    paths
+});
+
+/// The protos served, indexed by file name and by every symbol they declare.
+struct Descriptors {
+    files: HashMap<String, FileDescriptorProto>,
+    symbols: HashMap<String, String>,
+    services: Vec<String>,
+}
+
+fn qualify(scope: &str, name: &str) -> String {
+    if scope.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", scope, name)
+    }
+}
+
+impl Descriptors {
+    fn load() -> Self {
+        let set = FileDescriptorSet::decode(FILE_DESCRIPTOR_SET)
+            .expect("Failed to decode the file descriptor set.");
+        let mut descriptors = Self {
+            files: HashMap::new(),
+            symbols: HashMap::new(),
+            services: Vec::new(),
+        };
+
+        for file in set.file {
+            let name = file.name().to_string();
+            let package = file.package();
+
+            for message in &file.message_type {
+                descriptors.add_message(&name, package, message);
+            }
+            for enumeration in &file.enum_type {
+                descriptors.add_symbol(&name, qualify(package, enumeration.name()));
+            }
+            for service in &file.service {
+                let service_name = qualify(package, service.name());
+                for method in &service.method {
+                    descriptors.add_symbol(&name, qualify(&service_name, method.name()));
+                }
+                descriptors.add_symbol(&name, service_name.clone());
+                descriptors.services.push(service_name);
+            }
+
+            descriptors.files.insert(name, file);
+        }
+
+        descriptors
+    }
+
+    fn add_symbol(&mut self, file: &str, symbol: String) {
+        self.symbols.insert(symbol, file.to_string());
+    }
+
+    fn add_message(&mut self, file: &str, scope: &str, message: &DescriptorProto) {
+        let name = qualify(scope, message.name());
+        for nested in &message.nested_type {
+            self.add_message(file, &name, nested);
+        }
+        for enumeration in &message.enum_type {
+            self.add_symbol(file, qualify(&name, enumeration.name()));
+        }
+        self.add_symbol(file, name);
+    }
+
+    /// Serializes a file followed by everything it imports, each file once.
+    fn file_with_dependencies(&self, name: &str) -> Result<Vec<Vec<u8>>, Status> {
+        let mut pending = vec![name.to_string()];
+        let mut seen = Vec::new();
+        let mut encoded = Vec::new();
+
+        while let Some(name) = pending.pop() {
+            if seen.contains(&name) {
+                continue;
+            }
+            let file = self
+                .files
+                .get(&name)
+                .ok_or_else(|| Status::not_found("Unknown file ".to_string() + &name))?;
+            encoded.push(file.encode_to_vec());
+            pending.extend(file.dependency.iter().cloned());
+            seen.push(name);
+        }
+
+        Ok(encoded)
+    }
+
+    fn answer(&self, request: &MessageRequest) -> Result<MessageResponse, Status> {
+        match request {
+            MessageRequest::FileByFilename(name) => Ok(MessageResponse::FileDescriptorResponse(
+                FileDescriptorResponse {
+                    file_descriptor_proto: self.file_with_dependencies(name)?,
+                },
+            )),
+            MessageRequest::FileContainingSymbol(symbol) => {
+                let file = self
+                    .symbols
+                    .get(symbol.trim_start_matches('.'))
+                    .ok_or_else(|| Status::not_found("Unknown symbol ".to_string() + symbol))?;
+                Ok(MessageResponse::FileDescriptorResponse(
+                    FileDescriptorResponse {
+                        file_descriptor_proto: self.file_with_dependencies(file)?,
+                    },
+                ))
+            }
+            // None of the protos declare extensions.
+            MessageRequest::FileContainingExtension(extension) => Err(Status::not_found(
+                "No extensions of ".to_string() + &extension.containing_type,
+            )),
+            MessageRequest::AllExtensionNumbersOfType(base_type_name) => Ok(
+                MessageResponse::AllExtensionNumbersResponse(ExtensionNumberResponse {
+                    base_type_name: base_type_name.clone(),
+                    extension_number: Vec::new(),
+                }),
+            ),
+            MessageRequest::ListServices(_) => {
+                Ok(MessageResponse::ListServicesResponse(ListServiceResponse {
+                    service: self
+                        .services
+                        .iter()
+                        .map(|name| ServiceResponse { name: name.clone() })
+                        .collect(),
+                }))
+            }
+        }
+    }
+
+    fn respond(&self, request: ServerReflectionRequest) -> ServerReflectionResponse {
+        let answer = match &request.message_request {
+            Some(message_request) => self.answer(message_request),
+            None => Err(Status::invalid_argument("Empty reflection request")),
+        };
+        let message_response = answer.unwrap_or_else(|status| {
+            MessageResponse::ErrorResponse(ErrorResponse {
+                error_code: status.code() as i32,
+                error_message: status.message().to_string(),
+            })
+        });
+
+        ServerReflectionResponse {
+            valid_host: request.host.clone(),
+            original_request: Some(request),
+            message_response: Some(message_response),
+        }
+    }
+}
+
+/// gRPC server reflection, letting tools like grpcurl and Postman discover the services without a
+/// copy of the protos.
+#[derive(Clone)]
+pub struct Reflection {
+    descriptors: Arc<Descriptors>,
+}
+
+impl Reflection {
+    pub fn service() -> ServerReflectionServer<Self> {
+        ServerReflectionServer::new(Self {
+            descriptors: Arc::new(Descriptors::load()),
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl ServerReflection for Reflection {
+    type ServerReflectionInfoStream =
+        Pin<Box<dyn Stream<Item = Result<ServerReflectionResponse, Status>> + Send>>;
+
+    async fn server_reflection_info(
+        &self,
+        request: Request<Streaming<ServerReflectionRequest>>,
+    ) -> Result<Response<Self::ServerReflectionInfoStream>, Status> {
+        let descriptors = self.descriptors.clone();
+        let responses = request
+            .into_inner()
+            .map(move |request| request.map(|request| descriptors.respond(request)));
+
+        Ok(Response::new(Box::pin(responses)))
+    }
+}
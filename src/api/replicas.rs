@@ -0,0 +1,324 @@
+use redis::{Commands, ErrorKind, RedisError, RedisResult};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tonic::{Request, Status};
+use tracing::{error, info, warn};
+
+use crate::api::meta::now_ms;
+use crate::api::metrics;
+use crate::api::pool::{Pool, RedisPool};
+use crate::api::slots;
+use crate::api::tenant::INTERNAL_PREFIX;
+use crate::config::ReplicasConfig;
+
+/// Request metadata asking for a read to see every write acknowledged so far: `strong` reads
+/// from the primary, and `eventual`, the default, lets it go to a replica.
+pub const CONSISTENCY_HEADER: &str = "x-mrcache-consistency";
+
+/// Where a `Scan` cursor keeps which replica the scan started on, plus one, above that
+/// replica's own cursor. Replicas are not used on a cluster, so no master index is there.
+const REPLICA_CURSOR_SHIFT: u32 = 40;
+
+/// How reads of a request may be served.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Consistency {
+    Strong,
+    Eventual,
+}
+
+impl Consistency {
+    pub fn of<T>(request: &Request<T>) -> Result<Self, Status> {
+        match request.metadata().get(CONSISTENCY_HEADER) {
+            None => Ok(Self::Eventual),
+            Some(value) => match value.to_str() {
+                Ok("strong") => Ok(Self::Strong),
+                Ok("eventual") => Ok(Self::Eventual),
+                _ => Err(Status::invalid_argument(
+                    "Consistency must be strong or eventual",
+                )),
+            },
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Strong => "strong",
+            Self::Eventual => "eventual",
+        }
+    }
+}
+
+/// A replica reads can be sent to while it keeps up with the primary.
+pub struct Replica {
+    name: String,
+    index: usize,
+    pool: Arc<RedisPool>,
+    keeping_up: AtomicBool,
+}
+
+impl Replica {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn pool(&self) -> &RedisPool {
+        &self.pool
+    }
+
+    /// Stops sending reads here until the next check finds it keeping up again.
+    pub fn exclude(&self) {
+        if self.keeping_up.swap(false, Ordering::SeqCst) {
+            warn!(
+                replica = self.name,
+                "Excluding Redis replica after a failed read"
+            );
+        }
+    }
+}
+
+/// The replicas of the shared Redis DB, see `ReplicasConfig`. Each check writes the time to a
+/// heartbeat key on the primary, and a replica is excluded while the heartbeat it has lags the
+/// one written before by more than `max_lag_ms`.
+pub struct Replicas {
+    replicas: Vec<Replica>,
+    primary: Arc<RedisPool>,
+    max_lag_ms: u64,
+    next: AtomicUsize,
+    /// The heartbeat written by the last check, what each replica should have by the next.
+    last_beat: Mutex<Option<u64>>,
+}
+
+impl Replicas {
+    pub fn from_config(pool: &Pool, config: &ReplicasConfig) -> Option<Self> {
+        if config.nodes.is_empty() {
+            return None;
+        }
+        assert!(
+            !slots::is_cluster(),
+            "Replicas cannot be used with Redis Cluster."
+        );
+
+        let replicas = config
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(index, node)| Replica {
+                name: node.clone(),
+                index,
+                pool: pool.open_node(node).get_pool(),
+                keeping_up: AtomicBool::new(false),
+            })
+            .collect();
+
+        Some(Self {
+            replicas,
+            primary: pool.get_pool(),
+            max_lag_ms: config.max_lag_ms,
+            next: AtomicUsize::new(0),
+            last_beat: Mutex::new(None),
+        })
+    }
+
+    /// The next replica in turn that keeps up, or `None` for the primary.
+    pub fn pick(&self, consistency: Consistency) -> Option<&Replica> {
+        if consistency == Consistency::Strong {
+            return None;
+        }
+
+        let keeping_up: Vec<&Replica> = self
+            .replicas
+            .iter()
+            .filter(|replica| replica.keeping_up.load(Ordering::SeqCst))
+            .collect();
+        match keeping_up.len() {
+            0 => None,
+            len => Some(keeping_up[self.next.fetch_add(1, Ordering::Relaxed) % len]),
+        }
+    }
+
+    /// Where a scan page is read from: the node the scan started on, as named in the cursor, or
+    /// one picked for a new scan. Returns the cursor with the replica taken out. A scan whose
+    /// replica has since been excluded is `Aborted`, as its cursor means nothing to other nodes.
+    pub fn pick_for_scan(
+        &self,
+        consistency: Consistency,
+        cursor: u64,
+    ) -> Result<(Option<&Replica>, u64), Status> {
+        let node_cursor = cursor & ((1 << REPLICA_CURSOR_SHIFT) - 1);
+        match (cursor, cursor >> REPLICA_CURSOR_SHIFT) {
+            (0, _) => Ok((self.pick(consistency), 0)),
+            (_, 0) => Ok((None, node_cursor)),
+            (_, replica) => match self.replicas.get(replica as usize - 1) {
+                Some(replica) if replica.keeping_up.load(Ordering::SeqCst) => {
+                    Ok((Some(replica), node_cursor))
+                }
+                Some(_) => Err(Status::aborted(
+                    "Replica the scan was on is no longer in use, restart the scan",
+                )),
+                None => Err(Status::invalid_argument("Invalid scan cursor")),
+            },
+        }
+    }
+
+    /// Marks a scan's next cursor with the replica it is on, so the scan carries on there.
+    pub fn scan_cursor(replica: Option<&Replica>, cursor: u64) -> u64 {
+        match (replica, cursor) {
+            (Some(replica), cursor) if cursor != 0 => {
+                cursor | ((replica.index as u64 + 1) << REPLICA_CURSOR_SHIFT)
+            }
+            _ => cursor,
+        }
+    }
+
+    fn heartbeat_key() -> String {
+        INTERNAL_PREFIX.to_string() + "replica:heartbeat"
+    }
+
+    /// Measures every replica's lag against the last heartbeat, then writes the next one.
+    fn check(&self, interval: Duration) -> RedisResult<()> {
+        let last_beat = *self.last_beat.lock().unwrap();
+        if let Some(last_beat) = last_beat {
+            for replica in &self.replicas {
+                let lag_ms = replica
+                    .pool
+                    .get()
+                    .map_err(|e| RedisError::from((ErrorKind::IoError, "pool", e.to_string())))
+                    .and_then(|mut con| con.get::<_, Option<u64>>(Self::heartbeat_key()))
+                    .map(|beat| last_beat.saturating_sub(beat.unwrap_or(0)));
+                self.update(replica, lag_ms);
+            }
+        }
+
+        let beat = now_ms();
+        let mut con = self
+            .primary
+            .get()
+            .map_err(|e| RedisError::from((ErrorKind::IoError, "pool", e.to_string())))?;
+        let expiry_ms = 10 * interval.as_millis() as u64;
+        con.pset_ex::<_, _, ()>(Self::heartbeat_key(), beat, expiry_ms)?;
+        *self.last_beat.lock().unwrap() = Some(beat);
+        Ok(())
+    }
+
+    fn update(&self, replica: &Replica, lag_ms: RedisResult<u64>) {
+        let keeping_up = match &lag_ms {
+            Ok(lag_ms) => {
+                metrics::observe_replica_lag(&replica.name, *lag_ms);
+                *lag_ms <= self.max_lag_ms
+            }
+            Err(_) => false,
+        };
+
+        let was_keeping_up = replica.keeping_up.swap(keeping_up, Ordering::SeqCst);
+        match (was_keeping_up, keeping_up, lag_ms) {
+            (false, true, _) => info!(replica = replica.name, "Reading from Redis replica"),
+            (true, false, Ok(lag_ms)) => {
+                warn!(
+                    replica = replica.name,
+                    lag_ms, "Excluding lagging Redis replica"
+                )
+            }
+            (true, false, Err(e)) => {
+                warn!(replica = replica.name, error = %e, "Excluding unreachable Redis replica")
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks the replicas every `interval` in the background.
+    pub fn spawn_lag_check(self: Arc<Self>, interval: Duration) {
+        tokio::spawn(async move {
+            loop {
+                let replicas = self.clone();
+                let checked = tokio::task::spawn_blocking(move || replicas.check(interval)).await;
+
+                match checked {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!(error = %e, "Failed to write the replica heartbeat"),
+                    Err(e) => error!(error = %e, "Replica check panicked"),
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedisConfig;
+
+    /// Two replicas that are never connected to, both keeping up.
+    fn replicas() -> Replicas {
+        let pool = Pool::new(&RedisConfig {
+            host: "127.0.0.1".to_string(),
+            ..RedisConfig::default()
+        });
+        let config = ReplicasConfig {
+            nodes: vec!["127.0.0.1:6391".to_string(), "127.0.0.1:6392".to_string()],
+            ..ReplicasConfig::default()
+        };
+        let replicas = Replicas::from_config(&pool, &config).unwrap();
+        for replica in &replicas.replicas {
+            replica.keeping_up.store(true, Ordering::SeqCst);
+        }
+        replicas
+    }
+
+    #[test]
+    fn scan_cursors_carry_on_the_replica_they_started_on() {
+        let replicas = replicas();
+        let second = &replicas.replicas[1];
+
+        let cursor = Replicas::scan_cursor(Some(second), 5);
+        assert_eq!(cursor, 5 | 2 << REPLICA_CURSOR_SHIFT);
+        let (replica, node_cursor) = replicas.pick_for_scan(Consistency::Strong, cursor).unwrap();
+        assert_eq!(replica.map(Replica::name), Some("127.0.0.1:6392"));
+        assert_eq!(node_cursor, 5);
+
+        assert_eq!(Replicas::scan_cursor(Some(second), 0), 0);
+        assert_eq!(Replicas::scan_cursor(None, 5), 5);
+        let (replica, node_cursor) = replicas.pick_for_scan(Consistency::Eventual, 5).unwrap();
+        assert!(replica.is_none());
+        assert_eq!(node_cursor, 5);
+    }
+
+    #[test]
+    fn scans_start_on_a_replica_keeping_up() {
+        let replicas = replicas();
+        replicas.replicas[0].exclude();
+
+        for _ in 0..3 {
+            let (replica, _) = replicas.pick_for_scan(Consistency::Eventual, 0).unwrap();
+            assert_eq!(replica.map(Replica::name), Some("127.0.0.1:6392"));
+        }
+        let (replica, _) = replicas.pick_for_scan(Consistency::Strong, 0).unwrap();
+        assert!(replica.is_none());
+
+        replicas.replicas[1].exclude();
+        let (replica, _) = replicas.pick_for_scan(Consistency::Eventual, 0).unwrap();
+        assert!(replica.is_none());
+    }
+
+    #[test]
+    fn scans_on_an_excluded_replica_must_restart() {
+        let replicas = replicas();
+        let cursor = Replicas::scan_cursor(Some(&replicas.replicas[0]), 5);
+        replicas.replicas[0].exclude();
+
+        let refused = replicas
+            .pick_for_scan(Consistency::Eventual, cursor)
+            .err()
+            .unwrap();
+        assert_eq!(refused.code(), tonic::Code::Aborted);
+
+        let unknown = 5 | 3 << REPLICA_CURSOR_SHIFT;
+        let refused = replicas
+            .pick_for_scan(Consistency::Eventual, unknown)
+            .err()
+            .unwrap();
+        assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+    }
+}
@@ -0,0 +1,806 @@
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{
+    AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+};
+use tokio::io::{BufReader, BufWriter};
+use tokio::net::TcpListener;
+use tokio_stream::StreamExt;
+use tonic::transport::Certificate;
+use tonic::{Code, Request, Response, Status};
+use tracing::{debug, info_span, warn, Instrument};
+
+use crate::api::auth::{Auth, Caller};
+use crate::api::client::MrCacheService;
+use crate::api::metrics;
+use crate::api::mr_cache::mr_cache_server::MrCache;
+use crate::api::mr_cache::{
+    HashedKeyValues, HashedKeys, Key, KeyValue, KeyValues, Keys, Scan, Value,
+};
+use crate::api::tls::{self, Tls};
+
+/// Largest bulk string accepted, Redis' default `proto-max-bulk-len`.
+const MAX_BULK_LEN: u64 = 512 * 1024 * 1024;
+
+/// Most arguments a single command may have.
+const MAX_ARGS: u64 = 1024 * 1024;
+
+/// Longest inline command or array header line.
+const MAX_LINE_LEN: u64 = 64 * 1024;
+
+/// Commands answered, to tell a wrong argument count from an unknown command.
+const COMMANDS: [&str; 20] = [
+    "PING", "ECHO", "QUIT", "HELLO", "AUTH", "SELECT", "CLIENT", "COMMAND", "GET", "MGET", "SET",
+    "MSET", "HSET", "HMSET", "HGET", "HMGET", "HGETALL", "HKEYS", "HVALS", "SCAN",
+];
+
+/// A RESP2/RESP3 reply.
+enum Reply {
+    Simple(&'static str),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Vec<Reply>),
+    Map(Vec<(Reply, Reply)>),
+}
+
+impl Reply {
+    fn ok() -> Self {
+        Reply::Simple("OK")
+    }
+
+    fn bulk(value: impl Into<Vec<u8>>) -> Self {
+        Reply::Bulk(Some(value.into()))
+    }
+
+    fn bulks<T: Into<Vec<u8>>>(values: impl IntoIterator<Item = T>) -> Self {
+        Reply::Array(values.into_iter().map(Reply::bulk).collect())
+    }
+
+    /// Maps a status to the error prefixes Redis clients know: `NOAUTH` for missing or bad
+    /// credentials, `NOPERM` for a role that does not allow the command and `ERR` otherwise.
+    fn status(status: Status) -> Self {
+        let prefix = match status.code() {
+            Code::Unauthenticated => "NOAUTH",
+            Code::PermissionDenied => "NOPERM",
+            _ => "ERR",
+        };
+        Reply::Error(format!("{} {}", prefix, status.message()))
+    }
+
+    fn encode(&self, protocol: u8, out: &mut Vec<u8>) {
+        match self {
+            Reply::Simple(value) => out.extend_from_slice(format!("+{}\r\n", value).as_bytes()),
+            Reply::Error(message) => {
+                let message = message.replace(['\r', '\n'], " ");
+                out.extend_from_slice(format!("-{}\r\n", message).as_bytes());
+            }
+            Reply::Integer(value) => out.extend_from_slice(format!(":{}\r\n", value).as_bytes()),
+            Reply::Bulk(Some(value)) => {
+                out.extend_from_slice(format!("${}\r\n", value.len()).as_bytes());
+                out.extend_from_slice(value);
+                out.extend_from_slice(b"\r\n");
+            }
+            Reply::Bulk(None) if protocol >= 3 => out.extend_from_slice(b"_\r\n"),
+            Reply::Bulk(None) => out.extend_from_slice(b"$-1\r\n"),
+            Reply::Array(items) => {
+                out.extend_from_slice(format!("*{}\r\n", items.len()).as_bytes());
+                for item in items {
+                    item.encode(protocol, out);
+                }
+            }
+            // RESP2 has no maps, so they go out as flat arrays of keys and values.
+            Reply::Map(entries) => {
+                let header = if protocol >= 3 {
+                    format!("%{}\r\n", entries.len())
+                } else {
+                    format!("*{}\r\n", entries.len() * 2)
+                };
+                out.extend_from_slice(header.as_bytes());
+                for (key, value) in entries {
+                    key.encode(protocol, out);
+                    value.encode(protocol, out);
+                }
+            }
+        }
+    }
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "Protocol error: ".to_string() + message,
+    )
+}
+
+/// Reads a line without its `\r\n`, or `None` once the client has hung up.
+async fn read_line<R: AsyncBufRead + Unpin>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    if reader
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)
+        .await?
+        == 0
+    {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(protocol_error("line too long"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    Ok(Some(line))
+}
+
+fn parse_len(header: &[u8], max: u64) -> io::Result<u64> {
+    std::str::from_utf8(header)
+        .ok()
+        .and_then(|len| len.parse::<u64>().ok())
+        .filter(|len| *len <= max)
+        .ok_or_else(|| protocol_error("invalid length"))
+}
+
+/// Reads a command sent as an array of bulk strings, or inline as a line of words like
+/// `redis-cli` and telnet sessions do.
+async fn read_command<R>(reader: &mut R) -> io::Result<Option<Vec<Vec<u8>>>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let line = match read_line(reader).await? {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let count = match line.strip_prefix(b"*") {
+        Some(count) => parse_len(count, MAX_ARGS)?,
+        None => {
+            let words = line
+                .split(u8::is_ascii_whitespace)
+                .filter(|word| !word.is_empty())
+                .map(<[u8]>::to_vec)
+                .collect();
+            return Ok(Some(words));
+        }
+    };
+
+    let mut args = Vec::new();
+    for _ in 0..count {
+        let header = read_line(reader)
+            .await?
+            .ok_or_else(|| protocol_error("unexpected end of command"))?;
+        let len = header
+            .strip_prefix(b"$")
+            .ok_or_else(|| protocol_error("expected '$'"))?;
+        let len = parse_len(len, MAX_BULK_LEN)?;
+
+        let mut arg = Vec::new();
+        reader.take(len + 2).read_to_end(&mut arg).await?;
+        if arg.len() as u64 != len + 2 || !arg.ends_with(b"\r\n") {
+            return Err(protocol_error("bulk string length mismatch"));
+        }
+        arg.truncate(len as usize);
+        args.push(arg);
+    }
+
+    Ok(Some(args))
+}
+
+/// A client connection: the protocol version it asked for and who it logged in as.
+struct Session {
+    protocol: u8,
+    caller: Caller,
+}
+
+impl Session {
+    /// `AUTH [username] password`, see [`Caller::login`].
+    fn login(&mut self, username: Option<&str>, password: &str) -> Result<Reply, Status> {
+        match self.caller.login(username, password) {
+            Ok(()) => Ok(Reply::ok()),
+            Err(status) => Ok(Reply::Error("WRONGPASS ".to_string() + status.message())),
+        }
+    }
+
+    /// `HELLO [protover [AUTH username password] [SETNAME clientname]]`.
+    fn hello(&mut self, args: &[String]) -> Result<Reply, Status> {
+        let (protocol, options) = match args.split_first() {
+            Some((protocol, options)) => match protocol.as_str() {
+                "2" => (2, options),
+                "3" => (3, options),
+                _ => {
+                    return Ok(Reply::Error(
+                        "NOPROTO unsupported protocol version".to_string(),
+                    ))
+                }
+            },
+            None => (self.protocol, args),
+        };
+
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            match option.to_uppercase().as_str() {
+                "AUTH" => match (options.next(), options.next()) {
+                    (Some(username), Some(password)) => {
+                        if let reply @ Reply::Error(_) = self.login(Some(username), password)? {
+                            return Ok(reply);
+                        }
+                    }
+                    _ => return Err(Status::invalid_argument("syntax error")),
+                },
+                // Client names are not kept.
+                "SETNAME" => {
+                    options
+                        .next()
+                        .ok_or_else(|| Status::invalid_argument("syntax error"))?;
+                }
+                _ => return Err(Status::invalid_argument("syntax error")),
+            }
+        }
+        self.protocol = protocol;
+
+        Ok(Reply::Map(vec![
+            (Reply::bulk("server"), Reply::bulk("mrcache")),
+            (
+                Reply::bulk("version"),
+                Reply::bulk(env!("CARGO_PKG_VERSION")),
+            ),
+            (Reply::bulk("proto"), Reply::Integer(protocol as i64)),
+            (Reply::bulk("id"), Reply::Integer(0)),
+            (Reply::bulk("mode"), Reply::bulk("standalone")),
+            (Reply::bulk("role"), Reply::bulk("master")),
+            (Reply::bulk("modules"), Reply::Array(Vec::new())),
+        ]))
+    }
+}
+
+/// An argument that is not a value, like a key, field or option, which mrCache only takes as
+/// UTF-8.
+fn text(arg: &[u8]) -> Result<&str, Status> {
+    std::str::from_utf8(arg).map_err(|_| {
+        Status::invalid_argument("only values may be binary, keys and options must be UTF-8")
+    })
+}
+
+fn texts(args: &[Vec<u8>]) -> Result<Vec<String>, Status> {
+    args.iter()
+        .map(|arg| text(arg).map(str::to_string))
+        .collect()
+}
+
+fn keys(keys: &[Vec<u8>]) -> Result<Keys, Status> {
+    Ok(Keys {
+        keys: texts(keys)?.into_iter().map(|key| Key { key }).collect(),
+        ..Default::default()
+    })
+}
+
+/// Pairs of a key and its value, which goes in `data` when it is not UTF-8.
+fn key_values(pairs: &[Vec<u8>]) -> Result<Vec<KeyValue>, Status> {
+    pairs
+        .chunks(2)
+        .map(|pair| {
+            let (value, data) = match String::from_utf8(pair[1].clone()) {
+                Ok(value) => (value, Vec::new()),
+                Err(err) => (String::new(), err.into_bytes()),
+            };
+            Ok(KeyValue {
+                key: text(&pair[0])?.to_string(),
+                value,
+                data,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// The bytes of a value, or a nil when it is missing.
+fn bulk_or_nil(value: Option<Value>) -> Reply {
+    Reply::Bulk(value.map(Value::into_bytes))
+}
+
+fn integer(arg: &[u8]) -> Result<u64, Status> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse().ok())
+        .ok_or_else(|| Status::invalid_argument("value is not an integer or out of range"))
+}
+
+/// Serves the Redis commands mrCache has an RPC for by calling the gRPC service, so Redis clients
+/// get the same auth, tenants, metrics and Redis logic. Keys are set with `SET`/`MSET` and
+/// `HSET`/`HMSET` (expiring after `EX`/`PX` as a hard TTL), read with `GET`/`MGET`,
+/// `HGET`/`HMGET`, `HGETALL`, `HKEYS` and `HVALS`, and listed with `SCAN`.
+pub struct Resp {
+    service: Arc<MrCacheService>,
+    auth: Auth,
+}
+
+impl Resp {
+    pub fn new(service: Arc<MrCacheService>, auth: Auth) -> Arc<Self> {
+        Arc::new(Self { service, auth })
+    }
+
+    pub async fn serve(self: Arc<Self>, address: SocketAddr, tls: Option<Arc<Tls>>) {
+        let listener = TcpListener::bind(address)
+            .await
+            .expect("Failed to bind RESP port!");
+
+        match tls {
+            Some(tls) => {
+                let mut incoming = tls.incoming(listener);
+                while let Some(stream) = incoming.next().await {
+                    let Ok(stream) = stream else { continue };
+                    let peer_certs = tls::peer_certs(&stream);
+                    tokio::spawn(self.clone().connection(stream, peer_certs));
+                }
+            }
+            None => loop {
+                match listener.accept().await {
+                    Ok((stream, _)) => {
+                        tokio::spawn(self.clone().connection(stream, None));
+                    }
+                    Err(e) => warn!(error = %e, "Failed to accept RESP connection"),
+                }
+            },
+        }
+    }
+
+    async fn connection<S>(self: Arc<Self>, stream: S, peer_certs: Option<Vec<Certificate>>)
+    where
+        S: AsyncRead + AsyncWrite,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        let mut reader = BufReader::new(reader);
+        let mut writer = BufWriter::new(writer);
+        let mut session = Session {
+            protocol: 2,
+            caller: Caller::new(self.auth.clone(), peer_certs),
+        };
+        let mut out = Vec::new();
+
+        loop {
+            let (reply, quit) = match read_command(&mut reader).await {
+                Ok(Some(args)) if args.is_empty() => continue,
+                Ok(Some(args)) => {
+                    let quit = args[0].eq_ignore_ascii_case(b"QUIT");
+                    (self.execute(&mut session, args).await, quit)
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    debug!(error = %e, "Closing RESP connection");
+                    (Reply::Error("ERR ".to_string() + &e.to_string()), true)
+                }
+            };
+
+            out.clear();
+            reply.encode(session.protocol, &mut out);
+            let written = match writer.write_all(&out).await {
+                // Replies to pipelined commands go out together once the pipeline is drained.
+                Ok(()) if reader.buffer().is_empty() || quit => writer.flush().await,
+                written => written,
+            };
+            if written.is_err() || quit {
+                break;
+            }
+        }
+    }
+
+    async fn execute(&self, session: &mut Session, args: Vec<Vec<u8>>) -> Reply {
+        let command = String::from_utf8_lossy(&args[0]).to_uppercase();
+        let span = info_span!("resp", command = %command);
+
+        self.command(session, &command, &args[1..])
+            .instrument(span)
+            .await
+            .unwrap_or_else(Reply::status)
+    }
+
+    /// Times the RPC a command is served by and counts it by its status, like the gRPC
+    /// metrics layer does for gRPC calls.
+    async fn call<T, R, F, Fut>(
+        &self,
+        rpc: &str,
+        request: Result<Request<T>, Status>,
+        call: F,
+    ) -> Result<R, Status>
+    where
+        F: FnOnce(Request<T>) -> Fut,
+        Fut: Future<Output = Result<Response<R>, Status>>,
+    {
+        let start = Instant::now();
+        let result = match request {
+            Ok(request) => call(request).await,
+            Err(status) => Err(status),
+        };
+        let code = result.as_ref().map_or_else(Status::code, |_| Code::Ok);
+        metrics::observe_rpc(rpc, start, code);
+
+        result.map(Response::into_inner)
+    }
+
+    /// `GET` and `MGET`, with `None` for each missing key.
+    async fn get(
+        &self,
+        session: &Session,
+        rpc: &str,
+        names: &[Vec<u8>],
+    ) -> Result<Vec<Option<Value>>, Status> {
+        let found = self
+            .call(
+                rpc,
+                keys(names).and_then(|keys| session.caller.request(keys)),
+                |r| async { self.service.mget(r).await.map(Response::new) },
+            )
+            .await?;
+
+        Ok(found)
+    }
+
+    /// `HGET` and `HMGET`, with `None` for each missing field.
+    async fn hget(
+        &self,
+        session: &Session,
+        rpc: &str,
+        key: &[u8],
+        fields: &[Vec<u8>],
+    ) -> Result<Vec<Option<Value>>, Status> {
+        let hashed_keys = HashedKeys {
+            key: Some(Key {
+                key: text(key)?.to_string(),
+            }),
+            keys: Some(keys(fields)?),
+        };
+        let found = self
+            .call(rpc, session.caller.request(hashed_keys), |r| async {
+                self.service.hmget(r).await.map(Response::new)
+            })
+            .await?;
+
+        Ok(found)
+    }
+
+    async fn set(
+        &self,
+        session: &Session,
+        pairs: &[Vec<u8>],
+        options: &[Vec<u8>],
+    ) -> Result<Reply, Status> {
+        let mut hard_ttl_ms = None;
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            let ttl = options.next().map(|ttl| integer(ttl));
+            let ttl_ms = match (option.to_ascii_uppercase().as_slice(), ttl) {
+                (b"EX", Some(seconds)) => seconds?.checked_mul(1000),
+                (b"PX", Some(ms)) => Some(ms?),
+                _ => return Err(Status::invalid_argument("syntax error")),
+            };
+            // Like Redis, a TTL has to be positive and fit in milliseconds.
+            hard_ttl_ms = match ttl_ms {
+                Some(ttl_ms) if ttl_ms > 0 => Some(ttl_ms),
+                _ => {
+                    return Err(Status::invalid_argument(
+                        "invalid expire time in 'set' command",
+                    ))
+                }
+            };
+        }
+
+        let key_values = KeyValues {
+            key_values: key_values(pairs)?,
+            soft_ttl_ms: None,
+            hard_ttl_ms,
+            tags: Vec::new(),
+        };
+        self.call("SET", session.caller.request(key_values), |r| {
+            self.service.set(r)
+        })
+        .await?;
+
+        Ok(Reply::ok())
+    }
+
+    async fn hset(&self, session: &Session, key: &[u8], pairs: &[Vec<u8>]) -> Result<(), Status> {
+        let hashed_key_values = HashedKeyValues {
+            key: Some(Key {
+                key: text(key)?.to_string(),
+            }),
+            key_values: Some(KeyValues {
+                key_values: key_values(pairs)?,
+                soft_ttl_ms: None,
+                hard_ttl_ms: None,
+                tags: Vec::new(),
+            }),
+        };
+        self.call("HSET", session.caller.request(hashed_key_values), |r| {
+            self.service.hset(r)
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    async fn scan(
+        &self,
+        session: &Session,
+        cursor: &[u8],
+        options: &[Vec<u8>],
+    ) -> Result<Reply, Status> {
+        let mut scan = Scan {
+            pattern: String::new(),
+            cursor: integer(cursor)?,
+            count: 0,
+        };
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            match (option.to_ascii_uppercase().as_slice(), options.next()) {
+                (b"MATCH", Some(pattern)) => scan.pattern = text(pattern)?.to_string(),
+                (b"COUNT", Some(count)) => scan.count = integer(count)?,
+                _ => return Err(Status::invalid_argument("syntax error")),
+            }
+        }
+
+        let page = self
+            .call("SCAN", session.caller.request(scan), |r| {
+                self.service.scan(r)
+            })
+            .await?;
+        let keys = page.keys.map(|keys| keys.keys).unwrap_or_default();
+
+        Ok(Reply::Array(vec![
+            Reply::bulk(page.cursor.to_string()),
+            Reply::bulks(keys.into_iter().map(|key| key.key)),
+        ]))
+    }
+
+    async fn hash(&self, session: &Session, rpc: &str, key: &[u8]) -> Result<Reply, Status> {
+        let request = text(key).and_then(|key| {
+            session.caller.request(Key {
+                key: key.to_string(),
+            })
+        });
+        let reply = match rpc {
+            "HKEYS" => {
+                let keys = self.call(rpc, request, |r| self.service.hkeys(r)).await?;
+                Reply::bulks(keys.keys.into_iter().map(|key| key.key))
+            }
+            "HVALS" => {
+                let values = self.call(rpc, request, |r| self.service.hvals(r)).await?;
+                Reply::bulks(values.values.into_iter().map(Value::into_bytes))
+            }
+            // HGETALL answers with the fields and values interleaved.
+            _ => {
+                let values = self.call(rpc, request, |r| self.service.hgetall(r)).await?;
+                let mut values = values.values.into_iter().map(Value::into_bytes);
+                let mut entries = Vec::new();
+                while let (Some(field), Some(value)) = (values.next(), values.next()) {
+                    entries.push((Reply::bulk(field), Reply::bulk(value)));
+                }
+                Reply::Map(entries)
+            }
+        };
+
+        Ok(reply)
+    }
+
+    async fn command(
+        &self,
+        session: &mut Session,
+        command: &str,
+        args: &[Vec<u8>],
+    ) -> Result<Reply, Status> {
+        let even =
+            |pairs: &[Vec<u8>]| !pairs.is_empty() && pairs.chunks_exact(2).remainder().is_empty();
+
+        match (command, args) {
+            ("PING", []) => Ok(Reply::Simple("PONG")),
+            ("PING", [message]) | ("ECHO", [message]) => Ok(Reply::bulk(message.as_slice())),
+            ("QUIT", _) => Ok(Reply::ok()),
+            ("HELLO", args) => session.hello(&texts(args)?),
+            ("AUTH", [password]) => session.login(None, text(password)?),
+            ("AUTH", [username, password]) => session.login(Some(text(username)?), text(password)?),
+            ("SELECT", [db]) if db == b"0" => Ok(Reply::ok()),
+            ("SELECT", [_]) => Err(Status::invalid_argument(
+                "only DB 0 exists, pick a tenant with AUTH <tenant> <password>",
+            )),
+            ("CLIENT", [subcommand, ..])
+                if [&b"SETNAME"[..], b"SETINFO"]
+                    .contains(&subcommand.to_ascii_uppercase().as_slice()) =>
+            {
+                Ok(Reply::ok())
+            }
+            ("COMMAND", _) => Ok(Reply::Array(Vec::new())),
+            ("GET", [_]) => {
+                let found = self.get(session, command, args).await?;
+                Ok(bulk_or_nil(found.into_iter().next().flatten()))
+            }
+            ("MGET", keys) if !keys.is_empty() => {
+                let found = self.get(session, command, keys).await?;
+                Ok(Reply::Array(found.into_iter().map(bulk_or_nil).collect()))
+            }
+            ("SET", [key, value, options @ ..]) => {
+                self.set(session, &[key.clone(), value.clone()], options)
+                    .await
+            }
+            ("MSET", pairs) if even(pairs) => self.set(session, pairs, &[]).await,
+            // Redis counts the fields that were new, which mrCache cannot tell, so this is the
+            // number of fields written.
+            ("HSET", [key, pairs @ ..]) if even(pairs) => {
+                self.hset(session, key, pairs).await?;
+                Ok(Reply::Integer(pairs.len() as i64 / 2))
+            }
+            ("HMSET", [key, pairs @ ..]) if even(pairs) => {
+                self.hset(session, key, pairs).await?;
+                Ok(Reply::ok())
+            }
+            ("HGET", [key, field]) => {
+                let fields = std::slice::from_ref(field);
+                let found = self.hget(session, command, key, fields).await?;
+                Ok(bulk_or_nil(found.into_iter().next().flatten()))
+            }
+            ("HMGET", [key, fields @ ..]) if !fields.is_empty() => {
+                let found = self.hget(session, command, key, fields).await?;
+                Ok(Reply::Array(found.into_iter().map(bulk_or_nil).collect()))
+            }
+            ("HGETALL" | "HKEYS" | "HVALS", [key]) => self.hash(session, command, key).await,
+            ("SCAN", [cursor, options @ ..]) => self.scan(session, cursor, options).await,
+            (command, _) if COMMANDS.contains(&command) => Err(Status::invalid_argument(format!(
+                "wrong number of arguments for '{}' command",
+                command.to_lowercase()
+            ))),
+            (command, _) => Err(Status::invalid_argument(format!(
+                "unknown command '{}'",
+                command.to_lowercase()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::pool::Pool;
+    use crate::config::{AuthConfig, Config, RedisConfig};
+
+    /// A listener with auth on and no credentials anyone could log in with. Every command is
+    /// parsed and dispatched, but none gets as far as Redis, which is never connected to.
+    fn resp() -> Arc<Resp> {
+        let pool = Pool::new(&RedisConfig {
+            host: "127.0.0.1".to_string(),
+            ..RedisConfig::default()
+        });
+        let service = Arc::new(MrCacheService::new(&pool, &Config::default()));
+        let auth = Auth::from_config(&AuthConfig {
+            enabled: true,
+            ..AuthConfig::default()
+        });
+        Resp::new(service, auth)
+    }
+
+    /// Sends `input` on a connection of its own and returns all that was written back by the
+    /// time the listener closed it.
+    async fn exchange(input: &str) -> String {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let served = tokio::spawn(resp().connection(server, None));
+        let (mut reader, mut writer) = tokio::io::split(client);
+        writer.write_all(input.as_bytes()).await.unwrap();
+        writer.shutdown().await.unwrap();
+
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).await.unwrap();
+        served.await.unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    async fn commands(mut input: &[u8]) -> io::Result<Vec<Vec<Vec<u8>>>> {
+        let mut commands = Vec::new();
+        while let Some(command) = read_command(&mut input).await? {
+            commands.push(command);
+        }
+        Ok(commands)
+    }
+
+    fn args(args: &[&str]) -> Vec<Vec<u8>> {
+        args.iter().map(|arg| arg.as_bytes().to_vec()).collect()
+    }
+
+    #[tokio::test]
+    async fn reads_pipelined_array_and_inline_commands() {
+        let input =
+            b"*2\r\n$3\r\nGET\r\n$1\r\na\r\nPING  hello world\r\n\r\n*1\r\n$4\r\na\r\nb\r\n";
+        let commands = commands(input).await.unwrap();
+
+        assert_eq!(
+            commands,
+            vec![
+                args(&["GET", "a"]),
+                args(&["PING", "hello", "world"]),
+                Vec::new(),
+                args(&["a\r\nb"]),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_malformed_commands() {
+        for input in [
+            &b"*x\r\n"[..],
+            b"*1\r\n+a\r\n",
+            b"*1\r\n$5\r\nab\r\n",
+            b"*2\r\n$1\r\na\r\n",
+            b"*1\r\n$1\r\nabc\r\n",
+            b"*1\r\n$-1\r\n",
+        ] {
+            let refused = commands(input).await.unwrap_err();
+            assert_eq!(refused.kind(), io::ErrorKind::InvalidData, "{input:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_pipelined_commands_in_order() {
+        let output =
+            exchange("*1\r\n$4\r\nPING\r\nECHO hi\r\n*2\r\n$4\r\nping\r\n$2\r\nyo\r\n").await;
+        assert_eq!(output, "+PONG\r\n$2\r\nhi\r\n$2\r\nyo\r\n");
+    }
+
+    #[tokio::test]
+    async fn checks_arguments_before_calling_the_service() {
+        let output = exchange(
+            "SET a b PX 0\r\n\
+             SET a b EX 0\r\n\
+             SET a b EX 18446744073709551615\r\n\
+             SET a b PX\r\n\
+             SET a b KEEPTTL\r\n\
+             GET\r\n\
+             HMGET h\r\n\
+             BOGUS\r\n\
+             SELECT 1\r\n",
+        )
+        .await;
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(
+            lines,
+            vec![
+                "-ERR invalid expire time in 'set' command",
+                "-ERR invalid expire time in 'set' command",
+                "-ERR invalid expire time in 'set' command",
+                "-ERR syntax error",
+                "-ERR syntax error",
+                "-ERR wrong number of arguments for 'get' command",
+                "-ERR wrong number of arguments for 'hmget' command",
+                "-ERR unknown command 'bogus'",
+                "-ERR only DB 0 exists, pick a tenant with AUTH <tenant> <password>",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn needs_a_login_for_keys() {
+        let output = exchange("GET a\r\nMGET a b\r\nHMGET h f g\r\nAUTH nope\r\n").await;
+        let lines: Vec<&str> = output.lines().collect();
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[..3].iter().all(|line| line.starts_with("-NOAUTH ")));
+        assert!(lines[3].starts_with("-WRONGPASS "));
+    }
+
+    #[tokio::test]
+    async fn switches_protocol_with_hello() {
+        let output = exchange("HELLO 4\r\nHELLO 3\r\n").await;
+        let (refused, hello) = output.split_once("\r\n").unwrap();
+
+        assert_eq!(refused, "-NOPROTO unsupported protocol version");
+        assert!(hello.starts_with("%7\r\n$6\r\nserver\r\n$7\r\nmrcache\r\n"));
+    }
+
+    #[tokio::test]
+    async fn stops_at_quit_and_protocol_errors() {
+        assert_eq!(exchange("QUIT\r\nPING\r\n").await, "+OK\r\n");
+        assert_eq!(
+            exchange("*1\r\n+PING\r\nPING\r\n").await,
+            "-ERR Protocol error: expected '$'\r\n"
+        );
+    }
+}
@@ -0,0 +1,606 @@
+use axum::extract::rejection::{JsonRejection, QueryRejection};
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tonic::metadata::MetadataMap;
+use tonic::{Code, Request, Status};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+use crate::api::auth::{Auth, API_KEY_HEADER};
+use crate::api::client::MrCacheService;
+use crate::api::http_server::PeerCerts;
+use crate::api::metrics::RpcMetricsLayer;
+use crate::api::mr_cache::mr_cache_server::MrCache;
+use crate::api::mr_cache::{
+    ContentType, HashedKeyValues, HashedKeys, Key, KeyValue, KeyValues, Keys, Scan, Tags, Value,
+};
+
+/// A value as returned by the gRPC API.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ValueBody {
+    value: String,
+    /// Base64 of the value when it is not valid UTF-8, with `value` left empty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+    /// `raw`, `json`, `msgpack`, `cbor` or `protobuf`, for values set with a content type.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// Milliseconds since the value was last set, when mrCache knows it.
+    age_ms: Option<u64>,
+    /// The value is past its soft TTL. For string keys a refresh from the loader has been requested.
+    stale: bool,
+}
+
+impl From<Value> for ValueBody {
+    fn from(value: Value) -> Self {
+        let content_type = value.content_type();
+        Self {
+            data: (!value.data.is_empty()).then(|| BASE64.encode(&value.data)),
+            content_type: (content_type != ContentType::Unspecified)
+                .then(|| content_type.name().to_string()),
+            value: value.value,
+            age_ms: value.age_ms,
+            stale: value.stale,
+        }
+    }
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetBody {
+    #[serde(default)]
+    value: String,
+    /// Base64 of a binary value, used instead of `value`.
+    data: Option<String>,
+    /// `raw`, `json`, `msgpack`, `cbor` or `protobuf`, or the matching media type. Structured
+    /// values are validated, and read back as JSON where they can be transcoded.
+    content_type: Option<String>,
+    soft_ttl_ms: Option<u64>,
+    hard_ttl_ms: Option<u64>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SetHashBody {
+    #[serde(default)]
+    fields: HashMap<String, String>,
+    /// Base64 of binary field values, by field.
+    #[serde(default)]
+    data: HashMap<String, String>,
+    soft_ttl_ms: Option<u64>,
+    hard_ttl_ms: Option<u64>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Every field of a hash. The age and staleness are the hash's, shared by all its fields.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HashBody {
+    /// Fields whose values are valid UTF-8.
+    fields: HashMap<String, String>,
+    /// Base64 of the values that are not, by field.
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    data: HashMap<String, String>,
+    age_ms: Option<u64>,
+    stale: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FieldsBody {
+    fields: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ValuesBody {
+    values: Vec<ValueBody>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ScanQuery {
+    /// Glob-style pattern, matching every key when empty.
+    pattern: Option<String>,
+    /// Cursor from the previous page, 0 to start.
+    cursor: Option<u64>,
+    /// Hint for how many keys to look at, defaults to 10.
+    count: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ScanBody {
+    /// Cursor for the next page, 0 once the scan is complete.
+    cursor: u64,
+    keys: Vec<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct TagsBody {
+    tags: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct CountBody {
+    count: u64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    /// The gRPC status code the error maps from, e.g. `NotFound`.
+    code: String,
+    message: String,
+}
+
+/// A gRPC status turned into the closest HTTP status with a JSON body.
+pub struct ApiError(Status);
+
+impl From<Status> for ApiError {
+    fn from(status: Status) -> Self {
+        Self(status)
+    }
+}
+
+// Bodies and query strings that fail to parse are reported like any other bad argument.
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self(Status::invalid_argument(rejection.body_text()))
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self(Status::invalid_argument(rejection.body_text()))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match self.0.code() {
+            Code::Ok => StatusCode::OK,
+            Code::InvalidArgument | Code::OutOfRange | Code::FailedPrecondition => {
+                StatusCode::BAD_REQUEST
+            }
+            Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+            Code::PermissionDenied => StatusCode::FORBIDDEN,
+            Code::NotFound => StatusCode::NOT_FOUND,
+            Code::AlreadyExists | Code::Aborted => StatusCode::CONFLICT,
+            Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+            Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+            Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Code::DeadlineExceeded => StatusCode::GATEWAY_TIMEOUT,
+            Code::Cancelled | Code::Unknown | Code::Internal | Code::DataLoss => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        let body = ErrorBody {
+            code: format!("{:?}", self.0.code()),
+            message: self.0.message().to_string(),
+        };
+
+        let mut response = (status, Json(body)).into_response();
+        // For `RpcMetrics` to count the call by the code it failed with.
+        response.extensions_mut().insert(self.0.code());
+        response
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+/// Serves REST calls by handing them to the gRPC service, so both share the same auth, tenants
+/// and Redis logic.
+#[derive(Clone)]
+struct Gateway {
+    service: Arc<MrCacheService>,
+    auth: Auth,
+}
+
+impl Gateway {
+    /// Wraps `message` like a gRPC call, with the HTTP headers (API key, bearer token, tenant) as
+    /// its metadata and authenticated with the client certificate when there is no other.
+    fn request<T>(
+        &self,
+        headers: &HeaderMap,
+        peer_certs: &PeerCerts,
+        message: T,
+    ) -> ApiResult<Request<T>> {
+        let mut request = Request::new(message);
+        *request.metadata_mut() = MetadataMap::from_headers(headers.clone());
+        let peer_certs = peer_certs.0.as_deref().map(Vec::as_slice);
+        let grant = self.auth.authenticate(request.metadata(), peer_certs)?;
+        grant.record(&mut request);
+
+        Ok(request)
+    }
+}
+
+fn not_found(what: &str) -> ApiError {
+    ApiError(Status::not_found(what.to_string() + " not found"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/keys",
+    tag = "keys",
+    params(ScanQuery),
+    responses((status = 200, body = ScanBody), (status = "default", body = ErrorBody))
+)]
+async fn scan(
+    State(gateway): State<Gateway>,
+    query: Result<Query<ScanQuery>, QueryRejection>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+) -> ApiResult<Json<ScanBody>> {
+    let Query(query) = query?;
+    let scan = Scan {
+        pattern: query.pattern.unwrap_or_default(),
+        cursor: query.cursor.unwrap_or_default(),
+        count: query.count.unwrap_or_default(),
+    };
+    let page = gateway
+        .service
+        .scan(gateway.request(&headers, &peer_certs, scan)?)
+        .await?
+        .into_inner();
+
+    Ok(Json(ScanBody {
+        cursor: page.cursor,
+        keys: page
+            .keys
+            .map(|keys| keys.keys.into_iter().map(|key| key.key).collect())
+            .unwrap_or_default(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/keys/{key}",
+    tag = "strings",
+    params(("key" = String, Path, description = "Key, without any tenant prefix")),
+    responses((status = 200, body = ValueBody), (status = "default", body = ErrorBody))
+)]
+async fn get_key(
+    State(gateway): State<Gateway>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+) -> ApiResult<Json<ValueBody>> {
+    let keys = Keys {
+        keys: vec![Key { key }],
+        accept: ContentType::Json.into(),
+    };
+    let values = gateway
+        .service
+        .get(gateway.request(&headers, &peer_certs, keys)?)
+        .await?
+        .into_inner();
+
+    values
+        .values
+        .into_iter()
+        .next()
+        .map(|value| Json(value.into()))
+        .ok_or_else(|| not_found("Key"))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/keys/{key}",
+    tag = "strings",
+    params(("key" = String, Path, description = "Key, without any tenant prefix")),
+    request_body = SetBody,
+    responses((status = 204), (status = "default", body = ErrorBody))
+)]
+async fn put_key(
+    State(gateway): State<Gateway>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+    body: Result<Json<SetBody>, JsonRejection>,
+) -> ApiResult<StatusCode> {
+    let Json(body) = body?;
+    let content_type = match body.content_type.as_deref() {
+        Some(name) => ContentType::from_name(name)
+            .ok_or_else(|| Status::invalid_argument(format!("Unknown content type {name}")))?,
+        None => ContentType::Unspecified,
+    };
+    let data = match body.data {
+        Some(data) => BASE64
+            .decode(data)
+            .map_err(|_| Status::invalid_argument("data is not valid base64"))?,
+        None => Vec::new(),
+    };
+    let key_values = KeyValues {
+        key_values: vec![KeyValue {
+            key,
+            value: body.value,
+            data,
+            content_type: content_type.into(),
+        }],
+        soft_ttl_ms: body.soft_ttl_ms,
+        hard_ttl_ms: body.hard_ttl_ms,
+        tags: body.tags,
+    };
+    gateway
+        .service
+        .set(gateway.request(&headers, &peer_certs, key_values)?)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/hashes/{key}",
+    tag = "hashes",
+    params(("key" = String, Path, description = "Key, without any tenant prefix")),
+    responses((status = 200, body = HashBody), (status = "default", body = ErrorBody))
+)]
+async fn get_hash(
+    State(gateway): State<Gateway>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+) -> ApiResult<Json<HashBody>> {
+    let values = gateway
+        .service
+        .hgetall(gateway.request(&headers, &peer_certs, Key { key })?)
+        .await?
+        .into_inner()
+        .values;
+    let first = values.first().cloned().ok_or_else(|| not_found("Hash"))?;
+
+    // HGETALL answers with each field followed by its value.
+    let (mut fields, mut data) = (HashMap::new(), HashMap::new());
+    for pair in values.chunks_exact(2) {
+        let field = String::from_utf8_lossy(&pair[0].clone().into_bytes()).into_owned();
+        match pair[1].data.is_empty() {
+            true => fields.insert(field, pair[1].value.clone()),
+            false => data.insert(field, BASE64.encode(&pair[1].data)),
+        };
+    }
+
+    Ok(Json(HashBody {
+        fields,
+        data,
+        age_ms: first.age_ms,
+        stale: first.stale,
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/v1/hashes/{key}",
+    tag = "hashes",
+    params(("key" = String, Path, description = "Key, without any tenant prefix")),
+    request_body = SetHashBody,
+    responses((status = 204), (status = "default", body = ErrorBody))
+)]
+async fn put_hash(
+    State(gateway): State<Gateway>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+    body: Result<Json<SetHashBody>, JsonRejection>,
+) -> ApiResult<StatusCode> {
+    let Json(body) = body?;
+    let mut key_values: Vec<KeyValue> = body
+        .fields
+        .into_iter()
+        .map(|(key, value)| KeyValue {
+            key,
+            value,
+            ..Default::default()
+        })
+        .collect();
+    for (key, data) in body.data {
+        let data = BASE64
+            .decode(data)
+            .map_err(|_| Status::invalid_argument("data is not valid base64"))?;
+        key_values.push(KeyValue {
+            key,
+            data,
+            ..Default::default()
+        });
+    }
+    let hashed = HashedKeyValues {
+        key: Some(Key { key }),
+        key_values: Some(KeyValues {
+            key_values,
+            soft_ttl_ms: body.soft_ttl_ms,
+            hard_ttl_ms: body.hard_ttl_ms,
+            tags: body.tags,
+        }),
+    };
+    gateway
+        .service
+        .hset(gateway.request(&headers, &peer_certs, hashed)?)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/hashes/{key}/fields",
+    tag = "hashes",
+    params(("key" = String, Path, description = "Key, without any tenant prefix")),
+    responses((status = 200, body = FieldsBody), (status = "default", body = ErrorBody))
+)]
+async fn get_hash_fields(
+    State(gateway): State<Gateway>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+) -> ApiResult<Json<FieldsBody>> {
+    let keys = gateway
+        .service
+        .hkeys(gateway.request(&headers, &peer_certs, Key { key })?)
+        .await?
+        .into_inner();
+
+    Ok(Json(FieldsBody {
+        fields: keys.keys.into_iter().map(|key| key.key).collect(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/hashes/{key}/fields/{field}",
+    tag = "hashes",
+    params(("key" = String, Path, description = "Key, without any tenant prefix"), ("field" = String, Path, description = "Hash field")),
+    responses((status = 200, body = ValueBody), (status = "default", body = ErrorBody))
+)]
+async fn get_hash_field(
+    State(gateway): State<Gateway>,
+    Path((key, field)): Path<(String, String)>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+) -> ApiResult<Json<ValueBody>> {
+    let hashed = HashedKeys {
+        key: Some(Key { key }),
+        keys: Some(Keys {
+            keys: vec![Key { key: field }],
+            ..Default::default()
+        }),
+    };
+    let values = gateway
+        .service
+        .hget(gateway.request(&headers, &peer_certs, hashed)?)
+        .await?
+        .into_inner();
+
+    values
+        .values
+        .into_iter()
+        .next()
+        .map(|value| Json(value.into()))
+        .ok_or_else(|| not_found("Field"))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/hashes/{key}/values",
+    tag = "hashes",
+    params(("key" = String, Path, description = "Key, without any tenant prefix")),
+    responses((status = 200, body = ValuesBody), (status = "default", body = ErrorBody))
+)]
+async fn get_hash_values(
+    State(gateway): State<Gateway>,
+    Path(key): Path<String>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+) -> ApiResult<Json<ValuesBody>> {
+    let values = gateway
+        .service
+        .hvals(gateway.request(&headers, &peer_certs, Key { key })?)
+        .await?
+        .into_inner();
+
+    Ok(Json(ValuesBody {
+        values: values.values.into_iter().map(ValueBody::from).collect(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/v1/tags/invalidate",
+    tag = "tags",
+    request_body = TagsBody,
+    responses((status = 200, body = CountBody), (status = "default", body = ErrorBody))
+)]
+async fn invalidate_tags(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    ConnectInfo(peer_certs): ConnectInfo<PeerCerts>,
+    body: Result<Json<TagsBody>, JsonRejection>,
+) -> ApiResult<Json<CountBody>> {
+    let Json(body) = body?;
+    let count = gateway
+        .service
+        .invalidate_tags(gateway.request(&headers, &peer_certs, Tags { tags: body.tags })?)
+        .await?
+        .into_inner();
+
+    Ok(Json(CountBody { count: count.count }))
+}
+
+struct Security;
+
+impl Modify for Security {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "mrCache", description = "REST gateway to the mrCache gRPC API."),
+    paths(
+        scan,
+        get_key,
+        put_key,
+        get_hash,
+        put_hash,
+        get_hash_fields,
+        get_hash_field,
+        get_hash_values,
+        invalidate_tags
+    ),
+    components(schemas(
+        ValueBody,
+        SetBody,
+        SetHashBody,
+        HashBody,
+        FieldsBody,
+        ValuesBody,
+        ScanBody,
+        TagsBody,
+        CountBody,
+        ErrorBody
+    )),
+    modifiers(&Security),
+    security(("api_key" = []), ("bearer" = []))
+)]
+struct ApiDoc;
+
+/// The `/v1` REST API and its OpenAPI document at `/v1/openapi.json`.
+pub fn router(service: Arc<MrCacheService>, auth: Auth) -> Router {
+    Router::new()
+        .route(
+            "/v1/openapi.json",
+            get(|| async { Json(ApiDoc::openapi()) }),
+        )
+        .route("/v1/keys", get(scan))
+        .route("/v1/keys/:key", get(get_key).put(put_key))
+        .route("/v1/hashes/:key", get(get_hash).put(put_hash))
+        .route("/v1/hashes/:key/fields", get(get_hash_fields))
+        .route("/v1/hashes/:key/fields/:field", get(get_hash_field))
+        .route("/v1/hashes/:key/values", get(get_hash_values))
+        .route("/v1/tags/invalidate", post(invalidate_tags))
+        .route_layer(RpcMetricsLayer)
+        .with_state(Gateway { service, auth })
+}
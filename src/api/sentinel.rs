@@ -0,0 +1,202 @@
+use redis::{
+    from_redis_value, Client, Connection, ConnectionAddr, ConnectionInfo, ErrorKind, RedisError,
+    RedisResult, Value,
+};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+/// Where sentinels announce that a master moved, once a failover is over.
+const SWITCH_MASTER_CHANNEL: &str = "+switch-master";
+
+/// How long to wait on a sentinel, and how often to ask it where the master is in case an
+/// announcement was missed.
+const HEARTBEAT: Duration = Duration::from_secs(1);
+
+/// How long to wait before watching the next sentinel after losing one.
+const RETRY: Duration = Duration::from_secs(1);
+
+/// The sentinels watching a master, and where they last said it is. Connections are opened to
+/// that master and dropped once it moves.
+pub struct Sentinel {
+    sentinels: Vec<ConnectionInfo>,
+    service: String,
+    master: RwLock<Option<(String, u16)>>,
+}
+
+fn connect(info: &ConnectionInfo) -> RedisResult<Connection> {
+    let con = Client::open(info.clone())?.get_connection_with_timeout(HEARTBEAT)?;
+    con.set_read_timeout(Some(HEARTBEAT))?;
+    Ok(con)
+}
+
+fn host_port(addr: &ConnectionAddr) -> Option<(&str, u16)> {
+    match addr {
+        ConnectionAddr::Tcp(host, port) | ConnectionAddr::TcpTls { host, port, .. } => {
+            Some((host, *port))
+        }
+        _ => None,
+    }
+}
+
+impl Sentinel {
+    pub fn new(sentinels: Vec<ConnectionInfo>, service: String) -> Self {
+        Self {
+            sentinels,
+            service,
+            master: RwLock::new(None),
+        }
+    }
+
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    fn ask(&self, con: &mut Connection) -> RedisResult<(String, u16)> {
+        let master: Option<(String, u16)> = redis::cmd("SENTINEL")
+            .arg("GET-MASTER-ADDR-BY-NAME")
+            .arg(&self.service)
+            .query(con)?;
+        master.ok_or_else(|| {
+            RedisError::from((
+                ErrorKind::ResponseError,
+                "Sentinels know no such master",
+                self.service.clone(),
+            ))
+        })
+    }
+
+    fn update(&self, master: (String, u16)) {
+        let mut current = self.master.write().unwrap();
+        if current.as_ref() != Some(&master) {
+            info!(
+                host = master.0,
+                port = master.1,
+                "Following the Redis master"
+            );
+            *current = Some(master);
+        }
+    }
+
+    /// Forgets where the master is, so the next connection asks the sentinels again.
+    fn forget(&self) {
+        *self.master.write().unwrap() = None;
+    }
+
+    /// Where the master is: where it was last seen, or where the first sentinel that answers
+    /// says it is.
+    fn master(&self) -> RedisResult<(String, u16)> {
+        if let Some(master) = self.master.read().unwrap().clone() {
+            return Ok(master);
+        }
+
+        let mut last_error = None;
+        for info in &self.sentinels {
+            match connect(info).and_then(|mut con| self.ask(&mut con)) {
+                Ok(master) => {
+                    self.update(master.clone());
+                    return Ok(master);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.expect("At least one sentinel is listed."))
+    }
+
+    /// Points `info` at the master, keeping its TLS settings and credentials.
+    pub fn locate(&self, mut info: ConnectionInfo) -> RedisResult<ConnectionInfo> {
+        let (master_host, master_port) = self.master()?;
+        info.addr = match info.addr {
+            ConnectionAddr::TcpTls {
+                insecure,
+                tls_params,
+                ..
+            } => ConnectionAddr::TcpTls {
+                host: master_host,
+                port: master_port,
+                insecure,
+                tls_params,
+            },
+            _ => ConnectionAddr::Tcp(master_host, master_port),
+        };
+        Ok(info)
+    }
+
+    /// Makes sure a node just connected to is still the master, as a sentinel may not have seen
+    /// the last failover yet.
+    pub fn verify(&self, con: &mut Connection) -> RedisResult<()> {
+        let role: Vec<Value> = redis::cmd("ROLE").query(con)?;
+        match role.first().map(from_redis_value::<String>) {
+            Some(Ok(role)) if role == "master" => Ok(()),
+            _ => {
+                self.forget();
+                Err(RedisError::from((
+                    ErrorKind::ReadOnly,
+                    "Redis node is no longer the master",
+                )))
+            }
+        }
+    }
+
+    /// Whether `info` is where the master was last seen. Connections elsewhere are dropped.
+    pub fn is_master(&self, info: &ConnectionInfo) -> bool {
+        let master = self.master.read().unwrap();
+        match (master.as_ref(), host_port(&info.addr)) {
+            (Some((master_host, master_port)), Some((host, port))) => {
+                master_host == host && *master_port == port
+            }
+            _ => false,
+        }
+    }
+
+    /// Follows one sentinel's announcements of the master moving, asking it where the master is
+    /// whenever none comes in for a while, until the connection to it fails.
+    fn watch(&self, info: &ConnectionInfo) -> RedisResult<()> {
+        let mut asker = connect(info)?;
+        let mut subscriber = connect(info)?;
+        let mut pubsub = subscriber.as_pubsub();
+        pubsub.subscribe(SWITCH_MASTER_CHANNEL)?;
+        // A failover may have been announced before subscribing.
+        self.update(self.ask(&mut asker)?);
+
+        loop {
+            match pubsub.get_message() {
+                // Announced as `<service> <old host> <old port> <new host> <new port>`.
+                Ok(message) => {
+                    let payload: String = message.get_payload()?;
+                    let parts: Vec<&str> = payload.split(' ').collect();
+                    if let [service, _, _, host, port] = parts[..] {
+                        if let (true, Ok(port)) = (service == self.service, port.parse()) {
+                            self.update((host.to_string(), port));
+                        }
+                    }
+                }
+                Err(e) if e.is_timeout() => self.update(self.ask(&mut asker)?),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Watches the sentinels in the background, one at a time, moving on to the next whenever
+    /// one cannot be reached.
+    pub fn spawn_watch(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut next = 0;
+            loop {
+                let sentinel = self.clone();
+                let watched =
+                    tokio::task::spawn_blocking(move || sentinel.watch(&sentinel.sentinels[next]))
+                        .await;
+
+                match watched {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => warn!(error = %e, "Lost Redis Sentinel"),
+                    Err(e) => error!(error = %e, "Redis Sentinel watch panicked"),
+                }
+
+                next = (next + 1) % self.sentinels.len();
+                tokio::time::sleep(RETRY).await;
+            }
+        });
+    }
+}
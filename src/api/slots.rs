@@ -0,0 +1,142 @@
+use redis::cluster_routing::get_slot;
+use redis::RedisResult;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CLUSTER: AtomicBool = AtomicBool::new(false);
+
+/// Switches key names and multi-key commands over to Redis Cluster's rules, once at startup.
+pub fn enable_cluster() {
+    CLUSTER.store(true, Ordering::Relaxed);
+}
+
+pub fn is_cluster() -> bool {
+    CLUSTER.load(Ordering::Relaxed)
+}
+
+/// The part of `key` Redis Cluster hashes to pick its slot: what is between the first `{` and the
+/// `}` after it when that is not empty, the whole key otherwise.
+fn hash_tag(key: &str) -> &str {
+    key.find('{')
+        .and_then(|open| {
+            let rest = &key[open + 1..];
+            rest.find('}')
+                .filter(|&close| close > 0)
+                .map(|close| &rest[..close])
+        })
+        .unwrap_or(key)
+}
+
+/// Names a key mrCache keeps next to `key`, like its metadata. On a cluster the name carries
+/// `key`'s hash tag, so that scripts and transactions touching both stay within one slot.
+pub fn companion(prefix: &str, key: &str) -> String {
+    match is_cluster() {
+        true => tagged_companion(prefix, key),
+        false => prefix.to_string() + key,
+    }
+}
+
+fn tagged_companion(prefix: &str, key: &str) -> String {
+    format!("{prefix}{{{}}}{key}", hash_tag(key))
+}
+
+/// Whether `companion` can put keys next to `key`: always off a cluster, and on one unless
+/// `key` is empty or has a `}` outside of a hash tag.
+pub fn can_colocate(key: &str) -> bool {
+    !is_cluster() || has_usable_tag(key)
+}
+
+fn has_usable_tag(key: &str) -> bool {
+    let tag = hash_tag(key);
+    !tag.is_empty() && !tag.contains('}')
+}
+
+/// Groups the positions of `keys` by slot, in the order each slot first comes up. Off a cluster
+/// every key is in the one group.
+pub fn groups<K: AsRef<str>>(keys: &[K]) -> Vec<Vec<usize>> {
+    match is_cluster() {
+        true => slot_groups(keys),
+        false => vec![(0..keys.len()).collect()],
+    }
+}
+
+fn slot_groups<K: AsRef<str>>(keys: &[K]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<(u16, Vec<usize>)> = Vec::new();
+    for (i, key) in keys.iter().enumerate() {
+        let slot = get_slot(key.as_ref().as_bytes());
+        match groups.iter_mut().find(|(s, _)| *s == slot) {
+            Some((_, group)) => group.push(i),
+            None => groups.push((slot, vec![i])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// Runs a multi-key command once per slot and puts the results back in the order of `keys`.
+/// Missing results, like those of an `MGET` of one missing key, are filled in with defaults.
+pub fn split<T, F>(keys: &[&str], mut command: F) -> RedisResult<Vec<T>>
+where
+    T: Default,
+    F: FnMut(&[&str]) -> RedisResult<Vec<T>>,
+{
+    let mut results: Vec<T> = Vec::new();
+    results.resize_with(keys.len(), T::default);
+
+    for group in groups(keys) {
+        let group_keys: Vec<&str> = group.iter().map(|&i| keys[i]).collect();
+        for (i, result) in group.into_iter().zip(command(&group_keys)?) {
+            results[i] = result;
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The cluster switch is shared by every test in the binary and none of them turn it on, so
+    // what it switches to is tested through the functions behind it.
+
+    #[test]
+    fn hash_tags_are_the_first_non_empty_braces() {
+        assert_eq!(hash_tag("user:{42}:name"), "42");
+        assert_eq!(hash_tag("{a}{b}"), "a");
+        assert_eq!(hash_tag("a{b}}"), "b");
+        assert_eq!(hash_tag("plain"), "plain");
+        assert_eq!(hash_tag("{}"), "{}");
+        assert_eq!(hash_tag("{}{a}"), "{}{a}");
+        assert_eq!(hash_tag("open{only"), "open{only");
+    }
+
+    #[test]
+    fn companions_share_their_key_slot() {
+        for key in ["plain", "user:{42}:name", "open{only", "{a}{b}", "a{{b}"] {
+            assert!(has_usable_tag(key), "{key}");
+            let companion = tagged_companion("mrcache:meta:", key);
+            assert_eq!(
+                get_slot(companion.as_bytes()),
+                get_slot(key.as_bytes()),
+                "{key}"
+            );
+        }
+    }
+
+    #[test]
+    fn keys_without_a_usable_tag_cannot_be_colocated() {
+        for key in ["", "{}", "a}b", "{}{a}"] {
+            assert!(!has_usable_tag(key), "{key}");
+            assert!(can_colocate(key), "{key}");
+        }
+        assert_eq!(companion("mrcache:meta:", "{}"), "mrcache:meta:{}");
+    }
+
+    #[test]
+    fn groups_keys_by_slot_in_order() {
+        let keys = ["{a}1", "{b}1", "{a}2", "{c}", "{b}2"];
+        assert_eq!(slot_groups(&keys), vec![vec![0, 2], vec![1, 4], vec![3]]);
+        assert_eq!(groups(&keys), vec![vec![0, 1, 2, 3, 4]]);
+
+        let results = split(&keys, |keys| Ok(keys.iter().map(|key| key.len()).collect())).unwrap();
+        assert_eq!(results, vec![4, 4, 4, 3, 4]);
+    }
+}
@@ -0,0 +1,282 @@
+use redis::{Commands, ConnectionLike, ErrorKind, Pipeline, RedisError, RedisResult, Script};
+use std::collections::BTreeSet;
+
+use crate::api::meta::Meta;
+use crate::api::slots;
+
+const TAG_PREFIX: &str = "mrcache:tag:";
+const KEY_TAGS_PREFIX: &str = "mrcache:tags:";
+
+/// Adds a member to one tag index, which lives as long as its longest-lived member.
+///
+/// KEYS[1] is the tag index, ARGV[1] the member key and ARGV[2] its hard TTL in ms (0 for none).
+const INDEX_SCRIPT: &str = r"
+local index = KEYS[1]
+local ttl = tonumber(ARGV[2])
+local existed = redis.call('EXISTS', index)
+redis.call('SADD', index, ARGV[1])
+if ttl == 0 then
+  redis.call('PERSIST', index)
+elseif existed == 0 then
+  redis.call('PEXPIRE', index, ttl)
+else
+  local remaining = redis.call('PTTL', index)
+  if remaining >= 0 and remaining < ttl then
+    redis.call('PEXPIRE', index, ttl)
+  end
+end
+return 0
+";
+
+/// How many times `invalidate` reads the tags again when they change before its script runs.
+const INVALIDATE_ATTEMPTS: usize = 10;
+
+/// Deletes every key under the given tag indexes along with its metadata, drops it from any other
+/// tags it had and removes the indexes themselves. Returns how many keys were deleted, or -1
+/// without touching anything when the keys it was given are no longer every key it would touch,
+/// because a key was tagged in the meantime.
+///
+/// KEYS are the ARGV[1] tag indexes, then the member key, its metadata and its tag list for each
+/// of the ARGV[2] members, then the indexes of their other tags. ARGV[3] is the tag index prefix.
+const INVALIDATE_SCRIPT: &str = r"
+local indexes = tonumber(ARGV[1])
+local members = tonumber(ARGV[2])
+local declared = {}
+for _, key in ipairs(KEYS) do
+  declared[key] = true
+end
+for i = 1, indexes do
+  for _, member in ipairs(redis.call('SMEMBERS', KEYS[i])) do
+    if not declared[member] then
+      return -1
+    end
+  end
+end
+for j = 0, members - 1 do
+  for _, tag in ipairs(redis.call('SMEMBERS', KEYS[indexes + 3 * j + 3])) do
+    if not declared[ARGV[3] .. tag] then
+      return -1
+    end
+  end
+end
+
+local deleted = 0
+for j = 0, members - 1 do
+  local member = KEYS[indexes + 3 * j + 1]
+  local tagged = false
+  for i = 1, indexes do
+    if redis.call('SISMEMBER', KEYS[i], member) == 1 then
+      tagged = true
+    end
+  end
+  if tagged then
+    local key_tags = KEYS[indexes + 3 * j + 3]
+    deleted = deleted + redis.call('DEL', member)
+    redis.call('DEL', KEYS[indexes + 3 * j + 2])
+    for _, tag in ipairs(redis.call('SMEMBERS', key_tags)) do
+      redis.call('SREM', ARGV[3] .. tag, member)
+    end
+    redis.call('DEL', key_tags)
+  end
+end
+for i = 1, indexes do
+  redis.call('DEL', KEYS[i])
+end
+return deleted
+";
+
+fn index_key(tag: &str) -> String {
+    TAG_PREFIX.to_string() + tag
+}
+
+fn key_tags_key(key: &str) -> String {
+    slots::companion(KEY_TAGS_PREFIX, key)
+}
+
+/// Replaces the tags of `key` once it has been written; no tags drop it from those it had. The key
+/// keeps the list of its own tags so it can be dropped from them later. Off a cluster this is one
+/// transaction, retried when the key's tags change in the meantime. On a cluster tag indexes are
+/// in other slots than their members, so it goes one command at a time.
+pub fn write<C: ConnectionLike>(
+    con: &mut C,
+    key: &str,
+    tags: &[String],
+    hard_ttl_ms: Option<u64>,
+) -> RedisResult<()> {
+    let key_tags = key_tags_key(key);
+    if tags.is_empty() && !con.exists::<_, bool>(&key_tags)? {
+        return Ok(());
+    }
+    update(con, key, &key_tags, Some(tags), hard_ttl_ms)
+}
+
+/// Keeps the tags of `key` for as long as its new hard TTL, once it has been written again without
+/// them changing, as the loader does.
+pub fn renew<C: ConnectionLike>(
+    con: &mut C,
+    key: &str,
+    hard_ttl_ms: Option<u64>,
+) -> RedisResult<()> {
+    let key_tags = key_tags_key(key);
+    if !con.exists::<_, bool>(&key_tags)? {
+        return Ok(());
+    }
+    update(con, key, &key_tags, None, hard_ttl_ms)
+}
+
+/// Replaces the tags of `key` with `tags`, or with those it has when `None`.
+fn update<C: ConnectionLike>(
+    con: &mut C,
+    key: &str,
+    key_tags: &str,
+    tags: Option<&[String]>,
+    hard_ttl_ms: Option<u64>,
+) -> RedisResult<()> {
+    if slots::is_cluster() {
+        let old: Vec<String> = con.smembers(key_tags)?;
+        let mut pipe = redis::pipe();
+        retag(
+            &mut pipe,
+            key,
+            key_tags,
+            &old,
+            tags.unwrap_or(&old),
+            hard_ttl_ms,
+        );
+        for command in pipe.cmd_iter() {
+            command.query::<()>(con)?;
+        }
+        return Ok(());
+    }
+
+    redis::transaction(con, &[key_tags], |con, pipe| {
+        let old: Vec<String> = con.smembers(key_tags)?;
+        retag(pipe, key, key_tags, &old, tags.unwrap_or(&old), hard_ttl_ms);
+        pipe.query(con)
+    })
+}
+
+/// Queues dropping `key` from its `old` tags and adding it to `tags`.
+fn retag(
+    pipe: &mut Pipeline,
+    key: &str,
+    key_tags: &str,
+    old: &[String],
+    tags: &[String],
+    hard_ttl_ms: Option<u64>,
+) {
+    for tag in old {
+        pipe.srem(index_key(tag), key).ignore();
+    }
+    pipe.del(key_tags).ignore();
+
+    let ttl = hard_ttl_ms.unwrap_or(0);
+    for tag in tags {
+        pipe.cmd("EVAL")
+            .arg(INDEX_SCRIPT)
+            .arg(1)
+            .arg(index_key(tag))
+            .arg(key)
+            .arg(ttl)
+            .ignore();
+    }
+    if !tags.is_empty() {
+        pipe.sadd(key_tags, tags).ignore();
+        if ttl > 0 {
+            pipe.pexpire(key_tags, ttl as i64).ignore();
+        }
+    }
+}
+
+/// Deletes every key under the given tags along with its metadata, drops it from any other tags it
+/// had and removes the tag indexes themselves. Returns how many keys were deleted. Off a cluster
+/// this is one script, see `INVALIDATE_SCRIPT`. On a cluster tag indexes are in other slots than
+/// their members, so only the members sharing a slot are deleted together, see
+/// `invalidate_across_slots`.
+pub fn invalidate<C: ConnectionLike>(con: &mut C, tags: &[&str]) -> RedisResult<u64> {
+    if slots::is_cluster() {
+        return invalidate_across_slots(con, tags);
+    }
+
+    let indexes: BTreeSet<String> = tags.iter().map(|tag| index_key(tag)).collect();
+    let script = Script::new(INVALIDATE_SCRIPT);
+    for _ in 0..INVALIDATE_ATTEMPTS {
+        let members = members(con, &indexes)?;
+        let mut others = BTreeSet::new();
+        for member in &members {
+            let member_tags: Vec<String> = con.smembers(key_tags_key(member))?;
+            others.extend(member_tags.iter().map(|tag| index_key(tag)));
+        }
+
+        let mut invocation = script.prepare_invoke();
+        invocation.key(indexes.iter().collect::<Vec<_>>());
+        for member in &members {
+            invocation
+                .key(member)
+                .key(Meta::key(member))
+                .key(key_tags_key(member));
+        }
+        invocation.key(others.difference(&indexes).collect::<Vec<_>>());
+        let deleted: i64 = invocation
+            .arg(indexes.len())
+            .arg(members.len())
+            .arg(TAG_PREFIX)
+            .invoke(con)?;
+        if deleted >= 0 {
+            return Ok(deleted as u64);
+        }
+    }
+
+    Err(RedisError::from((
+        ErrorKind::TryAgain,
+        "Tags kept changing while being invalidated",
+    )))
+}
+
+/// Every key tagged with any of the tag `indexes`.
+fn members<C: ConnectionLike>(
+    con: &mut C,
+    indexes: &BTreeSet<String>,
+) -> RedisResult<BTreeSet<String>> {
+    let mut members = BTreeSet::new();
+    for index in indexes {
+        let tagged: Vec<String> = con.smembers(index)?;
+        members.extend(tagged);
+    }
+    Ok(members)
+}
+
+/// `invalidate` for a cluster. The members in each slot are deleted together with their metadata
+/// and tag lists, which share their slot, then dropped from their other tags, and the indexes go
+/// last. Unlike off a cluster this is not atomic as a whole: a key tagged while it runs can keep
+/// its value, and a failure partway leaves only some slots invalidated.
+fn invalidate_across_slots<C: ConnectionLike>(con: &mut C, tags: &[&str]) -> RedisResult<u64> {
+    let indexes: BTreeSet<String> = tags.iter().map(|tag| index_key(tag)).collect();
+    let members: Vec<String> = members(con, &indexes)?.into_iter().collect();
+
+    let mut deleted = 0;
+    for group in slots::groups(&members) {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for &i in &group {
+            let key_tags = key_tags_key(&members[i]);
+            pipe.del(&members[i])
+                .del(Meta::key(&members[i]))
+                .ignore()
+                .smembers(&key_tags)
+                .del(&key_tags)
+                .ignore();
+        }
+        let replies: Vec<(u64, Vec<String>)> = pipe.query(con)?;
+        for (&i, (count, member_tags)) in group.iter().zip(replies) {
+            deleted += count;
+            for member_tag in member_tags {
+                con.srem::<_, _, ()>(index_key(&member_tag), &members[i])?;
+            }
+        }
+    }
+    for index in &indexes {
+        con.del::<_, ()>(index)?;
+    }
+    Ok(deleted)
+}
@@ -0,0 +1,81 @@
+use opentelemetry::propagation::Extractor;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tonic::codegen::http;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter};
+
+use crate::api::tenant::TENANT_HEADER;
+use crate::config::{LogFormat, LoggingConfig, TracingConfig};
+
+/// Sets up logging at the configured level and format (overridable with `RUST_LOG`), and exports
+/// spans over OTLP when an endpoint is configured.
+pub fn init(logging: &LoggingConfig, tracing: &TracingConfig) {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&logging.level));
+
+    let otlp = tracing.otlp_endpoint.as_ref().map(|endpoint| {
+        let tracer =
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", tracing.service_name.clone()),
+                ])))
+                .install_batch(runtime::Tokio)
+                .expect("Failed to start OTLP exporter.");
+        tracing_opentelemetry::layer().with_tracer(tracer)
+    });
+
+    let registry = tracing_subscriber::registry().with(filter).with(otlp);
+    match logging.format {
+        LogFormat::Json => registry.with(fmt::layer().json()).init(),
+        LogFormat::Pretty => registry.with(fmt::layer().pretty()).init(),
+    }
+}
+
+/// Flushes any spans still waiting to be exported.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+struct HeaderExtractor<'a>(&'a http::HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Opens the span for one gRPC call, continuing the caller's trace when the metadata carries a
+/// W3C `traceparent`.
+pub fn request_span(request: &http::Request<()>) -> Span {
+    let tenant = request
+        .headers()
+        .get(TENANT_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::info_span!("rpc", rpc = request.uri().path(), tenant);
+
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    span.set_parent(parent);
+
+    span
+}
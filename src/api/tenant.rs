@@ -0,0 +1,460 @@
+use redis::{Connection, RedisResult};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tonic::{Request, Status};
+use tracing::{error, warn};
+
+use crate::api::auth::TenantGrant;
+use crate::api::pool::{Pool, RedisPool};
+use crate::api::replicas::{Consistency, Replica, Replicas};
+use crate::config::{Config, TenantConfig};
+
+/// Request metadata naming the tenant a call belongs to. Calls without it use the shared,
+/// unprefixed keyspace.
+pub const TENANT_HEADER: &str = "x-mrcache-tenant";
+
+/// What credentials call the shared keyspace when granting tenants, so no tenant can have it.
+pub const DEFAULT_TENANT: &str = "default";
+
+/// Prefix of mrCache's own bookkeeping keys, hidden from scans of the shared keyspace.
+pub const INTERNAL_PREFIX: &str = "mrcache:";
+
+/// Prefix of the hashes holding each tenant's usage, shared by every mrCache instance.
+const USAGE_PREFIX: &str = "mrcache:usage:";
+
+/// A tenant's slice of Redis: every key it touches is prefixed with `{name}:`, optionally on its
+/// own logical DB or server, and writes are refused once it goes over its quotas.
+pub struct Tenant {
+    pub prefix: String,
+    pub pool: Arc<RedisPool>,
+    /// Where `pool` is in `Tenants::pools`, telling apart keys of the same name on other pools.
+    pub pool_id: usize,
+    /// Whether the tenant's Redis has the RedisJSON module, found out on its first document call
+    /// and shared with the other tenants on the same pool.
+    pub redis_json: Arc<OnceLock<bool>>,
+    /// Replicas reads can go to, for tenants on the shared DB.
+    pub replicas: Option<Arc<Replicas>>,
+    /// Prefixes the tenant's keys may not start with, as they belong to mrCache or to other
+    /// tenants in the same DB. Only the shared keyspace, being unprefixed, has any.
+    reserved: Vec<String>,
+    max_keys: Option<u64>,
+    max_memory_bytes: Option<u64>,
+    keys: AtomicU64,
+    memory_bytes: AtomicU64,
+}
+
+impl Tenant {
+    fn new(prefix: String, pool: Arc<RedisPool>) -> Self {
+        Self {
+            prefix,
+            pool,
+            pool_id: 0,
+            redis_json: Arc::new(OnceLock::new()),
+            replicas: None,
+            reserved: Vec::new(),
+            max_keys: None,
+            max_memory_bytes: None,
+            keys: AtomicU64::new(0),
+            memory_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.prefix.strip_suffix(':')
+    }
+
+    pub fn key(&self, key: &str) -> String {
+        self.prefix.clone() + key
+    }
+
+    /// Refuses a stored key the tenant may not use, like the shared keyspace reaching for
+    /// mrCache's bookkeeping or for a configured tenant's keys.
+    pub fn check_key(&self, key: &str) -> Result<(), Status> {
+        match self
+            .reserved
+            .iter()
+            .find(|prefix| key.starts_with(prefix.as_str()))
+        {
+            Some(prefix) => Err(Status::invalid_argument(format!(
+                "Keys of the shared keyspace cannot start with {prefix}"
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Maps a stored key back to what the tenant called it, skipping keys it cannot see.
+    pub fn strip<'a>(&self, key: &'a str) -> Option<&'a str> {
+        if self.check_key(key).is_err() {
+            return None;
+        }
+
+        key.strip_prefix(self.prefix.as_str())
+    }
+
+    /// The replica a read goes to, or `None` for the primary.
+    pub fn replica(&self, consistency: Consistency) -> Option<&Replica> {
+        self.replicas.as_ref()?.pick(consistency)
+    }
+
+    fn has_quota(&self) -> bool {
+        self.max_keys.is_some() || self.max_memory_bytes.is_some()
+    }
+
+    /// Refuses a write that would take the tenant over its quotas, given an estimate of the keys
+    /// and bytes it adds. Usage is as of this instance's last write, so with several instances a
+    /// tenant can go over a quota by about one write per instance.
+    pub fn check_quota(&self, keys: u64, bytes: u64) -> Result<(), Status> {
+        if let Some(max) = self.max_keys {
+            if self.keys.load(Ordering::Relaxed).saturating_add(keys) > max {
+                return Err(Status::resource_exhausted(format!(
+                    "Tenant key quota of {} reached",
+                    max
+                )));
+            }
+        }
+
+        if let Some(max) = self.max_memory_bytes {
+            if self
+                .memory_bytes
+                .load(Ordering::Relaxed)
+                .saturating_add(bytes)
+                > max
+            {
+                return Err(Status::resource_exhausted(format!(
+                    "Tenant memory quota of {} bytes reached",
+                    max
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn usage_key(&self) -> String {
+        format!("{}{}", USAGE_PREFIX, self.prefix)
+    }
+
+    /// Adds a successful write to the tenant's usage counters in Redis and takes on the totals,
+    /// which include the other instances' writes. Overwrites count as new keys until the next
+    /// re-count.
+    pub fn record_usage(&self, keys: u64, bytes: u64) {
+        if !self.has_quota() {
+            return;
+        }
+
+        let recorded = self
+            .pool
+            .get()
+            .map_err(|e| {
+                redis::RedisError::from((redis::ErrorKind::IoError, "pool", e.to_string()))
+            })
+            .and_then(|mut con| {
+                redis::pipe()
+                    .hincr(self.usage_key(), "keys", keys)
+                    .hincr(self.usage_key(), "bytes", bytes)
+                    .query::<(u64, u64)>(&mut *con)
+            });
+        match recorded {
+            Ok((keys, memory_bytes)) => {
+                self.keys.store(keys, Ordering::Relaxed);
+                self.memory_bytes.store(memory_bytes, Ordering::Relaxed);
+            }
+            Err(e) => warn!(tenant = %self.prefix, error = %e, "Failed to record tenant usage"),
+        }
+    }
+
+    /// Re-counts the tenant's usage and resets the shared counters to it.
+    fn refresh_usage(&self) -> RedisResult<()> {
+        let (keys, memory_bytes) = self.measure()?;
+        let mut con = self.pool.get().map_err(|e| {
+            redis::RedisError::from((redis::ErrorKind::IoError, "pool", e.to_string()))
+        })?;
+        redis::cmd("HSET")
+            .arg(self.usage_key())
+            .arg("keys")
+            .arg(keys)
+            .arg("bytes")
+            .arg(memory_bytes)
+            .query::<()>(&mut *con)?;
+        self.keys.store(keys, Ordering::Relaxed);
+        self.memory_bytes.store(memory_bytes, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Counts the tenant's keys and their memory with `SCAN` and `MEMORY USAGE`.
+    fn measure(&self) -> RedisResult<(u64, u64)> {
+        let mut con = self.pool.get().map_err(|e| {
+            redis::RedisError::from((redis::ErrorKind::IoError, "pool", e.to_string()))
+        })?;
+        let pattern = self.prefix.clone() + "*";
+        let (mut keys, mut memory_bytes) = (0, 0);
+        let mut master = 0;
+        while let Some((k, m)) = con.on_master(master, |node| Self::measure_node(node, &pattern))? {
+            keys += k;
+            memory_bytes += m;
+            master += 1;
+        }
+        Ok((keys, memory_bytes))
+    }
+
+    /// Counts the keys matching `pattern` on one node and their memory.
+    fn measure_node(con: &mut Connection, pattern: &str) -> RedisResult<(u64, u64)> {
+        let mut cursor: u64 = 0;
+        let (mut keys, mut memory_bytes) = (0, 0);
+
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg(pattern)
+                .arg("COUNT")
+                .arg(1000)
+                .query(&mut *con)?;
+
+            let mut pipe = redis::pipe();
+            for key in &batch {
+                pipe.cmd("MEMORY").arg("USAGE").arg(key);
+            }
+            let usage: Vec<Option<u64>> = pipe.query(&mut *con)?;
+
+            keys += batch.len() as u64;
+            memory_bytes += usage.into_iter().flatten().sum::<u64>();
+
+            cursor = next;
+            if cursor == 0 {
+                return Ok((keys, memory_bytes));
+            }
+        }
+    }
+}
+
+pub struct Tenants {
+    default: Arc<Tenant>,
+    configured: HashMap<String, Arc<Tenant>>,
+    pools: Vec<Arc<RedisPool>>,
+}
+
+impl Tenants {
+    pub fn new(pool: &Pool, config: &Config) -> Self {
+        assert!(
+            !config.tenants.contains_key(DEFAULT_TENANT),
+            "No tenant can be named {DEFAULT_TENANT}."
+        );
+        let replicas = Replicas::from_config(pool, &config.replicas).map(Arc::new);
+        let mut pools = vec![pool.get_pool()];
+        let configured = config
+            .tenants
+            .iter()
+            .map(|(name, tenant_config)| {
+                let mut tenant = Self::configured_tenant(name, tenant_config, pool, &replicas);
+                tenant.pool_id = match pools.iter().position(|p| Arc::ptr_eq(p, &tenant.pool)) {
+                    Some(pool_id) => pool_id,
+                    None => {
+                        pools.push(tenant.pool.clone());
+                        pools.len() - 1
+                    }
+                };
+                (name.clone(), Arc::new(tenant))
+            })
+            .collect::<HashMap<String, Arc<Tenant>>>();
+        let mut reserved = vec![INTERNAL_PREFIX.to_string()];
+        reserved.extend(
+            configured
+                .values()
+                .filter(|tenant| tenant.pool_id == 0)
+                .map(|tenant| tenant.prefix.clone()),
+        );
+
+        Self {
+            default: Arc::new(Tenant {
+                replicas,
+                reserved,
+                ..Tenant::new(String::new(), pool.get_pool())
+            }),
+            configured,
+            pools,
+        }
+    }
+
+    fn configured_tenant(
+        name: &str,
+        config: &TenantConfig,
+        pool: &Pool,
+        replicas: &Option<Arc<Replicas>>,
+    ) -> Tenant {
+        let (pool, replicas) = match (&config.url, config.db) {
+            (None, None) => (pool.get_pool(), replicas.clone()),
+            (url, db) => (pool.open(url.as_deref(), db).get_pool(), None),
+        };
+
+        Tenant {
+            replicas,
+            max_keys: config.max_keys,
+            max_memory_bytes: config.max_memory_bytes,
+            ..Tenant::new(name.to_string() + ":", pool)
+        }
+    }
+
+    /// Picks the tenant named in the request metadata, as long as the caller's credentials grant
+    /// it. Tenants missing from the config still get their own prefix on the shared DB, just
+    /// without quotas.
+    pub fn resolve<T>(&self, request: &Request<T>) -> Result<Arc<Tenant>, Status> {
+        let name = match request.metadata().get(TENANT_HEADER) {
+            Some(name) => Some(
+                name.to_str()
+                    .map_err(|_| Status::invalid_argument("Tenant must be ASCII"))?,
+            ),
+            None => None,
+        };
+        let granted = request.extensions().get::<TenantGrant>();
+        if !granted.is_some_and(|granted| granted.allows(name)) {
+            return Err(Status::permission_denied(format!(
+                "Caller may not use tenant {}",
+                name.unwrap_or(DEFAULT_TENANT)
+            )));
+        }
+        let Some(name) = name else {
+            return Ok(self.default.clone());
+        };
+
+        if let Some(tenant) = self.configured.get(name) {
+            return Ok(tenant.clone());
+        }
+
+        let valid = !name.is_empty()
+            && name != DEFAULT_TENANT
+            && name != INTERNAL_PREFIX.trim_end_matches(':')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(Status::invalid_argument(
+                "Invalid tenant ".to_string() + name,
+            ));
+        }
+
+        Ok(Arc::new(Tenant {
+            redis_json: self.default.redis_json.clone(),
+            replicas: self.default.replicas.clone(),
+            ..Tenant::new(name.to_string() + ":", self.default.pool.clone())
+        }))
+    }
+
+    /// The replicas of the shared DB, if any are configured.
+    pub fn replicas(&self) -> Option<Arc<Replicas>> {
+        self.default.replicas.clone()
+    }
+
+    /// Every distinct pool the tenants use, the shared one first.
+    pub fn pools(&self) -> Vec<Arc<RedisPool>> {
+        self.pools.clone()
+    }
+
+    /// Periodically re-measures every tenant that has a quota, correcting the counts kept on write
+    /// for overwrites, deletions and expiry.
+    pub fn spawn_quota_refresh(self: Arc<Self>, interval: Duration) {
+        if !self.configured.values().any(|tenant| tenant.has_quota()) {
+            return;
+        }
+
+        tokio::spawn(async move {
+            loop {
+                let tenants = self.clone();
+                let measured = tokio::task::spawn_blocking(move || {
+                    for (name, tenant) in tenants.configured.iter() {
+                        if !tenant.has_quota() {
+                            continue;
+                        }
+
+                        if let Err(e) = tenant.refresh_usage() {
+                            warn!(tenant = %name, error = %e, "Failed to measure tenant");
+                        }
+                    }
+                })
+                .await;
+
+                if let Err(e) = measured {
+                    error!(error = %e, "Tenant quota refresh panicked");
+                }
+
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RedisConfig;
+
+    /// Tenants on a Redis that is never connected to: `acme` in the shared DB and `solo` on a
+    /// server of its own.
+    fn tenants() -> Tenants {
+        let pool = Pool::new(&RedisConfig {
+            host: "127.0.0.1".to_string(),
+            ..RedisConfig::default()
+        });
+        let mut config = Config::default();
+        config
+            .tenants
+            .insert("acme".to_string(), TenantConfig::default());
+        config.tenants.insert(
+            "solo".to_string(),
+            TenantConfig {
+                url: Some("redis://127.0.0.1:6390".to_string()),
+                ..TenantConfig::default()
+            },
+        );
+        Tenants::new(&pool, &config)
+    }
+
+    #[test]
+    fn shared_keyspace_cannot_reach_bookkeeping_keys() {
+        let tenants = tenants();
+        for key in ["mrcache:meta:k", "mrcache:lock:k", "mrcache:tags:k"] {
+            let refused = tenants.default.check_key(key).unwrap_err();
+            assert_eq!(refused.code(), tonic::Code::InvalidArgument);
+            assert_eq!(tenants.default.strip(key), None);
+        }
+        assert!(tenants.default.check_key("mrcache").is_ok());
+    }
+
+    #[test]
+    fn shared_keyspace_cannot_reach_tenants_in_its_db() {
+        let tenants = tenants();
+        assert!(tenants.default.check_key("acme:k").is_err());
+        assert_eq!(tenants.default.strip("acme:k"), None);
+        assert!(tenants.default.check_key("acmex:k").is_ok());
+        assert!(tenants.default.check_key("solo:k").is_ok());
+        assert_eq!(tenants.default.strip("solo:k"), Some("solo:k"));
+    }
+
+    #[test]
+    fn tenants_keep_the_whole_of_their_namespace() {
+        let tenants = tenants();
+        let acme = &tenants.configured["acme"];
+        let key = acme.key("mrcache:meta:k");
+        assert!(acme.check_key(&key).is_ok());
+        assert_eq!(acme.strip(&key), Some("mrcache:meta:k"));
+    }
+
+    #[test]
+    fn quotas_refuse_writes_that_would_go_over_them() {
+        let tenants = tenants();
+        let mut acme = Tenant::new("acme:".to_string(), tenants.default.pool.clone());
+        acme.max_keys = Some(10);
+        acme.max_memory_bytes = Some(1000);
+        acme.keys.store(9, Ordering::Relaxed);
+        acme.memory_bytes.store(900, Ordering::Relaxed);
+
+        assert!(acme.check_quota(1, 100).is_ok());
+        let refused = acme.check_quota(2, 0).unwrap_err();
+        assert_eq!(refused.code(), tonic::Code::ResourceExhausted);
+        assert!(acme.check_quota(0, 101).is_err());
+        assert!(acme.check_quota(u64::MAX, 0).is_err());
+        assert!(tenants.default.check_quota(u64::MAX, u64::MAX).is_ok());
+    }
+}
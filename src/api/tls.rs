@@ -0,0 +1,186 @@
+use rustls_pemfile::Item;
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::server::{
+    AllowAnyAnonymousOrAuthenticatedClient, AllowAnyAuthenticatedClient,
+};
+use tokio_rustls::rustls::{Certificate, PrivateKey, RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Certificate as PeerCertificate;
+use tracing::{debug, error, info, warn};
+
+use crate::config::TlsConfig;
+
+/// TLS for the gRPC listener. The certificate, key and client CA are re-read whenever one of the
+/// files changes, and new connections pick up the reloaded config while existing ones keep theirs.
+pub struct Tls {
+    config: TlsConfig,
+    http1: bool,
+    server_config: RwLock<Arc<ServerConfig>>,
+}
+
+fn read_pem(path: &str) -> io::Result<Vec<Item>> {
+    rustls_pemfile::read_all(&mut BufReader::new(File::open(path)?))
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+impl Tls {
+    /// `http1` offers HTTP/1.1 next to HTTP/2 during the handshake, for listeners that take it.
+    pub fn from_config(config: &TlsConfig, http1: bool) -> Option<Arc<Self>> {
+        if !config.enabled {
+            return None;
+        }
+
+        let server_config = Self::load(config, http1).expect("Failed to load TLS certificates.");
+        Some(Arc::new(Self {
+            config: config.clone(),
+            http1,
+            server_config: RwLock::new(Arc::new(server_config)),
+        }))
+    }
+
+    fn load(config: &TlsConfig, http1: bool) -> io::Result<ServerConfig> {
+        let certs: Vec<Certificate> = read_pem(&config.cert_file)?
+            .into_iter()
+            .filter_map(|item| match item {
+                Item::X509Certificate(der) => Some(Certificate(der)),
+                _ => None,
+            })
+            .collect();
+        if certs.is_empty() {
+            return Err(invalid(format!("No certificates in {}", config.cert_file)));
+        }
+
+        let key = read_pem(&config.key_file)?
+            .into_iter()
+            .find_map(|item| match item {
+                Item::RSAKey(der) | Item::PKCS8Key(der) | Item::ECKey(der) => Some(PrivateKey(der)),
+                _ => None,
+            })
+            .ok_or_else(|| invalid(format!("No private key in {}", config.key_file)))?;
+
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = match &config.client_ca_file {
+            Some(path) => {
+                let mut roots = RootCertStore::empty();
+                for item in read_pem(path)? {
+                    if let Item::X509Certificate(der) = item {
+                        roots
+                            .add(&Certificate(der))
+                            .map_err(|e| invalid(format!("Bad client CA in {}: {}", path, e)))?;
+                    }
+                }
+
+                let verifier = if config.require_client_cert {
+                    AllowAnyAuthenticatedClient::new(roots).boxed()
+                } else {
+                    AllowAnyAnonymousOrAuthenticatedClient::new(roots).boxed()
+                };
+                builder.with_client_cert_verifier(verifier)
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let mut server_config = builder
+            .with_single_cert(certs, key)
+            .map_err(|e| invalid(e.to_string()))?;
+        server_config.alpn_protocols = vec![b"h2".to_vec()];
+        if http1 {
+            server_config.alpn_protocols.push(b"http/1.1".to_vec());
+        }
+
+        Ok(server_config)
+    }
+
+    fn files(&self) -> Vec<&str> {
+        let mut files = vec![
+            self.config.cert_file.as_str(),
+            self.config.key_file.as_str(),
+        ];
+        files.extend(self.config.client_ca_file.as_deref());
+        files
+    }
+
+    /// Polls the certificate files and swaps in a new config when any of them changes. A file
+    /// that fails to load is reported and the previous config kept.
+    pub fn spawn_reload(self: Arc<Self>) {
+        let interval = Duration::from_millis(self.config.reload_ms);
+        tokio::spawn(async move {
+            let mut seen: Vec<_> = self.files().into_iter().map(modified).collect();
+            loop {
+                tokio::time::sleep(interval).await;
+
+                let current: Vec<_> = self.files().into_iter().map(modified).collect();
+                if current == seen {
+                    continue;
+                }
+                seen = current;
+
+                match Self::load(&self.config, self.http1) {
+                    Ok(server_config) => {
+                        *self.server_config.write().unwrap() = Arc::new(server_config);
+                        info!("Reloaded TLS certificates");
+                    }
+                    Err(e) => error!(error = %e, "Failed to reload TLS certificates"),
+                }
+            }
+        });
+    }
+
+    /// Accepts connections on `listener` and hands them to tonic once their handshake finishes.
+    /// Handshakes run on their own tasks so a slow client cannot hold up the others.
+    pub fn incoming(
+        self: Arc<Self>,
+        listener: TcpListener,
+    ) -> ReceiverStream<io::Result<TlsStream<TcpStream>>> {
+        let (sender, receiver) = mpsc::channel(128);
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        warn!(error = %e, "Failed to accept connection");
+                        continue;
+                    }
+                };
+
+                let acceptor = TlsAcceptor::from(self.server_config.read().unwrap().clone());
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match acceptor.accept(stream).await {
+                        Ok(stream) => {
+                            let _ = sender.send(Ok(stream)).await;
+                        }
+                        Err(e) => debug!(%peer, error = %e, "TLS handshake failed"),
+                    }
+                });
+            }
+        });
+
+        ReceiverStream::new(receiver)
+    }
+}
+
+/// The client's certificate chain, as DER like tonic hands it to the auth interceptor.
+pub fn peer_certs(stream: &TlsStream<TcpStream>) -> Option<Vec<PeerCertificate>> {
+    let certs = stream.get_ref().1.peer_certificates()?;
+    Some(
+        certs
+            .iter()
+            .map(|cert| PeerCertificate::from_pem(&cert.0))
+            .collect(),
+    )
+}
@@ -0,0 +1,574 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use tracing::{info, warn};
+
+use crate::api::auth::Role;
+use crate::api::tenant::DEFAULT_TENANT;
+
+/// Settings read from the TOML file at `MR_CACHE_CONFIG` (defaults to `mrcache.toml`).
+/// Every section is optional and falls back to its defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub redis: RedisConfig,
+    pub http: HttpConfig,
+    pub metrics: MetricsConfig,
+    pub health: HealthConfig,
+    pub reflection: ReflectionConfig,
+    pub rest: RestConfig,
+    pub grpc_web: GrpcWebConfig,
+    pub resp: RespConfig,
+    pub memcached: MemcachedConfig,
+    pub logging: LoggingConfig,
+    pub tracing: TracingConfig,
+    pub tls: TlsConfig,
+    pub auth: AuthConfig,
+    pub recompute_lock: RecomputeLockConfig,
+    pub loader: LoaderConfig,
+    pub quotas: QuotaConfig,
+    pub json: JsonConfig,
+    pub compression: CompressionConfig,
+    pub encryption: EncryptionConfig,
+    pub l1: L1Config,
+    pub replicas: ReplicasConfig,
+    pub tenants: HashMap<String, TenantConfig>,
+    /// Whether the settings came from a file rather than the defaults.
+    #[serde(skip)]
+    loaded: bool,
+}
+
+/// The Redis server every pool connects to. `tls` switches to `rediss://`, verified against
+/// `ca_file` instead of the system roots when set, and presenting a client certificate when
+/// `client_cert_file` and `client_key_file` are. ACL credentials come from the `*_file` secrets
+/// if set and from the `*_env` variables otherwise, and are re-read every `credentials_reload_ms`.
+/// Listing `cluster_nodes` as `host:port` seeds connects to a Redis Cluster instead of `host`.
+/// Listing `sentinels` instead connects to whichever node they say is the master named
+/// `sentinel_service`, following it through failovers.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RedisConfig {
+    pub host: String,
+    pub port: u16,
+    pub cluster_nodes: Vec<String>,
+    pub sentinels: Vec<String>,
+    pub sentinel_service: String,
+    pub db: i64,
+    pub tls: bool,
+    pub ca_file: Option<String>,
+    pub client_cert_file: Option<String>,
+    pub client_key_file: Option<String>,
+    pub username_env: String,
+    pub username_file: Option<String>,
+    pub password_env: String,
+    pub password_file: Option<String>,
+    pub credentials_reload_ms: u64,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            host: "host.docker.internal".to_string(),
+            port: 6379,
+            cluster_nodes: Vec::new(),
+            sentinels: Vec::new(),
+            sentinel_service: "mymaster".to_string(),
+            db: 0,
+            tls: false,
+            ca_file: None,
+            client_cert_file: None,
+            client_key_file: None,
+            username_env: "REDIS_USERNAME".to_string(),
+            username_file: None,
+            password_env: "REDIS_PASSWORD".to_string(),
+            password_file: None,
+            credentials_reload_ms: 10000,
+        }
+    }
+}
+
+/// Port of the plain HTTP server carrying the `/healthz` and `/readyz` probes and `/metrics`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    pub port: u16,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self { port: 9100 }
+    }
+}
+
+/// Whether `/metrics` serves Prometheus metrics. `port` is the older name of `http.port`, from
+/// when metrics had a server of their own, and still sets it.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub port: Option<u16>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: None,
+        }
+    }
+}
+
+/// How often Redis is pinged to decide whether the server is ready.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    pub check_ms: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self { check_ms: 1000 }
+    }
+}
+
+/// Whether the gRPC server reflection service is offered.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ReflectionConfig {
+    pub enabled: bool,
+}
+
+impl Default for ReflectionConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// The `/v1` REST gateway, served on its own port with TLS whenever the gRPC listener has it.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RestConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for RestConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 8080,
+        }
+    }
+}
+
+/// gRPC-Web on the gRPC port, so browsers can call the RPCs without a proxy. Cross-origin calls
+/// are allowed from `allowed_origins` only, which must be listed to turn it on, and preflight
+/// answers are cached for `max_age_s`.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GrpcWebConfig {
+    pub enabled: bool,
+    pub allowed_origins: Vec<String>,
+    pub max_age_s: u64,
+}
+
+impl Default for GrpcWebConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            allowed_origins: Vec::new(),
+            max_age_s: 86400,
+        }
+    }
+}
+
+/// A listener speaking the Redis protocol, for clients that only have a Redis library. It uses
+/// TLS whenever the gRPC listener does.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RespConfig {
+    pub enabled: bool,
+    pub port: u16,
+}
+
+impl Default for RespConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 6380,
+        }
+    }
+}
+
+/// A listener speaking the memcached text and binary protocols, for clients that only have a
+/// memcached library. Values over `max_value_bytes` are refused like memcached's item size limit.
+/// It uses TLS whenever the gRPC listener does.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct MemcachedConfig {
+    pub enabled: bool,
+    pub port: u16,
+    pub max_value_bytes: usize,
+}
+
+impl Default for MemcachedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 11211,
+            max_value_bytes: 1024 * 1024,
+        }
+    }
+}
+
+/// Log output. `level` takes `RUST_LOG`-style directives, and `RUST_LOG` itself wins when set.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    pub level: String,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Pretty,
+            level: "info".to_string(),
+        }
+    }
+}
+
+/// OpenTelemetry spans, exported over OTLP/gRPC to `otlp_endpoint` (e.g. a local collector on
+/// `http://localhost:4317`) when it is set.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct TracingConfig {
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "mrcache".to_string(),
+        }
+    }
+}
+
+/// Distributed lock taken on a `GET` miss so only one mrCache instance hands the miss back to
+/// its caller for recomputing, while the others wait for the refreshed value.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct RecomputeLockConfig {
+    pub enabled: bool,
+    pub ttl_ms: u64,
+    pub wait_ms: u64,
+    pub poll_ms: u64,
+}
+
+impl Default for RecomputeLockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            ttl_ms: 5000,
+            wait_ms: 1000,
+            poll_ms: 50,
+        }
+    }
+}
+
+/// HTTP origin used to refresh values that are past their soft TTL. Without a `url` stale values
+/// are still flagged but never refreshed.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct LoaderConfig {
+    pub url: Option<String>,
+    pub timeout_ms: u64,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            timeout_ms: 2000,
+        }
+    }
+}
+
+/// How often tenants with quotas have their keys and memory re-counted, correcting the usage
+/// counted on write.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    pub refresh_ms: u64,
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self { refresh_ms: 60000 }
+    }
+}
+
+/// Where the `Json*` RPCs keep documents. `redis_json = true` always uses the RedisJSON module and
+/// `false` always keeps documents as JSON strings edited by mrCache, while leaving it unset uses
+/// the module wherever `MODULE LIST` shows it.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct JsonConfig {
+    pub redis_json: Option<bool>,
+}
+
+/// Compression of values on their way into Redis. Values of at least `min_bytes` are compressed
+/// with the codec of the longest `namespaces` key prefix they match, or `codec` otherwise, and
+/// kept as they are when compressing does not make them smaller. Compressed values are marked
+/// with their codec, so they are read back whatever the settings are by then. Values that would
+/// decompress to more than `max_decompressed_bytes` are returned as stored instead.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub codec: Codec,
+    pub min_bytes: usize,
+    pub zstd_level: i32,
+    pub max_decompressed_bytes: usize,
+    pub namespaces: HashMap<String, Codec>,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Codec {
+    #[default]
+    None,
+    Zstd,
+    Lz4,
+    Snappy,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            codec: Codec::None,
+            min_bytes: 1024,
+            zstd_level: 3,
+            max_decompressed_bytes: 512 * 1024 * 1024,
+            namespaces: HashMap::new(),
+        }
+    }
+}
+
+/// Envelope encryption of values before they reach Redis. `keys` maps key IDs to files holding
+/// 32-byte AES-256 keys, raw or base64, and setting `active_key` turns encryption on: every value
+/// is sealed with its own data key, itself sealed with the active key. Values sealed with another
+/// key are re-sealed in the background, `reencrypt_batch` keys at a time, until none are left.
+/// `key_hash_file` holds a secret to store keys under their HMAC instead of their name.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub keys: HashMap<String, String>,
+    pub active_key: Option<String>,
+    pub key_hash_file: Option<String>,
+    pub reencrypt_interval_ms: u64,
+    pub reencrypt_batch: u64,
+}
+
+impl Default for EncryptionConfig {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::new(),
+            active_key: None,
+            key_hash_file: None,
+            reencrypt_interval_ms: 60000,
+            reencrypt_batch: 100,
+        }
+    }
+}
+
+/// An in-process cache in front of Redis for `Get`. Only keys starting with one of the
+/// `namespaces` prefixes are kept, up to `max_bytes` of keys and values in all and for at most
+/// `ttl_ms` each, and entries are dropped as Redis reports their keys changed through client
+/// tracking, which needs Redis 6 or later. An empty `namespaces` keeps the cache off.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct L1Config {
+    pub namespaces: Vec<String>,
+    pub max_bytes: u64,
+    pub ttl_ms: u64,
+}
+
+impl Default for L1Config {
+    fn default() -> Self {
+        Self {
+            namespaces: Vec::new(),
+            max_bytes: 64 * 1024 * 1024,
+            ttl_ms: 60000,
+        }
+    }
+}
+
+/// Replicas of the Redis server, as `host:port`, that `Get`, `HGet`, `HGetAll`, `HKeys`, `HVals`
+/// and `Scan` are spread over in turn, for the tenants on the shared DB. Every `check_ms` a
+/// heartbeat is written to the primary, and replicas more than `max_lag_ms` behind it are left
+/// out. Calls with `x-mrcache-consistency: strong` metadata always read from the primary.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct ReplicasConfig {
+    pub nodes: Vec<String>,
+    pub max_lag_ms: u64,
+    pub check_ms: u64,
+}
+
+impl Default for ReplicasConfig {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            max_lag_ms: 1000,
+            check_ms: 500,
+        }
+    }
+}
+
+/// A tenant named by the `x-mrcache-tenant` metadata. `url` and `db` move it off the shared Redis
+/// DB, and the quotas refuse its writes once reached.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TenantConfig {
+    pub url: Option<String>,
+    pub db: Option<i64>,
+    pub max_keys: Option<u64>,
+    pub max_memory_bytes: Option<u64>,
+}
+
+/// TLS for the gRPC listener. With `client_ca_file` set, clients may present a certificate signed
+/// by that CA, and must when `require_client_cert` is on. The files are re-read every `reload_ms`
+/// if they changed.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub cert_file: String,
+    pub key_file: String,
+    pub client_ca_file: Option<String>,
+    pub require_client_cert: bool,
+    pub reload_ms: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cert_file: "tls/server.crt".to_string(),
+            key_file: "tls/server.key".to_string(),
+            client_ca_file: None,
+            require_client_cert: false,
+            reload_ms: 10000,
+        }
+    }
+}
+
+/// Who may call the gRPC API. Callers send either an `x-api-key` listed here or an
+/// `authorization: Bearer` JWT signed by a key in `jwks_file`, whose `role_claim` holds the role
+/// and `tenant_claim` the tenants. Over mutual TLS, callers sending neither get the role and
+/// tenants of their certificate's subject.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub enabled: bool,
+    pub api_keys: Vec<ApiKeyConfig>,
+    pub jwks_file: Option<String>,
+    pub issuer: Option<String>,
+    pub audience: Option<String>,
+    pub role_claim: String,
+    pub tenant_claim: String,
+    pub client_certs: Vec<ClientCertConfig>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            api_keys: Vec::new(),
+            jwks_file: None,
+            issuer: None,
+            audience: None,
+            role_claim: "role".to_string(),
+            tenant_claim: "tenants".to_string(),
+            client_certs: Vec::new(),
+        }
+    }
+}
+
+/// An API key, with the tenants it may name in `x-mrcache-tenant`: `default` for the shared
+/// keyspace, which is all it gets when none are listed, or `*` for every tenant.
+#[derive(Debug, Deserialize)]
+pub struct ApiKeyConfig {
+    pub key: String,
+    pub role: Role,
+    #[serde(default = "default_tenants")]
+    pub tenants: Vec<String>,
+}
+
+/// A client certificate subject, either the full distinguished name in certificate order
+/// (`O=Acme, CN=web`) or just its common name (`CN=web`), with its tenants like an API key's.
+#[derive(Debug, Deserialize)]
+pub struct ClientCertConfig {
+    pub subject: String,
+    pub role: Role,
+    #[serde(default = "default_tenants")]
+    pub tenants: Vec<String>,
+}
+
+fn default_tenants() -> Vec<String> {
+    vec![DEFAULT_TENANT.to_string()]
+}
+
+impl Config {
+    fn path() -> String {
+        env::var("MR_CACHE_CONFIG").unwrap_or_else(|_| "mrcache.toml".to_string())
+    }
+
+    pub fn load() -> Self {
+        let mut config = match fs::read_to_string(Self::path()) {
+            Ok(contents) => Self {
+                loaded: true,
+                ..toml::from_str(&contents).expect("Failed to parse config file.")
+            },
+            Err(_) => Self::default(),
+        };
+        if let Some(port) = config.metrics.port {
+            config.http.port = port;
+        }
+
+        let grpc_web = &config.grpc_web;
+        assert!(
+            !grpc_web.enabled || !grpc_web.allowed_origins.is_empty(),
+            "gRPC-Web needs its allowed_origins listed."
+        );
+        assert!(
+            !grpc_web.allowed_origins.iter().any(|origin| origin == "*"),
+            "gRPC-Web cannot allow every origin, as calls carry credentials."
+        );
+        config
+    }
+
+    /// Logs where the settings came from and any deprecated ones they use, once logging has been
+    /// set up from them.
+    pub fn log_source(&self) {
+        let path = Self::path();
+        match self.loaded {
+            true => info!(path, "Loaded config file"),
+            false => warn!(path, "No config file found, using defaults"),
+        }
+        if self.metrics.port.is_some() {
+            warn!("metrics.port is deprecated, set http.port instead");
+        }
+    }
+}
@@ -1,31 +1,175 @@
 #![allow(non_snake_case)]
 
+use crate::api::auth::Auth;
 use crate::api::client::MrCacheService;
+use crate::api::grpc_web;
+use crate::api::health::Health;
+use crate::api::http_server;
+use crate::api::memcached::Memcached;
+use crate::api::metrics::RpcMetricsLayer;
 use crate::api::mr_cache::mr_cache_server::MrCacheServer;
 use crate::api::pool::Pool;
+use crate::api::reflection::Reflection;
+use crate::api::resp::Resp;
+use crate::api::rest;
+use crate::api::telemetry;
+use crate::api::tls::Tls;
+use crate::config::Config;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tonic::service::interceptor::InterceptedService;
 use tonic::transport::Server;
+use tonic_web::GrpcWebLayer;
+use tower::util::option_layer;
+use tracing::info;
 
 mod api {
+    #[allow(clippy::result_large_err)]
+    pub mod auth;
+    #[allow(clippy::result_large_err)]
     pub mod client;
+    pub mod coalesce;
+    pub mod compression;
+    #[allow(clippy::result_large_err)]
+    pub mod documents;
+    #[allow(clippy::result_large_err)]
+    pub mod encoding;
+    #[allow(clippy::result_large_err)]
+    pub mod encryption;
+    pub mod grpc_web;
+    #[allow(clippy::result_large_err)]
+    pub mod health;
+    #[path = "grpc.health.v1.rs"]
+    pub mod health_proto;
+    pub mod http_server;
+    #[allow(clippy::result_large_err)]
+    pub mod items;
+    pub mod l1;
+    #[allow(clippy::result_large_err)]
+    pub mod loader;
+    pub mod lock;
+    #[allow(clippy::result_large_err)]
+    pub mod memcached;
+    #[allow(clippy::result_large_err)]
+    pub mod meta;
+    pub mod metrics;
     pub mod mr_cache;
     pub mod pool;
+    #[allow(clippy::result_large_err)]
+    pub mod reflection;
+    #[allow(clippy::enum_variant_names)]
+    #[path = "grpc.reflection.v1alpha.rs"]
+    pub mod reflection_proto;
+    #[allow(clippy::result_large_err)]
+    pub mod replicas;
+    #[allow(clippy::result_large_err)]
+    pub mod resp;
+    #[allow(clippy::result_large_err)]
+    pub mod rest;
+    pub mod sentinel;
+    pub mod slots;
+    pub mod tags;
+    pub mod telemetry;
+    #[allow(clippy::result_large_err)]
+    pub mod tenant;
+    pub mod tls;
 }
+mod config;
 
 #[tokio::main]
 async fn main() {
     let grpc_port = "50051";
-    let pool = Pool::new();
+    let config = Config::load();
+    telemetry::init(&config.logging, &config.tracing);
+    config.log_source();
+    let pool = Pool::new(&config.redis);
+    pool.spawn_credential_reload(Duration::from_millis(config.redis.credentials_reload_ms));
+    pool.spawn_sentinel_watch();
+    let tls = Tls::from_config(&config.tls, config.grpc_web.enabled);
+    let scheme = if tls.is_some() { "https" } else { "http" };
 
-    println!("Starting server...");
-    println!("gRPC listening on: http://localhost:{}", grpc_port);
+    info!("Starting server...");
+    info!("gRPC listening on: {}://localhost:{}", scheme, grpc_port);
+
+    let service = Arc::new(MrCacheService::new(&pool, &config));
+    let health = Health::spawn(
+        service.pools(),
+        Duration::from_millis(config.health.check_ms),
+    );
+    let auth = Auth::from_config(&config.auth);
+
+    info!("HTTP listening on: http://localhost:{}", config.http.port);
+    tokio::spawn(http_server::serve(
+        http_server::router(health.clone(), config.metrics.enabled),
+        ([0, 0, 0, 0], config.http.port).into(),
+        None,
+    ));
+
+    if config.rest.enabled {
+        let rest_tls = Tls::from_config(&config.tls, true);
+        if let Some(rest_tls) = &rest_tls {
+            rest_tls.clone().spawn_reload();
+        }
+        info!(
+            "REST listening on: {}://localhost:{}",
+            scheme, config.rest.port
+        );
+        tokio::spawn(http_server::serve(
+            rest::router(service.clone(), auth.clone()),
+            ([0, 0, 0, 0], config.rest.port).into(),
+            rest_tls,
+        ));
+    }
+
+    if config.resp.enabled {
+        info!("RESP listening on: localhost:{}", config.resp.port);
+        tokio::spawn(
+            Resp::new(service.clone(), auth.clone())
+                .serve(([0, 0, 0, 0], config.resp.port).into(), tls.clone()),
+        );
+    }
+
+    if config.memcached.enabled {
+        info!(
+            "memcached listening on: localhost:{}",
+            config.memcached.port
+        );
+        tokio::spawn(
+            Memcached::new(service.clone(), auth.clone(), &config.memcached)
+                .serve(([0, 0, 0, 0], config.memcached.port).into(), tls.clone()),
+        );
+    }
 
     let address = ("0.0.0.0:".to_owned() + grpc_port).parse().unwrap();
 
-    Server::builder()
-        .add_service(MrCacheServer::new(MrCacheService {
-            pool: pool.get_pool(),
-        }))
-        .serve(address)
-        .await
-        .expect("Server failed to start!")
+    let grpc_web = config.grpc_web.enabled;
+    let router = Server::builder()
+        .accept_http1(grpc_web)
+        .trace_fn(telemetry::request_span)
+        .layer(option_layer(
+            grpc_web.then(|| grpc_web::cors(&config.grpc_web)),
+        ))
+        .layer(option_layer(grpc_web.then(GrpcWebLayer::new)))
+        .layer(RpcMetricsLayer)
+        .add_service(health.service())
+        .add_optional_service(config.reflection.enabled.then(Reflection::service))
+        .add_service(InterceptedService::new(
+            MrCacheServer::from_arc(service),
+            auth,
+        ));
+
+    let served = match tls {
+        Some(tls) => {
+            let listener = TcpListener::bind(address)
+                .await
+                .expect("Failed to bind gRPC port!");
+            tls.clone().spawn_reload();
+            router.serve_with_incoming(tls.incoming(listener)).await
+        }
+        None => router.serve(address).await,
+    };
+
+    telemetry::shutdown();
+    served.expect("Server failed to start!")
 }
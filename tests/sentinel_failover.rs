@@ -0,0 +1,197 @@
+//! Runs mrCache against a local Redis master, its replica and one sentinel, fails the master over
+//! to the replica and checks that calls resume against the new master.
+//!
+//! Needs `redis-server` on the `PATH` and port 50051 free, so it only runs when asked for:
+//! `cargo test --test sentinel_failover -- --ignored`.
+
+use mrcache_client::{Client, SetOptions};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const SERVICE: &str = "mymaster";
+const GRPC_ENDPOINT: &str = "http://127.0.0.1:50051";
+
+/// Child processes killed when the test ends, however it ends.
+struct Processes(Vec<Child>);
+
+impl Drop for Processes {
+    fn drop(&mut self) {
+        for child in &mut self.0 {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("Failed to find a free port.")
+        .port()
+}
+
+fn redis_server(dir: &Path, args: &[String]) -> Child {
+    Command::new("redis-server")
+        .args(args)
+        .current_dir(dir)
+        .stdout(Stdio::null())
+        .spawn()
+        .expect("Failed to start redis-server.")
+}
+
+fn redis(port: u16) -> redis::Connection {
+    redis::Client::open(format!("redis://127.0.0.1:{port}"))
+        .and_then(|client| client.get_connection())
+        .expect("Failed to connect to Redis.")
+}
+
+/// Polls `check` until it holds, failing the test after `timeout`.
+async fn eventually<F, Fut>(what: &str, timeout: Duration, mut check: F)
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = bool>,
+{
+    let deadline = Instant::now() + timeout;
+    while !check().await {
+        assert!(Instant::now() < deadline, "Timed out waiting for {what}.");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+    }
+}
+
+fn master_port(sentinel: u16) -> Option<u16> {
+    let (_, port): (String, String) = redis::cmd("SENTINEL")
+        .arg("GET-MASTER-ADDR-BY-NAME")
+        .arg(SERVICE)
+        .query(&mut redis(sentinel))
+        .ok()?;
+    port.parse().ok()
+}
+
+#[tokio::test]
+#[ignore]
+async fn calls_resume_on_the_new_master_after_a_failover() {
+    let dir: PathBuf =
+        std::env::temp_dir().join(format!("mrcache-sentinel-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("Failed to create the test directory.");
+    let (master, replica, sentinel, http) = (free_port(), free_port(), free_port(), free_port());
+    let mut processes = Processes(Vec::new());
+
+    let persistence = ["--save", "", "--appendonly", "no"].map(String::from);
+    let mut master_args = vec!["--port".to_string(), master.to_string()];
+    master_args.extend(persistence.clone());
+    processes.0.push(redis_server(&dir, &master_args));
+    let mut replica_args = vec!["--port".to_string(), replica.to_string()];
+    replica_args.extend(persistence);
+    replica_args.extend([
+        "--replicaof".to_string(),
+        "127.0.0.1".to_string(),
+        master.to_string(),
+    ]);
+    processes.0.push(redis_server(&dir, &replica_args));
+
+    let sentinel_conf = dir.join("sentinel.conf");
+    std::fs::write(
+        &sentinel_conf,
+        format!(
+            "port {sentinel}\n\
+             sentinel monitor {SERVICE} 127.0.0.1 {master} 1\n\
+             sentinel down-after-milliseconds {SERVICE} 1000\n\
+             sentinel failover-timeout {SERVICE} 5000\n"
+        ),
+    )
+    .expect("Failed to write the sentinel config.");
+    processes.0.push(redis_server(
+        &dir,
+        &[
+            sentinel_conf.display().to_string(),
+            "--sentinel".to_string(),
+        ],
+    ));
+
+    eventually("the replica to sync", Duration::from_secs(10), || async {
+        redis::cmd("INFO")
+            .arg("replication")
+            .query::<String>(&mut redis(replica))
+            .is_ok_and(|info| info.contains("master_link_status:up"))
+    })
+    .await;
+    eventually(
+        "the sentinel to see the master",
+        Duration::from_secs(10),
+        || async { master_port(sentinel) == Some(master) },
+    )
+    .await;
+
+    let config = dir.join("mrcache.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "[redis]\n\
+             sentinels = [\"127.0.0.1:{sentinel}\"]\n\
+             sentinel_service = \"{SERVICE}\"\n\
+             [http]\n\
+             port = {http}\n\
+             [rest]\n\
+             enabled = false\n"
+        ),
+    )
+    .expect("Failed to write the mrCache config.");
+    processes.0.push(
+        Command::new(env!("CARGO_BIN_EXE_mrCache"))
+            .env("MR_CACHE_CONFIG", &config)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start mrCache."),
+    );
+
+    let client = Client::builder()
+        .endpoint(GRPC_ENDPOINT)
+        .deadline(Duration::from_secs(2))
+        .build()
+        .expect("Failed to build the client.");
+    let options = SetOptions::default();
+    eventually(
+        "mrCache to serve calls",
+        Duration::from_secs(10),
+        || async { client.set("before", "failover", &options).await.is_ok() },
+    )
+    .await;
+
+    redis::cmd("SENTINEL")
+        .arg("FAILOVER")
+        .arg(SERVICE)
+        .query::<()>(&mut redis(sentinel))
+        .expect("Failed to start the failover.");
+    eventually(
+        "the replica to be promoted",
+        Duration::from_secs(20),
+        || async { master_port(sentinel) == Some(replica) },
+    )
+    .await;
+
+    eventually("calls to resume", Duration::from_secs(20), || async {
+        client.set("after", "failover", &options).await.is_ok()
+    })
+    .await;
+    let role: Vec<redis::Value> = redis::cmd("ROLE")
+        .query(&mut redis(replica))
+        .expect("Failed to read the new master's role.");
+    assert_eq!(role.first(), Some(&redis::Value::Data(b"master".to_vec())));
+    let written: Option<String> = redis::cmd("GET")
+        .arg("after")
+        .query(&mut redis(replica))
+        .expect("Failed to read from the new master.");
+    assert_eq!(written.as_deref(), Some("\"failover\""));
+    assert_eq!(
+        client
+            .get::<String>("before")
+            .await
+            .expect("Failed to read after the failover."),
+        Some("failover".to_string())
+    );
+
+    drop(processes);
+    let _ = std::fs::remove_dir_all(&dir);
+}